@@ -1,9 +1,13 @@
-
-
 use log::{error, info, Level};
+use std::cell::RefCell;
 use std::panic;
 use wasm_sockets::{self, WebSocketError};
 
+thread_local! {
+    // The app's state, which keeps the connection open for the life of the page
+    static CLIENT: RefCell<Option<wasm_sockets::EventClient>> = const { RefCell::new(None) };
+}
+
 fn main() -> Result<(), WebSocketError> {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
     // console_log and log macros are used instead of println!
@@ -31,5 +35,7 @@ fn main() -> Result<(), WebSocketError> {
     )));
 
     info!("Connection successfully created");
+    // Dropping the client closes the connection, so keep it in the app's state
+    CLIENT.with(|cell| *cell.borrow_mut() = Some(client));
     Ok(())
 }
@@ -1,9 +1,7 @@
-
 use log::{info, Level};
 use std::cell::RefCell;
 use std::panic;
 use std::rc::Rc;
-#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use wasm_sockets::{self, ConnectionStatus, WebSocketError};
 
@@ -0,0 +1,104 @@
+// The miniquad plugin used by wasm_sockets::miniquad::PollingClient.
+// Load it after gl.js and sapp_jsutils.js.
+"use strict";
+
+var wasm_sockets = {};
+var wasm_sockets_next_id = 0;
+
+function register_plugin(importObject) {
+    importObject.env.wasm_sockets_connect = wasm_sockets_connect;
+    importObject.env.wasm_sockets_status = wasm_sockets_status;
+    importObject.env.wasm_sockets_close_info = wasm_sockets_close_info;
+    importObject.env.wasm_sockets_send = wasm_sockets_send;
+    importObject.env.wasm_sockets_try_recv = wasm_sockets_try_recv;
+    importObject.env.wasm_sockets_close = wasm_sockets_close;
+    importObject.env.wasm_sockets_free = wasm_sockets_free;
+}
+
+miniquad_add_plugin({ register_plugin, version: 2, name: "wasm_sockets" });
+
+// Statuses match ConnectionStatus: 0 connecting, 1 connected, 2 error, 3 disconnected
+function wasm_sockets_connect(url) {
+    var socket;
+    try {
+        socket = new WebSocket(consume_js_object(url));
+    } catch (e) {
+        console.error(e);
+        return -1;
+    }
+    socket.binaryType = "arraybuffer";
+    var state = { socket: socket, status: 0, received: [], close: null };
+    socket.onopen = function () {
+        state.status = 1;
+    };
+    socket.onerror = function () {
+        state.status = 2;
+    };
+    socket.onclose = function (e) {
+        state.status = e.wasClean ? 3 : 2;
+        state.close = { code: e.code, reason: e.reason, was_clean: e.wasClean ? 1 : 0 };
+    };
+    socket.onmessage = function (e) {
+        if (typeof e.data === "string") {
+            state.received.push({ text: 1, data: e.data });
+        } else {
+            state.received.push({ text: 0, data: new Uint8Array(e.data) });
+        }
+    };
+    var id = wasm_sockets_next_id;
+    wasm_sockets_next_id += 1;
+    wasm_sockets[id] = state;
+    return id;
+}
+
+function wasm_sockets_status(id) {
+    return wasm_sockets[id].status;
+}
+
+function wasm_sockets_close_info(id) {
+    var close = wasm_sockets[id].close;
+    if (close === null) {
+        return -1;
+    }
+    return js_object(close);
+}
+
+function wasm_sockets_send(id, data) {
+    var state = wasm_sockets[id];
+    data = consume_js_object(data);
+    if (state.socket.readyState !== WebSocket.OPEN) {
+        return 1;
+    }
+    state.socket.send(data);
+    return 0;
+}
+
+function wasm_sockets_try_recv(id) {
+    var state = wasm_sockets[id];
+    if (state.received.length === 0) {
+        return -1;
+    }
+    return js_object(state.received.shift());
+}
+
+function wasm_sockets_close(id, code, reason) {
+    var socket = wasm_sockets[id].socket;
+    reason = consume_js_object(reason);
+    try {
+        if (code < 0) {
+            socket.close();
+        } else {
+            socket.close(code, reason);
+        }
+    } catch (e) {
+        return 1;
+    }
+    return 0;
+}
+
+function wasm_sockets_free(id) {
+    var socket = wasm_sockets[id].socket;
+    socket.onopen = socket.onerror = socket.onclose = socket.onmessage = null;
+    socket.close();
+    delete wasm_sockets[id];
+}
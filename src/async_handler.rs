@@ -0,0 +1,104 @@
+//! Message handlers that return a future, for work like decrypting with SubtleCrypto or writing to IndexedDB.
+//!
+//! The futures are spawned on the local executor, wasm-bindgen-futures in the browser and tokio's
+//! `LocalSet` with the `native` feature. They run one at a time, in the order the messages arrived,
+//! so a handler never sees a message before the one it's still working on.
+//! ```
+//! client.set_on_message_async(Some(Box::new(|client, message| {
+//!     Box::pin(async move {
+//!         let plain = decrypt(&key, message).await;
+//!         db.store(&plain).await;
+//!     })
+//! })));
+//! ```
+use crate::{EventClient, Message};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// A handler bound to the on_message event that returns a future, see [`EventClient::set_on_message_async`]
+pub type AsyncMessageHandler =
+    Box<dyn Fn(EventClient, Message) -> Pin<Box<dyn Future<Output = ()>>>>;
+
+/// The async handler and the messages waiting for it
+#[derive(Default)]
+pub(crate) struct AsyncMessages {
+    handler: Option<Rc<AsyncMessageHandler>>,
+    waiting: VecDeque<Message>,
+    /// Whether a task is working through the waiting messages
+    running: bool,
+}
+
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+fn spawn_local(future: impl Future<Output = ()> + 'static) {
+    wasm_bindgen_futures::spawn_local(future);
+}
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+fn spawn_local(future: impl Future<Output = ()> + 'static) {
+    tokio::task::spawn_local(future);
+}
+
+impl EventClient {
+    /// Set an on_message event handler that returns a future, overwriting the previous one.
+    ///
+    /// It runs after the other on_message handlers. Each future is awaited before the handler is
+    /// called with the next message, so messages are handled in order. Messages already waiting
+    /// when the handler is changed go to the new handler, and are dropped if it's set to None.
+    /// ```
+    /// client.set_on_message_async(Some(Box::new(|client, message| {
+    ///     Box::pin(async move {
+    ///         let reply = fetch_reply(message).await;
+    ///         client.send_string(&reply).ok();
+    ///     })
+    /// })));
+    /// ```
+    pub fn set_on_message_async(&mut self, f: Option<AsyncMessageHandler>) {
+        let mut listeners = self.listeners.borrow_mut();
+        if f.is_none() {
+            listeners.async_messages.waiting.clear();
+        }
+        listeners.async_messages.handler = f.map(Rc::new);
+        drop(listeners);
+        self.replay_early();
+    }
+    /// Whether an async message handler is set
+    pub(crate) fn has_async_handler(&self) -> bool {
+        self.listeners.borrow().async_messages.handler.is_some()
+    }
+    /// Queue a message for the async handler, starting a task to handle it if there isn't one
+    pub(crate) fn dispatch_async(&self, message: Message) {
+        {
+            let mut listeners = self.listeners.borrow_mut();
+            let messages = &mut listeners.async_messages;
+            if messages.handler.is_none() {
+                return;
+            }
+            messages.waiting.push_back(message);
+            if messages.running {
+                return;
+            }
+            messages.running = true;
+        }
+        let client = self.clone();
+        spawn_local(async move {
+            loop {
+                let next = {
+                    let mut listeners = client.listeners.borrow_mut();
+                    let messages = &mut listeners.async_messages;
+                    match (messages.handler.clone(), messages.waiting.pop_front()) {
+                        (Some(handler), Some(message)) => Some((handler, message)),
+                        _ => {
+                            messages.running = false;
+                            None
+                        }
+                    }
+                };
+                match next {
+                    Some((handler, message)) => handler.as_ref()(client.clone(), message).await,
+                    None => return,
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,179 @@
+//! Noticing when a [`PollingClient`]'s messages arrive faster than they're received.
+//!
+//! Messages wait in the client until [`PollingClient::receive`] takes them, so an app that falls
+//! behind builds up a backlog. With limits set, the on_backpressure handler is called once the
+//! backlog gets too deep or its oldest message too old, so the app can shed work or ask the
+//! server to send less.
+//! ```
+//! client.set_backpressure_limits(Some(BackpressureLimits {
+//!     max_depth: Some(500),
+//!     max_age_ms: Some(250.0),
+//! }));
+//! client.set_on_backpressure(Some(Box::new(|report| {
+//!     warn!("{} messages waiting, the oldest for {}ms", report.depth, report.oldest_age_ms);
+//! })));
+//! ```
+use crate::timer::{monotonic_ms, set_timeout};
+use crate::{EventClient, Message, PollingClient};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// When a [`PollingClient`]'s backlog is too big, see [`PollingClient::set_backpressure_limits`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BackpressureLimits {
+    /// The most messages that can be waiting, or None for no limit
+    pub max_depth: Option<usize>,
+    /// How long the oldest message can wait, in milliseconds, or None for no limit
+    pub max_age_ms: Option<f64>,
+}
+
+/// The backlog of a [`PollingClient`], see [`PollingClient::buffer_report`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferReport {
+    /// The number of messages waiting to be received
+    pub depth: usize,
+    /// How long the oldest waiting message has waited, in milliseconds
+    pub oldest_age_ms: f64,
+}
+
+/// A handler called when a [`PollingClient`]'s backlog goes over its limits
+pub type BackpressureHandler = Box<dyn Fn(BufferReport)>;
+
+/// The backpressure settings and state shared with the client's message handler
+#[derive(Default)]
+pub(crate) struct Backpressure {
+    limits: Option<BackpressureLimits>,
+    on_backpressure: Option<Rc<BackpressureHandler>>,
+    /// When the oldest waiting message arrived, from [`monotonic_ms`]
+    oldest_at: Option<f64>,
+    /// Whether the handler has been called since messages were last received
+    reported: bool,
+    /// Whether a check is scheduled for when the oldest message gets too old
+    age_check: bool,
+}
+
+/// Note that a message was queued, and check the limits
+pub(crate) fn queued(
+    client: &EventClient,
+    backpressure: &Rc<RefCell<Backpressure>>,
+    data: &Rc<RefCell<VecDeque<Message>>>,
+) {
+    let max_age_ms = {
+        let mut backpressure = backpressure.borrow_mut();
+        if backpressure.oldest_at.is_none() {
+            backpressure.oldest_at = Some(monotonic_ms());
+        }
+        match backpressure.limits.and_then(|limits| limits.max_age_ms) {
+            Some(max_age_ms) if !backpressure.age_check => {
+                backpressure.age_check = true;
+                Some(max_age_ms)
+            }
+            _ => None,
+        }
+    };
+    // Check again once the message is too old, in case no others arrive before then
+    if let Some(max_age_ms) = max_age_ms {
+        schedule_age_check(client, backpressure, data, max_age_ms);
+    }
+    check(client, backpressure, data);
+}
+
+/// Check the limits after `delay_ms`, and again until the oldest message is too old or received
+fn schedule_age_check(
+    client: &EventClient,
+    backpressure: &Rc<RefCell<Backpressure>>,
+    data: &Rc<RefCell<VecDeque<Message>>>,
+    delay_ms: f64,
+) {
+    let (client, backpressure, data) = (client.clone(), backpressure.clone(), data.clone());
+    set_timeout(delay_ms, move || {
+        match check(&client, &backpressure, &data) {
+            Some(remaining_ms) => schedule_age_check(&client, &backpressure, &data, remaining_ms),
+            None => backpressure.borrow_mut().age_check = false,
+        }
+    });
+}
+
+/// Call the handler if the backlog is over its limits, returning how long until the oldest
+/// message is too old if it's still under them
+fn check(
+    client: &EventClient,
+    backpressure: &Rc<RefCell<Backpressure>>,
+    data: &Rc<RefCell<VecDeque<Message>>>,
+) -> Option<f64> {
+    let (f, report) = {
+        let mut backpressure = backpressure.borrow_mut();
+        let (limits, oldest_at) = match (backpressure.limits, backpressure.oldest_at) {
+            (Some(limits), Some(oldest_at)) if !backpressure.reported => (limits, oldest_at),
+            _ => return None,
+        };
+        let report = BufferReport {
+            depth: data.borrow().len(),
+            oldest_age_ms: monotonic_ms() - oldest_at,
+        };
+        let too_deep = matches!(limits.max_depth, Some(max) if report.depth > max);
+        let too_old = matches!(limits.max_age_ms, Some(max) if report.oldest_age_ms >= max);
+        if !(too_deep || too_old) {
+            return limits.max_age_ms.map(|max| max - report.oldest_age_ms);
+        }
+        backpressure.reported = true;
+        (backpressure.on_backpressure.clone(), report)
+    };
+    trace!(
+        client: client,
+        "Backlog of {} messages, the oldest {}ms old",
+        report.depth,
+        report.oldest_age_ms
+    );
+    if let Some(f) = f {
+        f.as_ref()(report);
+    }
+    None
+}
+
+impl PollingClient {
+    /// Call the on_backpressure handler when the messages waiting to be received go over `limits`,
+    /// or set None to stop checking.
+    ///
+    /// The handler is called once, and again after messages have been received if the backlog
+    /// is still over the limits when the next message arrives.
+    /// ```
+    /// client.set_backpressure_limits(Some(BackpressureLimits {
+    ///     max_depth: Some(1000),
+    ///     ..BackpressureLimits::default()
+    /// }));
+    /// ```
+    pub fn set_backpressure_limits(&self, limits: Option<BackpressureLimits>) {
+        self.backpressure.borrow_mut().limits = limits;
+    }
+    /// Set a handler for the backlog going over its limits, overwriting the previous one.
+    /// You can set None to disable it.
+    /// ```
+    /// client.set_on_backpressure(Some(Box::new(|_report| {
+    ///     client_ref.send_string(r#"{"op": "slow_down"}"#).ok();
+    /// })));
+    /// ```
+    pub fn set_on_backpressure(&mut self, f: Option<BackpressureHandler>) {
+        self.backpressure.borrow_mut().on_backpressure = f.map(Rc::new);
+    }
+    /// The current backlog of messages waiting to be received
+    /// ```
+    /// hud.set_text(&format!("{} messages behind", client.buffer_report().depth));
+    /// ```
+    pub fn buffer_report(&self) -> BufferReport {
+        let oldest_at = self.backpressure.borrow().oldest_at;
+        BufferReport {
+            depth: self.data.borrow().len(),
+            oldest_age_ms: oldest_at.map_or(0.0, |oldest_at| monotonic_ms() - oldest_at),
+        }
+    }
+    /// Note that messages were received, so the handler can be called again
+    pub(crate) fn consumed(&self) {
+        let mut backpressure = self.backpressure.borrow_mut();
+        backpressure.reported = false;
+        if self.data.borrow().is_empty() {
+            backpressure.oldest_at = None;
+        }
+    }
+}
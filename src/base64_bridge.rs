@@ -0,0 +1,55 @@
+//! Carrying binary messages as base64 text, for gateways that only pass text frames, enabled with
+//! the `base64` feature.
+//!
+//! With [`EventClient::set_base64_bridge`] on, binary messages are sent as standard, padded base64
+//! text, and every received text message is decoded from base64 into a binary message. The
+//! application keeps sending and receiving [`Message::Binary`], as if the gateway weren't there.
+//! ```
+//! client.set_base64_bridge(true);
+//! client.send_binary(state.encode())?;
+//! ```
+use crate::{EventClient, Message, ProtocolError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+impl EventClient {
+    /// Send binary messages as base64 text and decode received text messages from base64, or set
+    /// false to send and receive them as they are.
+    ///
+    /// While this is on, text messages are still sent as text, but every text message received is
+    /// expected to be base64. Ones that aren't are skipped and passed to the on_protocol_error
+    /// handler. Blobs and Files can't be sent.
+    /// ```
+    /// client.set_base64_bridge(true);
+    /// ```
+    pub fn set_base64_bridge(&self, enabled: bool) {
+        self.listeners.borrow_mut().base64_bridge = enabled;
+    }
+    /// Whether binary messages are carried as base64 text, see [`set_base64_bridge`](Self::set_base64_bridge)
+    pub(crate) fn base64_bridged(&self) -> bool {
+        self.listeners.borrow().base64_bridge
+    }
+    /// Encode a binary message as base64 text if the bridge is on
+    pub(crate) fn encode_base64(&self, message: &Message) -> Option<Message> {
+        match message {
+            Message::Binary(data) if self.base64_bridged() => {
+                Some(Message::Text(STANDARD.encode(data).into()))
+            }
+            _ => None,
+        }
+    }
+    /// Decode a received text message from base64 if the bridge is on, returning None if it couldn't be
+    pub(crate) fn decode_base64(&self, message: Message) -> Option<Message> {
+        let text = match &message {
+            Message::Text(text) if self.base64_bridged() => text,
+            _ => return Some(message),
+        };
+        match STANDARD.decode(text.as_bytes()) {
+            Ok(data) => Some(Message::Binary(data.into())),
+            Err(e) => {
+                self.dispatch_protocol_error(ProtocolError::Base64(e.to_string()));
+                None
+            }
+        }
+    }
+}
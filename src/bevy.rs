@@ -0,0 +1,127 @@
+//! Bevy integration, enabled with the `bevy` feature.
+//!
+//! [`WasmSocketsPlugin`] connects a [`PollingClient`] when the app is built and stores it as the
+//! non-send [`SocketConnection`] resource. Every frame, received messages are written as
+//! [`SocketMessage`] events and status changes as [`SocketStatusChanged`] events, and
+//! [`SendSocketMessage`] events are sent to the server.
+//! ```
+//! App::new()
+//!     .add_plugins(WasmSocketsPlugin::new("wss://ws.ifelse.io"))
+//!     .add_systems(Update, (send_hello, log_messages));
+//!
+//! fn send_hello(mut statuses: EventReader<SocketStatusChanged>, mut outgoing: EventWriter<SendSocketMessage>) {
+//!     for SocketStatusChanged(status) in statuses.read() {
+//!         if status.is_connected() {
+//!             outgoing.write(SendSocketMessage(Message::Text("Hello, World!".into())));
+//!         }
+//!     }
+//! }
+//!
+//! fn log_messages(mut messages: EventReader<SocketMessage>) {
+//!     for SocketMessage(message) in messages.read() {
+//!         info!("New Message: {:#?}", message);
+//!     }
+//! }
+//! ```
+use crate::{ConnectionStatus, Message, PollingClient, WebSocketError};
+use bevy_app::{App, Plugin, PostUpdate, PreUpdate};
+use bevy_ecs::prelude::*;
+
+/// Connects to a websocket server and exposes the connection through events
+pub struct WasmSocketsPlugin {
+    /// The URL to connect to
+    pub url: String,
+}
+
+impl WasmSocketsPlugin {
+    /// Create a plugin that connects to a WebSocket URL
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+impl Plugin for WasmSocketsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SocketMessage>()
+            .add_event::<SocketStatusChanged>()
+            .add_event::<SendSocketMessage>()
+            .add_systems(PreUpdate, receive_messages)
+            .add_systems(PostUpdate, send_messages);
+        match SocketConnection::connect(&self.url) {
+            Ok(connection) => {
+                app.insert_non_send_resource(connection);
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// The app's websocket connection.
+///
+/// This is a non-send resource, so access it with `NonSend<SocketConnection>`.
+/// Insert a new one to reconnect.
+pub struct SocketConnection {
+    /// The client used for this connection
+    pub client: PollingClient,
+    last_status: ConnectionStatus,
+}
+
+impl SocketConnection {
+    /// Connect to a WebSocket URL
+    pub fn connect(url: &str) -> Result<Self, WebSocketError> {
+        let client = PollingClient::new(url)?;
+        Ok(Self {
+            last_status: client.status(),
+            client,
+        })
+    }
+}
+
+/// Written for every message received from the server
+#[derive(Event, Debug, Clone)]
+pub struct SocketMessage(pub Message);
+
+/// Written whenever the connection status changes
+#[derive(Event, Debug, Clone)]
+pub struct SocketStatusChanged(pub ConnectionStatus);
+
+/// Write this event to send a message to the server
+#[derive(Event, Debug, Clone)]
+pub struct SendSocketMessage(pub Message);
+
+fn receive_messages(
+    connection: Option<NonSendMut<SocketConnection>>,
+    mut messages: EventWriter<SocketMessage>,
+    mut statuses: EventWriter<SocketStatusChanged>,
+) {
+    let mut connection = match connection {
+        Some(connection) => connection,
+        None => return,
+    };
+    // Report the status before the messages, so a Connected event comes before the first message
+    let status = connection.client.status();
+    if status != connection.last_status {
+        connection.last_status = status.clone();
+        statuses.write(SocketStatusChanged(status));
+    }
+    messages.write_batch(connection.client.receive().into_iter().map(SocketMessage));
+}
+
+fn send_messages(
+    connection: Option<NonSend<SocketConnection>>,
+    mut outgoing: EventReader<SendSocketMessage>,
+) {
+    let connection = match connection {
+        Some(connection) => connection,
+        None => return outgoing.clear(),
+    };
+    for SendSocketMessage(message) in outgoing.read() {
+        let result = match message {
+            Message::Text(text) => connection.client.send_string(text),
+            Message::Binary(data) => connection.client.send_binary(data.to_vec()),
+        };
+        if let Err(e) = result {
+            error!("{}", e);
+        }
+    }
+}
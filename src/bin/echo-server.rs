@@ -0,0 +1,88 @@
+//! A WebSocket server that sends every text and binary message back, for running tests without
+//! depending on a public echo server. Clients offering subprotocols get the first one selected.
+//!
+//! Build it with the `echo-server` feature, and pass the address to listen on, which defaults to
+//! 127.0.0.1:9001:
+//! ```text
+//! cargo run --target x86_64-unknown-linux-gnu --features echo-server --bin echo-server -- 127.0.0.1:9001
+//! WASM_SOCKETS_ECHO_URL=ws://127.0.0.1:9001 wasm-pack test --headless --firefox
+//! ```
+#[cfg(not(target_arch = "wasm32"))]
+mod server {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+    const DEFAULT_ADDRESS: &str = "127.0.0.1:9001";
+
+    #[tokio::main(flavor = "current_thread")]
+    pub async fn main() {
+        let address = std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+        let listener = match TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Couldn't listen on {}: {}", address, e);
+                std::process::exit(1);
+            }
+        };
+        println!("Echoing on ws://{}", address);
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(echo(stream));
+                }
+                Err(e) => eprintln!("Couldn't accept a connection: {}", e),
+            }
+        }
+    }
+
+    async fn echo(stream: TcpStream) {
+        let mut socket = match tokio_tungstenite::accept_hdr_async(stream, select_protocol).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                eprintln!("Handshake failed: {}", e);
+                return;
+            }
+        };
+        // Pings and closes are answered by tungstenite itself
+        while let Some(Ok(message)) = socket.next().await {
+            if (message.is_text() || message.is_binary()) && socket.send(message).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Select the first subprotocol the client offered, if any
+    // The error type is fixed by tungstenite's callback
+    #[allow(clippy::result_large_err)]
+    fn select_protocol(
+        request: &Request,
+        mut response: Response,
+    ) -> Result<Response, ErrorResponse> {
+        let offered = request
+            .headers()
+            .get("Sec-WebSocket-Protocol")
+            .and_then(|protocols| protocols.to_str().ok())
+            .and_then(|protocols| protocols.split(',').next())
+            .and_then(|protocol| protocol.trim().parse().ok());
+        if let Some(protocol) = offered {
+            response
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", protocol);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    server::main();
+}
+
+/// tokio can't listen for connections on wasm32, so this is only there for `--all-features` builds
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    panic!("The echo server has to be built for a native target, like x86_64-unknown-linux-gnu");
+}
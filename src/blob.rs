@@ -0,0 +1,122 @@
+//! Reading Blob messages, enabled with the `blob` feature.
+//!
+//! With [`BinaryType::Blob`](crate::BinaryType::Blob), a binary message can be read in chunks as
+//! it's needed instead of all at once, which allows progress bars for large downloads.
+//!
+//! A Blob that can't be read is skipped, and passed to the on_protocol_error handler as
+//! [`ProtocolError::BlobRead`].
+use crate::{Bytes, Describe, EventClient, ProtocolError};
+use js_sys::{ArrayBuffer, Uint8Array};
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{Blob, FileReader, ProgressEvent};
+
+/// The size of the chunks Blob messages are read in, when a blob chunk handler is set
+pub const BLOB_CHUNK_SIZE: u64 = 1 << 20;
+
+/// A piece of a Blob message, passed to the handler set with [`EventClient::set_on_blob_chunk`]
+#[derive(Debug, Clone)]
+pub struct BlobChunk {
+    /// The contents of this chunk
+    pub data: Bytes,
+    /// Where this chunk starts in the message
+    pub offset: u64,
+    /// The size of the whole message
+    pub total: u64,
+}
+
+impl BlobChunk {
+    /// Whether this is the last chunk of the message
+    pub fn is_last(&self) -> bool {
+        self.offset + self.data.len() as u64 >= self.total
+    }
+}
+
+/// A handler bound to the chunks of Blob messages
+pub type BlobChunkHandler = Box<dyn Fn(&EventClient, BlobChunk)>;
+
+impl EventClient {
+    /// Set a handler that receives Blob messages in chunks of [`BLOB_CHUNK_SIZE`] bytes, overwriting the previous handler.
+    ///
+    /// While this is set, Blob messages are passed to it instead of the on_message handlers, one chunk
+    /// at a time, so the whole message never has to be in wasm memory at once. This only applies
+    /// after switching to [`BinaryType::Blob`](crate::BinaryType::Blob), since ArrayBuffer messages
+    /// have already been received in full.
+    /// You can set [None](std::option) to go back to receiving [`Message::Binary`](crate::Message::Binary).
+    /// ```
+    /// client.set_binary_type(BinaryType::Blob);
+    /// client.set_on_blob_chunk(Some(Box::new(|_client, chunk| {
+    ///     file.write(&chunk.data);
+    ///     progress.set_value((chunk.offset + chunk.data.len() as u64) as f64 / chunk.total as f64);
+    /// })));
+    /// ```
+    pub fn set_on_blob_chunk(&mut self, f: Option<BlobChunkHandler>) {
+        self.listeners.borrow_mut().on_blob_chunk = f.map(Rc::new);
+    }
+    /// Read a received Blob message, either in chunks or all at once
+    pub(crate) fn receive_blob(&self, blob: Blob) {
+        let on_blob_chunk = self.listeners.borrow().on_blob_chunk.clone();
+        match on_blob_chunk {
+            Some(f) => read_chunk(self.clone(), blob, f, 0),
+            None => {
+                let client = self.clone();
+                read(self, &blob, move |buffer| client.dispatch_binary(buffer));
+            }
+        }
+    }
+}
+
+/// Read the chunk of `blob` starting at `offset`, then the chunks after it
+fn read_chunk(client: EventClient, blob: Blob, f: Rc<BlobChunkHandler>, offset: u64) {
+    let total = blob.size() as u64;
+    let end = (offset + BLOB_CHUNK_SIZE).min(total);
+    let slice = match blob.slice_with_f64_and_f64(offset as f64, end as f64) {
+        Ok(slice) => slice,
+        Err(e) => return client.dispatch_protocol_error(ProtocolError::BlobRead(e.describe())),
+    };
+    read(&client.clone(), &slice, move |buffer| {
+        let data = Uint8Array::new(&buffer).to_vec();
+        f.as_ref()(
+            &client,
+            BlobChunk {
+                data: data.into(),
+                offset,
+                total,
+            },
+        );
+        if end < total {
+            read_chunk(client, blob, f, end);
+        }
+    });
+}
+
+/// Read a Blob into an ArrayBuffer, and pass it to `f` once it's loaded.
+/// If it can't be read, `client`'s on_protocol_error handler gets the reason instead.
+fn read(client: &EventClient, blob: &Blob, f: impl FnOnce(ArrayBuffer) + 'static) {
+    let failed = |client: &EventClient, reason: String| {
+        client.dispatch_protocol_error(ProtocolError::BlobRead(reason))
+    };
+    let reader = match FileReader::new() {
+        Ok(reader) => reader,
+        Err(e) => return failed(client, e.describe()),
+    };
+    let (reader_ref, client_ref) = (reader.clone(), client.clone());
+    // once_into_js frees the closure after it has been called
+    let onloadend = Closure::once_into_js(move |_: ProgressEvent| {
+        match reader_ref.result().map(JsCast::dyn_into::<ArrayBuffer>) {
+            Ok(Ok(buffer)) => f(buffer),
+            _ => {
+                let reason = match reader_ref.error() {
+                    Some(e) => format!("{}: {}", e.name(), e.message()),
+                    None => "The reader didn't produce an ArrayBuffer".into(),
+                };
+                failed(&client_ref, reason)
+            }
+        }
+    });
+    reader.set_onloadend(Some(onloadend.unchecked_ref()));
+    if let Err(e) = reader.read_as_array_buffer(blob) {
+        failed(client, e.describe());
+    }
+}
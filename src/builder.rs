@@ -0,0 +1,100 @@
+//! Options for creating an [`EventClient`], for when [`EventClient::new`]'s defaults don't fit.
+use crate::{EventClient, TextPolicy, WebSocketError};
+
+/// How binary messages are delivered by the browser before they're turned into [`Message::Binary`](crate::Message::Binary)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryType {
+    /// Receive binary messages as ArrayBuffers, which are decoded right away.
+    /// This is the fastest option for small messages, like game state or CBOR.
+    #[default]
+    ArrayBuffer,
+    /// Receive binary messages as Blobs, which are read asynchronously.
+    /// Browsers can keep large Blobs out of memory until they're read, which lowers peak memory for large files.
+    /// This needs the `blob` feature, which is enabled by default.
+    #[cfg(feature = "blob")]
+    Blob,
+}
+
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+impl From<BinaryType> for web_sys::BinaryType {
+    fn from(binary_type: BinaryType) -> Self {
+        match binary_type {
+            BinaryType::ArrayBuffer => web_sys::BinaryType::Arraybuffer,
+            #[cfg(feature = "blob")]
+            BinaryType::Blob => web_sys::BinaryType::Blob,
+        }
+    }
+}
+
+/// A builder for an [`EventClient`], created with [`EventClient::builder`]
+/// ```
+/// let client = EventClient::builder("wss://ws.ifelse.io")
+///     .binary_type(BinaryType::Blob)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventClientBuilder {
+    url: String,
+    protocols: Vec<String>,
+    binary_type: BinaryType,
+    text_policy: TextPolicy,
+    deferred_dispatch: bool,
+    buffer_early_messages: bool,
+}
+
+impl EventClientBuilder {
+    /// Offer the server subprotocols, in order of preference, see [`EventClient::with_protocols`]
+    pub fn protocols(mut self, protocols: &[&str]) -> Self {
+        self.protocols = protocols.iter().map(|p| p.to_string()).collect();
+        self
+    }
+    /// Set how binary messages are received, see [`EventClient::set_binary_type`]
+    pub fn binary_type(mut self, binary_type: BinaryType) -> Self {
+        self.binary_type = binary_type;
+        self
+    }
+    /// Set what happens to text messages that aren't valid Unicode, see [`EventClient::set_text_policy`]
+    pub fn text_policy(mut self, text_policy: TextPolicy) -> Self {
+        self.text_policy = text_policy;
+        self
+    }
+    /// Run handlers on a microtask instead of inside the browser's event callback, see [`EventClient::set_deferred_dispatch`]
+    pub fn deferred_dispatch(mut self, deferred: bool) -> Self {
+        self.deferred_dispatch = deferred;
+        self
+    }
+    /// Hold messages that arrive before a handler is registered, see [`EventClient::set_buffer_early_messages`]
+    pub fn buffer_early_messages(mut self, enabled: bool) -> Self {
+        self.buffer_early_messages = enabled;
+        self
+    }
+    /// Create the EventClient and connect
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    pub fn build(self) -> Result<EventClient, WebSocketError> {
+        let protocols: Vec<&str> = self.protocols.iter().map(String::as_str).collect();
+        let client = EventClient::with_protocols(&self.url, &protocols)?;
+        client.set_binary_type(self.binary_type);
+        client.set_text_policy(self.text_policy);
+        client.set_deferred_dispatch(self.deferred_dispatch);
+        client.set_buffer_early_messages(self.buffer_early_messages);
+        Ok(client)
+    }
+}
+
+impl EventClient {
+    /// Create a builder to configure a new EventClient before connecting
+    /// ```
+    /// let client = EventClient::builder("wss://ws.ifelse.io").build()?;
+    /// ```
+    pub fn builder(url: &str) -> EventClientBuilder {
+        EventClientBuilder {
+            url: url.to_string(),
+            protocols: vec![],
+            binary_type: BinaryType::default(),
+            text_policy: TextPolicy::default(),
+            deferred_dispatch: false,
+            buffer_early_messages: false,
+        }
+    }
+}
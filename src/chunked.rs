@@ -0,0 +1,178 @@
+//! Sending large binary payloads in chunks, so they don't freeze the page or flood the socket's buffer.
+//!
+//! [`EventClient::send_chunked`] splits a payload into binary messages that each start with a
+//! [`CHUNK_HEADER_LEN`] byte header: the transfer id, the chunk's index, and the number of chunks,
+//! each a big endian `u32`. A [`Reassembler`] puts the chunks back together on the receiving side.
+//! ```
+//! let mut reassembler = Reassembler::new();
+//! reassembler.set_on_progress(Some(Box::new(|progress| {
+//!     info!("{}/{} chunks", progress.received, progress.total);
+//! })));
+//! // Tag its log messages with the connection
+//! reassembler.set_client(Some(&client));
+//! let reassembler = RefCell::new(reassembler);
+//! client.set_on_message(Some(Box::new(move |_, message| {
+//!     if let Message::Binary(data) = message {
+//!         if let Some(payload) = reassembler.borrow_mut().push(&data) {
+//!             info!("Received {} bytes", payload.len());
+//!         }
+//!     }
+//! })));
+//! ```
+use crate::timer::sleep;
+use crate::{EventClient, WeakEventClient, WebSocketError};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The length of the header at the start of every chunk
+pub const CHUNK_HEADER_LEN: usize = 12;
+
+/// How many bytes can wait in the socket's buffer before the next chunk is sent
+pub(crate) const MAX_BUFFERED: u32 = 1 << 20;
+/// How often to check whether the buffer has drained, in milliseconds
+pub(crate) const DRAIN_POLL_MS: f64 = 10.0;
+
+/// Ids for transfers started by this program
+static NEXT_TRANSFER: AtomicU32 = AtomicU32::new(0);
+
+/// Build the messages for one transfer. Empty payloads are still sent as one empty chunk.
+pub(crate) fn chunks(id: u32, data: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+    let parts: Vec<&[u8]> = if data.is_empty() {
+        vec![&[]]
+    } else {
+        data.chunks(chunk_size).collect()
+    };
+    let total = parts.len() as u32;
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, part)| {
+            let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + part.len());
+            chunk.extend_from_slice(&id.to_be_bytes());
+            chunk.extend_from_slice(&(index as u32).to_be_bytes());
+            chunk.extend_from_slice(&total.to_be_bytes());
+            chunk.extend_from_slice(part);
+            chunk
+        })
+        .collect()
+}
+
+impl EventClient {
+    /// Send a large binary payload as chunks of at most `chunk_size` bytes, for a [`Reassembler`] to put back together.
+    ///
+    /// Whenever more than 1 MiB is waiting in the socket's buffer, this waits for it to drain
+    /// before sending the next chunk, so the page stays responsive.
+    /// Fails if the connection closes before every chunk is sent.
+    /// ```
+    /// wasm_bindgen_futures::spawn_local(async move {
+    ///     client.send_chunked(replay, 64 * 1024).await.unwrap();
+    /// });
+    /// ```
+    pub async fn send_chunked(
+        &self,
+        data: Vec<u8>,
+        chunk_size: usize,
+    ) -> Result<(), WebSocketError> {
+        if chunk_size == 0 {
+            return Err(WebSocketError::SendError(
+                "The chunk size must be greater than 0".into(),
+            ));
+        }
+        let id = NEXT_TRANSFER.fetch_add(1, Ordering::Relaxed);
+        for chunk in chunks(id, &data, chunk_size) {
+            while self.buffered_amount() > MAX_BUFFERED {
+                if !self.is_open() {
+                    return Err(WebSocketError::SendError(
+                        "The connection closed during a chunked transfer".into(),
+                    ));
+                }
+                sleep(DRAIN_POLL_MS).await;
+            }
+            self.send_binary(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// How far along a chunked transfer is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The transfer id from the chunk headers
+    pub id: u32,
+    /// The number of chunks received so far
+    pub received: u32,
+    /// The number of chunks in the transfer
+    pub total: u32,
+}
+
+/// A handler called for every chunk a [`Reassembler`] receives
+pub type ProgressHandler = Box<dyn Fn(Progress)>;
+
+/// A transfer that hasn't received all its chunks yet
+struct Transfer {
+    data: Vec<u8>,
+    received: u32,
+}
+
+/// Puts the chunks sent with [`EventClient::send_chunked`] back together
+#[derive(Default)]
+pub struct Reassembler {
+    transfers: HashMap<u32, Transfer>,
+    on_progress: Option<ProgressHandler>,
+    client: Option<WeakEventClient>,
+}
+
+impl Reassembler {
+    /// Create a Reassembler with no transfers in progress
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set a handler that's called for every chunk received, overwriting the previous handler
+    pub fn set_on_progress(&mut self, f: Option<ProgressHandler>) {
+        self.on_progress = f;
+    }
+    /// Set the client the chunks come from, whose connection tags this Reassembler's log messages
+    pub fn set_client(&mut self, client: Option<&EventClient>) {
+        self.client = client.map(WeakEventClient::new);
+    }
+    /// Add a received chunk, returning the whole payload once its last chunk arrives.
+    ///
+    /// Messages too short to be chunks, and chunks that arrive out of order, are ignored.
+    pub fn push(&mut self, chunk: &[u8]) -> Option<Vec<u8>> {
+        if chunk.len() < CHUNK_HEADER_LEN {
+            return None;
+        }
+        let field = |i: usize| u32::from_be_bytes(chunk[i * 4..i * 4 + 4].try_into().unwrap());
+        let (id, index, total) = (field(0), field(1), field(2));
+        let transfer = self.transfers.entry(id).or_insert(Transfer {
+            data: vec![],
+            received: 0,
+        });
+        if index != transfer.received {
+            let message = format!(
+                "Chunk {} of transfer {} arrived out of order, expected chunk {}",
+                index, id, transfer.received
+            );
+            match self.client.as_ref().and_then(WeakEventClient::upgrade) {
+                Some(client) => error!(client: client, "{}", message),
+                None => error!("{}", message),
+            }
+            return None;
+        }
+        transfer.data.extend_from_slice(&chunk[CHUNK_HEADER_LEN..]);
+        transfer.received += 1;
+        let received = transfer.received;
+        if let Some(f) = &self.on_progress {
+            f(Progress {
+                id,
+                received,
+                total,
+            });
+        }
+        if received < total {
+            return None;
+        }
+        self.transfers.remove(&id).map(|transfer| transfer.data)
+    }
+}
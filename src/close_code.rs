@@ -0,0 +1,153 @@
+//! Close codes, as registered for the WebSocket protocol, and a [`CloseCode`] enum for matching on them.
+//! ```
+//! client.close_with(close_code::NORMAL, Some("bye"))?;
+//!
+//! client.set_on_close(Some(Box::new(|e| {
+//!     if CloseCode::from(e.code()).is_retryable() {
+//!         schedule_reconnect();
+//!     }
+//! })));
+//! ```
+
+/// The connection finished what it was for
+pub const NORMAL: u16 = 1000;
+/// The server is shutting down, or the browser is leaving the page
+pub const GOING_AWAY: u16 = 1001;
+/// A side broke the WebSocket protocol
+pub const PROTOCOL_ERROR: u16 = 1002;
+/// A side received a type of data it can't accept, like binary data when it only understands text
+pub const UNSUPPORTED_DATA: u16 = 1003;
+/// The close frame didn't have a code. This is never sent, only reported.
+pub const NO_STATUS: u16 = 1005;
+/// The connection was lost without a close frame. This is never sent, only reported.
+pub const ABNORMAL: u16 = 1006;
+/// A text message wasn't valid UTF-8, or a message didn't match its type
+pub const INVALID_PAYLOAD: u16 = 1007;
+/// A message broke the server's policy
+pub const POLICY_VIOLATION: u16 = 1008;
+/// A message was too big to process
+pub const MESSAGE_TOO_BIG: u16 = 1009;
+/// The server didn't agree to an extension the client requires
+pub const MANDATORY_EXTENSION: u16 = 1010;
+/// The server hit an unexpected error
+pub const INTERNAL_ERROR: u16 = 1011;
+/// The server is restarting
+pub const SERVICE_RESTART: u16 = 1012;
+/// The server is overloaded, and the client should connect to another server or try again later
+pub const TRY_AGAIN_LATER: u16 = 1013;
+/// A gateway or proxy got an invalid response from the server
+pub const BAD_GATEWAY: u16 = 1014;
+/// The TLS handshake failed. This is never sent, only reported.
+pub const TLS_HANDSHAKE: u16 = 1015;
+
+/// A close code, sorted into the registered codes and the ranges left for libraries and applications
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CloseCode {
+    /// [`NORMAL`]
+    Normal,
+    /// [`GOING_AWAY`]
+    GoingAway,
+    /// [`PROTOCOL_ERROR`]
+    ProtocolError,
+    /// [`UNSUPPORTED_DATA`]
+    UnsupportedData,
+    /// [`NO_STATUS`]
+    NoStatus,
+    /// [`ABNORMAL`]
+    Abnormal,
+    /// [`INVALID_PAYLOAD`]
+    InvalidPayload,
+    /// [`POLICY_VIOLATION`]
+    PolicyViolation,
+    /// [`MESSAGE_TOO_BIG`]
+    MessageTooBig,
+    /// [`MANDATORY_EXTENSION`]
+    MandatoryExtension,
+    /// [`INTERNAL_ERROR`]
+    InternalError,
+    /// [`SERVICE_RESTART`]
+    ServiceRestart,
+    /// [`TRY_AGAIN_LATER`]
+    TryAgainLater,
+    /// [`BAD_GATEWAY`]
+    BadGateway,
+    /// [`TLS_HANDSHAKE`]
+    TlsHandshake,
+    /// A code from 3000 to 3999, registered by libraries and frameworks
+    Library(u16),
+    /// A code from 4000 to 4999, for private use by applications
+    Application(u16),
+    /// A reserved or unassigned code
+    Other(u16),
+}
+
+impl CloseCode {
+    /// Whether a connection closed with this code is worth reopening.
+    ///
+    /// These are the codes for servers going away or restarting, overloaded or failing servers,
+    /// and connections that were lost. Library and application codes aren't, since only the
+    /// application knows what they mean.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            CloseCode::GoingAway
+                | CloseCode::NoStatus
+                | CloseCode::Abnormal
+                | CloseCode::InternalError
+                | CloseCode::ServiceRestart
+                | CloseCode::TryAgainLater
+                | CloseCode::BadGateway
+        )
+    }
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            NORMAL => CloseCode::Normal,
+            GOING_AWAY => CloseCode::GoingAway,
+            PROTOCOL_ERROR => CloseCode::ProtocolError,
+            UNSUPPORTED_DATA => CloseCode::UnsupportedData,
+            NO_STATUS => CloseCode::NoStatus,
+            ABNORMAL => CloseCode::Abnormal,
+            INVALID_PAYLOAD => CloseCode::InvalidPayload,
+            POLICY_VIOLATION => CloseCode::PolicyViolation,
+            MESSAGE_TOO_BIG => CloseCode::MessageTooBig,
+            MANDATORY_EXTENSION => CloseCode::MandatoryExtension,
+            INTERNAL_ERROR => CloseCode::InternalError,
+            SERVICE_RESTART => CloseCode::ServiceRestart,
+            TRY_AGAIN_LATER => CloseCode::TryAgainLater,
+            BAD_GATEWAY => CloseCode::BadGateway,
+            TLS_HANDSHAKE => CloseCode::TlsHandshake,
+            3000..=3999 => CloseCode::Library(code),
+            4000..=4999 => CloseCode::Application(code),
+            _ => CloseCode::Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        match code {
+            CloseCode::Normal => NORMAL,
+            CloseCode::GoingAway => GOING_AWAY,
+            CloseCode::ProtocolError => PROTOCOL_ERROR,
+            CloseCode::UnsupportedData => UNSUPPORTED_DATA,
+            CloseCode::NoStatus => NO_STATUS,
+            CloseCode::Abnormal => ABNORMAL,
+            CloseCode::InvalidPayload => INVALID_PAYLOAD,
+            CloseCode::PolicyViolation => POLICY_VIOLATION,
+            CloseCode::MessageTooBig => MESSAGE_TOO_BIG,
+            CloseCode::MandatoryExtension => MANDATORY_EXTENSION,
+            CloseCode::InternalError => INTERNAL_ERROR,
+            CloseCode::ServiceRestart => SERVICE_RESTART,
+            CloseCode::TryAgainLater => TRY_AGAIN_LATER,
+            CloseCode::BadGateway => BAD_GATEWAY,
+            CloseCode::TlsHandshake => TLS_HANDSHAKE,
+            CloseCode::Library(code) | CloseCode::Application(code) | CloseCode::Other(code) => {
+                code
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Dropping outdated state updates when a [`PollingClient`] falls behind.
+//!
+//! Position updates and the like replace each other, so once a newer update for the same entity
+//! has arrived, the older ones are only a stale history. With a coalesce key set, each call to
+//! [`receive`](PollingClient::receive) returns only the newest message for each key.
+//! ```
+//! client.set_coalesce_key(Some(Box::new(|message| match message {
+//!     Message::Binary(data) if data.first() == Some(&POSITION) => {
+//!         Some(u32::from_be_bytes(data[1..5].try_into().unwrap()) as u64)
+//!     }
+//!     _ => None,
+//! })));
+//! ```
+use crate::{Message, PollingClient};
+use std::collections::{HashSet, VecDeque};
+
+/// Picks the key a message is coalesced by, like an entity id, or None to always keep it
+pub type CoalesceKey = Box<dyn Fn(&Message) -> Option<u64>>;
+
+/// Drop every message that has a newer message with the same key after it
+pub(crate) fn coalesce(data: &mut VecDeque<Message>, key: &CoalesceKey) {
+    let mut seen = HashSet::new();
+    let keep: Vec<bool> = data
+        .iter()
+        .rev()
+        .map(|message| match key(message) {
+            Some(key) => seen.insert(key),
+            None => true,
+        })
+        .collect();
+    let mut keep = keep.into_iter().rev();
+    data.retain(|_| keep.next().unwrap_or(true));
+}
+
+impl PollingClient {
+    /// Only return the newest of the waiting messages with the same key, or set None to return every message.
+    ///
+    /// Messages keep their order, and messages without a key are always returned.
+    /// ```
+    /// client.set_coalesce_key(Some(Box::new(|message| entity_id(message))));
+    /// ```
+    pub fn set_coalesce_key(&self, key: Option<CoalesceKey>) {
+        *self.coalesce_key.borrow_mut() = key;
+    }
+}
@@ -0,0 +1,49 @@
+//! Conversions between [`Message`] and the message types of other websocket crates.
+use crate::Message;
+#[cfg(feature = "tungstenite")]
+use std::convert::TryFrom;
+
+#[cfg(feature = "gloo-net")]
+impl From<Message> for gloo_net::websocket::Message {
+    fn from(message: Message) -> Self {
+        match message {
+            Message::Text(text) => gloo_net::websocket::Message::Text(text.to_string()),
+            Message::Binary(data) => gloo_net::websocket::Message::Bytes(data.into()),
+        }
+    }
+}
+
+#[cfg(feature = "gloo-net")]
+impl From<gloo_net::websocket::Message> for Message {
+    fn from(message: gloo_net::websocket::Message) -> Self {
+        match message {
+            gloo_net::websocket::Message::Text(text) => Message::Text(text.into()),
+            gloo_net::websocket::Message::Bytes(data) => Message::Binary(data.into()),
+        }
+    }
+}
+
+#[cfg(feature = "tungstenite")]
+impl From<Message> for tungstenite::Message {
+    fn from(message: Message) -> Self {
+        match message {
+            Message::Text(text) => tungstenite::Message::text(text.to_string()),
+            Message::Binary(data) => tungstenite::Message::binary(data),
+        }
+    }
+}
+
+/// Only text and binary messages can be converted.
+/// Control frames (ping, pong, close, and raw frames) are handed back unchanged as the error.
+#[cfg(feature = "tungstenite")]
+impl TryFrom<tungstenite::Message> for Message {
+    type Error = tungstenite::Message;
+
+    fn try_from(message: tungstenite::Message) -> Result<Self, Self::Error> {
+        match message {
+            tungstenite::Message::Text(text) => Ok(Message::Text(text.as_str().into())),
+            tungstenite::Message::Binary(data) => Ok(Message::Binary(data)),
+            other => Err(other),
+        }
+    }
+}
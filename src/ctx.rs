@@ -0,0 +1,79 @@
+//! A handler context, for handlers that only need to reply to the server or check on the connection.
+//!
+//! Handlers added with [`EventClient::add_on_message_ctx`] and [`EventClient::add_on_connection_ctx`]
+//! get a [`Ctx`] instead of the client. It only exposes sending, the status, and closing, so it can't
+//! be used to swap handlers or reconfigure the client while it's dispatching.
+//! ```
+//! client.add_on_message_ctx(Box::new(|ctx, message| {
+//!     if message == Message::Text("ping".into()) {
+//!         ctx.send_string("pong").ok();
+//!     }
+//! }));
+//! ```
+use crate::{ConnectionStatus, EventClient, HandlerId, Message, WebSocketError};
+
+/// An on_message event handler that gets a [`Ctx`] instead of the client
+pub type CtxMessageHandler = Box<dyn Fn(&Ctx, Message)>;
+/// An on_connection event handler that gets a [`Ctx`] instead of the client
+pub type CtxConnectionHandler = Box<dyn Fn(&Ctx)>;
+
+/// A handle to a client's connection, for replying from handlers.
+///
+/// It shares its state with the client, so cloning it is cheap and it can be kept after the
+/// handler returns.
+#[derive(Clone)]
+pub struct Ctx {
+    client: EventClient,
+}
+
+impl Ctx {
+    /// Send a text message to the server
+    pub fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        self.client.send_string(message)
+    }
+    /// Send a binary message to the server
+    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        self.client.send_binary(message)
+    }
+    /// Get the client's current connection status
+    pub fn status(&self) -> ConnectionStatus {
+        self.client.status()
+    }
+    /// Close the connection
+    pub fn close(&self) -> Result<(), WebSocketError> {
+        self.client.close()
+    }
+}
+
+impl EventClient {
+    /// Get a [`Ctx`] for this client
+    /// ```
+    /// let ctx = client.ctx();
+    /// ctx.send_string("Hello!")?;
+    /// ```
+    pub fn ctx(&self) -> Ctx {
+        Ctx {
+            client: self.clone(),
+        }
+    }
+    /// Add an on_message event handler that gets a [`Ctx`], which runs alongside the handlers
+    /// added with [`add_on_message`](Self::add_on_message)
+    /// ```
+    /// let id = client.add_on_message_ctx(Box::new(|ctx, m| {
+    ///     ctx.send_string("ack").ok();
+    /// }));
+    /// ```
+    pub fn add_on_message_ctx(&self, f: CtxMessageHandler) -> HandlerId {
+        self.add_on_message(Box::new(move |client, message| f(&client.ctx(), message)))
+    }
+    /// Add an on_connection event handler that gets a [`Ctx`], which runs alongside the handlers
+    /// added with [`add_on_connection`](Self::add_on_connection)
+    /// ```
+    /// let id = client.add_on_connection_ctx(Box::new(|ctx| {
+    ///     ctx.send_string("hello").ok();
+    /// }));
+    /// ```
+    pub fn add_on_connection_ctx(&self, f: CtxConnectionHandler) -> HandlerId {
+        self.add_on_connection(Box::new(move |client| f(&client.ctx())))
+    }
+}
@@ -0,0 +1,129 @@
+//! Decompressing binary messages that servers compressed before sending, enabled with the
+//! `decompress` feature.
+//!
+//! Some servers gzip or deflate large payloads, like JSON snapshots, and send them as binary
+//! messages. With [`Decompression`] set, those messages are decompressed before the handlers see
+//! them. Compressed messages are recognized by the gzip or zlib header, or by a flag byte the
+//! server puts in front of them. Output is capped, so a small message can't expand to fill the
+//! wasm heap. Messages that fail to decompress are passed to the on_protocol_error handler.
+//! ```
+//! client.set_decompression(Some(Decompression {
+//!     max_output: 8 << 20,
+//!     ..Decompression::default()
+//! }));
+//! ```
+use crate::{EventClient, Message, ProtocolError};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::io::Read;
+
+/// How compressed messages are recognized and decompressed, see [`EventClient::set_decompression`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decompression {
+    /// Decompress binary messages that start with a gzip or zlib header.
+    ///
+    /// Uncompressed binary data can start with the same two bytes by chance, so turn this off
+    /// when the protocol uses a flag byte.
+    pub detect_headers: bool,
+    /// Decompress binary messages that start with this byte, which is removed first.
+    /// The rest is gzip or zlib if it has their header, and raw deflate otherwise.
+    pub flag_byte: Option<u8>,
+    /// The largest a decompressed message can be, in bytes. Larger messages are dropped.
+    pub max_output: usize,
+}
+
+impl Default for Decompression {
+    fn default() -> Self {
+        Self {
+            detect_headers: true,
+            flag_byte: None,
+            max_output: 16 << 20,
+        }
+    }
+}
+
+/// The formats a compressed message can be in
+enum Format {
+    Gzip,
+    Zlib,
+    Deflate,
+}
+
+/// Recognize a gzip or zlib header
+fn format(data: &[u8]) -> Option<Format> {
+    match data {
+        [0x1f, 0x8b, ..] => Some(Format::Gzip),
+        // Deflate with a window size zlib allows, and the header's checksum
+        [cmf, flg, ..]
+            if cmf & 0x0f == 8
+                && cmf >> 4 <= 7
+                && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0 =>
+        {
+            Some(Format::Zlib)
+        }
+        _ => None,
+    }
+}
+
+/// Decompress `data`, failing if it's invalid or larger than `max_output` bytes
+fn decompress(data: &[u8], format: Format, max_output: usize) -> Result<Vec<u8>, String> {
+    let reader: Box<dyn Read + '_> = match format {
+        Format::Gzip => Box::new(GzDecoder::new(data)),
+        Format::Zlib => Box::new(ZlibDecoder::new(data)),
+        Format::Deflate => Box::new(DeflateDecoder::new(data)),
+    };
+    let mut output = vec![];
+    // One byte over the limit is enough to know it's too large
+    reader
+        .take(max_output as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| e.to_string())?;
+    if output.len() > max_output {
+        return Err(format!(
+            "The decompressed message is over the {} byte limit",
+            max_output
+        ));
+    }
+    Ok(output)
+}
+
+impl EventClient {
+    /// Decompress received binary messages that were compressed by the server, or set None to
+    /// deliver them as they arrive
+    /// ```
+    /// client.set_decompression(Some(Decompression {
+    ///     detect_headers: false,
+    ///     flag_byte: Some(0xff),
+    ///     ..Decompression::default()
+    /// }));
+    /// ```
+    pub fn set_decompression(&self, decompression: Option<Decompression>) {
+        self.listeners.borrow_mut().decompression = decompression;
+    }
+    /// Decompress a received message if it's compressed, returning None if it couldn't be
+    pub(crate) fn decompress_inbound(&self, message: Message) -> Option<Message> {
+        let settings = match self.listeners.borrow().decompression {
+            Some(settings) => settings,
+            None => return Some(message),
+        };
+        let data = match &message {
+            Message::Binary(data) => data,
+            Message::Text(_) => return Some(message),
+        };
+        let (data, format) = match (settings.flag_byte, data.split_first()) {
+            (Some(flag), Some((first, rest))) if *first == flag => {
+                (rest, format(rest).unwrap_or(Format::Deflate))
+            }
+            _ => match format(data) {
+                Some(format) if settings.detect_headers => (&data[..], format),
+                _ => return Some(message),
+            },
+        };
+        match decompress(data, format, settings.max_output) {
+            Ok(data) => Some(Message::Binary(data.into())),
+            Err(e) => {
+                self.dispatch_protocol_error(ProtocolError::Decompress(e));
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Dioxus integration, enabled with the `dioxus` feature.
+//!
+//! [`use_websocket`] opens a connection owned by a component, and re-renders the component
+//! whenever the connection status changes or a message arrives.
+//! ```
+//! #[component]
+//! fn Chat() -> Element {
+//!     let socket = use_websocket("wss://ws.ifelse.io");
+//!     rsx! {
+//!         p { "{socket.status:?}" }
+//!         p { "{socket.message:?}" }
+//!         button {
+//!             onclick: move |_| { socket.send(Message::Text("Hello, World!".into())).ok(); },
+//!             "Send"
+//!         }
+//!     }
+//! }
+//! ```
+use crate::{CloseInfo, ConnectionStatus, EventClient, Message, WebSocketError};
+use dioxus_core::{use_drop, use_hook};
+use dioxus_hooks::use_signal;
+use dioxus_signals::{CopyValue, ReadSignal, ReadableExt, Signal, WritableExt};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A websocket connection owned by a component.
+///
+/// This is `Copy`, so it can be moved into as many event handlers as needed.
+#[derive(Clone, Copy)]
+pub struct UseWebSocket {
+    /// The current connection status
+    pub status: ReadSignal<ConnectionStatus>,
+    /// The most recent message received from the server
+    pub message: ReadSignal<Option<Message>>,
+    client: CopyValue<Rc<RefCell<Option<EventClient>>>>,
+}
+
+impl UseWebSocket {
+    /// Send a message to the server
+    pub fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        match &*self.client.read().borrow() {
+            Some(client) => match message {
+                Message::Text(text) => client.send_string(&text),
+                Message::Binary(data) => client.send_binary(data.into()),
+            },
+            None => Err(WebSocketError::SendError("Not connected".into())),
+        }
+    }
+    /// Close the connection
+    pub fn close(&self) -> Result<(), WebSocketError> {
+        match &*self.client.read().borrow() {
+            Some(client) => client.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Connect to a WebSocket URL for as long as the component is mounted.
+///
+/// Like other Dioxus hooks, the connection is only created on the first render,
+/// so later changes to `url` are ignored.
+pub fn use_websocket(url: &str) -> UseWebSocket {
+    let status = use_signal(|| ConnectionStatus::Connecting);
+    let message = use_signal(|| None);
+    let socket = use_hook(|| {
+        let client = match EventClient::new(url) {
+            Ok(mut client) => {
+                client.set_on_connection(Some(Box::new(move |_| {
+                    set(status, ConnectionStatus::Connected);
+                })));
+                client.set_on_error(Some(Box::new(move |_| {
+                    set(status, ConnectionStatus::Error(CloseInfo::abnormal()));
+                })));
+                client.set_on_close(Some(Box::new(move |e| {
+                    set(status, ConnectionStatus::closed(CloseInfo::from(&e)));
+                })));
+                client.set_on_message(Some(Box::new(move |_, m| {
+                    set(message, Some(m));
+                })));
+                Some(client)
+            }
+            Err(e) => {
+                error!("{}", e);
+                set(status, ConnectionStatus::Error(CloseInfo::abnormal()));
+                None
+            }
+        };
+        UseWebSocket {
+            status: status.into(),
+            message: message.into(),
+            client: CopyValue::new(Rc::new(RefCell::new(client))),
+        }
+    });
+    // Hold on to the client separately, since the CopyValue may be dropped before this runs
+    let client = use_hook(|| socket.client.read().clone());
+    use_drop(move || {
+        // The signals are dropped along with the component, so the handlers can't run anymore
+        if let Some(mut client) = client.borrow_mut().take() {
+            client.set_on_error(None);
+            client.set_on_connection(None);
+            client.set_on_message(None);
+            client.set_on_close(None);
+            client.close().ok();
+        }
+    });
+    socket
+}
+
+/// Set a signal from a handler, which can only capture it immutably
+fn set<T: 'static>(mut signal: Signal<T>, value: T) {
+    signal.set(value);
+}
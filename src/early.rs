@@ -0,0 +1,98 @@
+//! Holding messages that arrive before any on_message handler is registered.
+//!
+//! A server that sends as soon as the connection opens can beat the code registering the
+//! handlers, especially when there's an `.await` in between, and those messages are lost. With
+//! early buffering on, they're held until the first message handler is registered, then passed to
+//! it in order. A [`PollingClient`](crate::PollingClient) registers its handler when it's created,
+//! so it never loses them.
+//! ```
+//! let client = EventClient::builder("wss://ws.ifelse.io")
+//!     .buffer_early_messages(true)
+//!     .build()?;
+//! load_assets().await;
+//! // Gets the greeting the server sent while the assets were loading
+//! client.add_on_message(Box::new(|_client, message| info!("{:?}", message)));
+//! ```
+use crate::outbound::message_len;
+use crate::{memory, EventClient, Message};
+use std::collections::VecDeque;
+
+impl EventClient {
+    /// Hold messages that arrive before any on_message handler is registered, and pass them to the
+    /// first one registered, or set false to drop them as usual.
+    ///
+    /// This covers [`set_on_message`](Self::set_on_message), [`add_on_message`](Self::add_on_message),
+    /// [`once_on_message`](Self::once_on_message), [`subscribe_filtered`](Self::subscribe_filtered),
+    /// [`set_on_message_async`](Self::set_on_message_async) and [`set_schema_registry`](Self::set_schema_registry),
+    /// and the held messages are passed on before the method returns. Held messages count toward
+    /// the [memory limit](Self::set_memory_limit).
+    /// ```
+    /// client.set_buffer_early_messages(true);
+    /// ```
+    pub fn set_buffer_early_messages(&self, enabled: bool) {
+        let early = &mut self.listeners.borrow_mut().early;
+        if !enabled {
+            *early = None;
+        } else if early.is_none() {
+            *early = Some(VecDeque::new());
+        }
+    }
+    /// Whether messages are held until a handler is registered, see
+    /// [`set_buffer_early_messages`](Self::set_buffer_early_messages)
+    pub fn buffers_early_messages(&self) -> bool {
+        self.listeners.borrow().early.is_some()
+    }
+    /// Hold `message` if early buffering is on and nothing would handle it, returning whether it was held
+    pub(crate) fn hold_early(&self, message: &Message) -> bool {
+        if !self.buffers_early_messages() || self.has_message_handlers() {
+            return false;
+        }
+        let other_bytes = self.buffered_bytes() - self.early_bytes();
+        let discarded = {
+            let mut listeners = self.listeners.borrow_mut();
+            let limit = listeners.memory_limit;
+            match &mut listeners.early {
+                Some(early) => {
+                    early.push_back(message.clone());
+                    memory::trim(early, other_bytes, limit)
+                }
+                None => 0,
+            }
+        };
+        self.discarded_inbound(discarded);
+        true
+    }
+    /// Pass the held messages to the handlers once one is registered, and stop holding them
+    pub(crate) fn replay_early(&self) {
+        if !self.has_message_handlers() {
+            return;
+        }
+        let early = match self.listeners.borrow_mut().early.take() {
+            Some(early) => early,
+            None => return,
+        };
+        if !early.is_empty() {
+            trace!(client: self, "Passing on {} messages received early", early.len());
+        }
+        for message in early {
+            self.run_message_handlers(message);
+        }
+    }
+    /// Whether any handler would receive a message
+    fn has_message_handlers(&self) -> bool {
+        if self.on_message.borrow().is_some() || self.has_async_handler() {
+            return true;
+        }
+        let listeners = self.listeners.borrow();
+        !listeners.on_message.is_empty()
+            || !listeners.subscriptions.is_empty()
+            || listeners.schemas.is_some()
+    }
+    /// The bytes of the messages held until a handler is registered
+    pub(crate) fn early_bytes(&self) -> usize {
+        match &self.listeners.borrow().early {
+            Some(early) => early.iter().map(message_len).sum(),
+            None => 0,
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! egui integration, enabled with the `egui` feature.
+//!
+//! egui only repaints after input events, so a [`PollingClient`] polled from `update` could sit on
+//! new messages until the mouse moves. [`EguiClient`] requests a repaint whenever a message
+//! arrives or the connection status changes, so the next frame picks them up right away.
+//! ```
+//! impl eframe::App for ChatApp {
+//!     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+//!         self.messages.extend(self.client.receive());
+//!         egui::CentralPanel::default().show(ctx, |ui| {
+//!             ui.label(format!("{:?}", self.client.status()));
+//!             for message in &self.messages {
+//!                 ui.label(format!("{:?}", message));
+//!             }
+//!         });
+//!     }
+//! }
+//! ```
+use crate::{ConnectionStatus, Message, PollingClient, WebSocketError, WsClient};
+
+/// A [`PollingClient`] that requests an egui repaint for every event
+pub struct EguiClient {
+    /// The client used for this connection
+    pub client: PollingClient,
+}
+
+impl EguiClient {
+    /// Create a new EguiClient and connect to a WebSocket URL
+    /// ```
+    /// let client = EguiClient::new("wss://ws.ifelse.io", &cc.egui_ctx)?;
+    /// ```
+    pub fn new(url: &str, ctx: &egui::Context) -> Result<Self, WebSocketError> {
+        let mut client = PollingClient::new(url)?;
+        let ctx_ref = ctx.clone();
+        client.set_on_connection(Some(Box::new(move |_| ctx_ref.request_repaint())));
+        let ctx_ref = ctx.clone();
+        client.set_on_error(Some(Box::new(move |_| ctx_ref.request_repaint())));
+        let ctx_ref = ctx.clone();
+        client.set_on_close(Some(Box::new(move |_| ctx_ref.request_repaint())));
+        let ctx_ref = ctx.clone();
+        client.set_on_message(Some(Box::new(move |_, _| ctx_ref.request_repaint())));
+        Ok(Self { client })
+    }
+    /// Get all new WebSocket messages that were received since this function was last called
+    pub fn receive(&mut self) -> Vec<Message> {
+        self.client.receive()
+    }
+    /// Get the client's current connection status
+    pub fn status(&self) -> ConnectionStatus {
+        self.client.status()
+    }
+    /// Send a text message to the server
+    pub fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        self.client.send_string(message)
+    }
+    /// Send a binary message to the server
+    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        self.client.send_binary(message)
+    }
+    /// Close the connection
+    pub fn close(&self) -> Result<(), WebSocketError> {
+        self.client.close()
+    }
+}
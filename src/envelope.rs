@@ -0,0 +1,47 @@
+//! A standard header for typed messages, so layers that need message metadata share one format.
+//!
+//! An [`Envelope`] wraps a payload with an id, the time it was sent and a type tag. A
+//! [`TypedPollingClient`](crate::TypedPollingClient) wraps what it sends with
+//! [`send_enveloped`](crate::TypedPollingClient::send_enveloped), numbering messages from 1, and
+//! receives envelopes when its message type is an `Envelope<T>`. With [`Json`](crate::Json), an
+//! envelope looks like `{"id": 1, "sent_at": 1700000000000, "type": "chat", "payload": ...}`.
+//! ```
+//! let mut client = TypedPollingClient::<Envelope<ServerMessage>, Json>::new("wss://game.example.com")?;
+//! let id = client.send_enveloped("join", &Join { name: "ferris" })?;
+//! for envelope in client.receive().into_iter().flatten() {
+//!     if !seen.insert(envelope.id) {
+//!         continue;
+//!     }
+//!     world.apply(envelope.payload);
+//! }
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// A payload with the metadata every message carries, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    /// The message's number, unique for each sender
+    pub id: u64,
+    /// When the message was sent, in milliseconds since the Unix epoch on the sender's clock
+    pub sent_at: f64,
+    /// What kind of message the payload is
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The message itself
+    pub payload: T,
+}
+
+impl<T> Envelope<T> {
+    /// Wrap a payload, stamping it with the current time
+    /// ```
+    /// let envelope = Envelope::new(7, "chat", "Hello!");
+    /// ```
+    pub fn new(id: u64, kind: impl Into<String>, payload: T) -> Self {
+        Self {
+            id,
+            sent_at: crate::timer::now_ms(),
+            kind: kind.into(),
+            payload,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Length-prefixed framing, for servers that pack several logical messages into one binary frame.
+//!
+//! Each logical message is preceded by its length, as an unsigned integer of the configured
+//! [`PrefixSize`] and [`Endianness`]. [`FramedWriter`] packs messages into a frame, and
+//! [`FramedReader`] splits them apart again. With [`EventClient::set_framing`], binary messages are
+//! split automatically, so each logical message is passed to on_message on its own.
+//! ```
+//! let framing = Framing::default();
+//! client.set_framing(Some(framing));
+//!
+//! let mut writer = FramedWriter::new(framing);
+//! writer.push(b"first")?;
+//! writer.push(b"second")?;
+//! client.send_binary(writer.finish())?;
+//! ```
+use crate::{Bytes, EventClient};
+use std::fmt;
+
+/// The size of the length prefix before each logical message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixSize {
+    /// A 1 byte prefix, for messages up to 255 bytes
+    U8,
+    /// A 2 byte prefix, for messages up to 64 KiB
+    U16,
+    /// A 4 byte prefix, for messages up to 4 GiB
+    U32,
+}
+
+impl PrefixSize {
+    pub(crate) fn len(self) -> usize {
+        match self {
+            PrefixSize::U8 => 1,
+            PrefixSize::U16 => 2,
+            PrefixSize::U32 => 4,
+        }
+    }
+    fn max(self) -> usize {
+        match self {
+            PrefixSize::U8 => u8::MAX as usize,
+            PrefixSize::U16 => u16::MAX as usize,
+            PrefixSize::U32 => u32::MAX as usize,
+        }
+    }
+}
+
+/// The byte order of the length prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    /// Most significant byte first, also called network byte order
+    Big,
+    /// Least significant byte first
+    Little,
+}
+
+/// How logical messages are packed into a binary frame.
+///
+/// The default is a 4 byte big endian prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    /// The size of the length prefix
+    pub prefix: PrefixSize,
+    /// The byte order of the length prefix
+    pub endianness: Endianness,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Self {
+            prefix: PrefixSize::U32,
+            endianness: Endianness::Big,
+        }
+    }
+}
+
+/// An error from packing or splitting a framed message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramingError {
+    /// A logical message is too long for the prefix size
+    TooLong(usize),
+    /// The frame ended in the middle of a length prefix or message
+    Truncated,
+}
+
+impl fmt::Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLong(len) => write!(f, "A {} byte message is too long for the prefix", len),
+            Self::Truncated => write!(f, "The frame ended in the middle of a message"),
+        }
+    }
+}
+
+impl std::error::Error for FramingError {}
+
+/// Packs logical messages into one binary frame
+#[derive(Debug, Clone)]
+pub struct FramedWriter {
+    framing: Framing,
+    frame: Vec<u8>,
+}
+
+impl FramedWriter {
+    /// Create a writer with an empty frame
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            frame: vec![],
+        }
+    }
+    /// Add a logical message to the frame
+    pub fn push(&mut self, message: &[u8]) -> Result<(), FramingError> {
+        let Framing { prefix, endianness } = self.framing;
+        if message.len() > prefix.max() {
+            return Err(FramingError::TooLong(message.len()));
+        }
+        let len = message.len() as u32;
+        let bytes = match endianness {
+            Endianness::Big => len.to_be_bytes(),
+            Endianness::Little => len.to_le_bytes(),
+        };
+        // The length fits in the prefix, so the other bytes are all zero
+        match endianness {
+            Endianness::Big => self.frame.extend_from_slice(&bytes[4 - prefix.len()..]),
+            Endianness::Little => self.frame.extend_from_slice(&bytes[..prefix.len()]),
+        }
+        self.frame.extend_from_slice(message);
+        Ok(())
+    }
+    /// Whether no messages have been added since the last frame was finished
+    pub fn is_empty(&self) -> bool {
+        self.frame.is_empty()
+    }
+    /// The size of the frame so far, in bytes
+    pub fn len(&self) -> usize {
+        self.frame.len()
+    }
+    /// Take the finished frame, leaving the writer empty for the next one
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.frame)
+    }
+}
+
+/// Splits binary frames into the logical messages inside them
+#[derive(Debug, Clone, Copy)]
+pub struct FramedReader {
+    framing: Framing,
+}
+
+impl FramedReader {
+    /// Create a reader for frames packed with `framing`
+    pub fn new(framing: Framing) -> Self {
+        Self { framing }
+    }
+    /// Split a frame into its logical messages.
+    /// The messages share the frame's memory, so this doesn't copy them.
+    pub fn split(&self, frame: &Bytes) -> Result<Vec<Bytes>, FramingError> {
+        let Framing { prefix, endianness } = self.framing;
+        let mut messages = vec![];
+        let mut offset = 0;
+        while offset < frame.len() {
+            let start = offset + prefix.len();
+            let prefix_bytes = frame.get(offset..start).ok_or(FramingError::Truncated)?;
+            let len = match endianness {
+                Endianness::Big => prefix_bytes.iter().fold(0, |len, b| len << 8 | *b as usize),
+                Endianness::Little => prefix_bytes
+                    .iter()
+                    .rev()
+                    .fold(0, |len, b| len << 8 | *b as usize),
+            };
+            let end = start + len;
+            if end > frame.len() {
+                return Err(FramingError::Truncated);
+            }
+            messages.push(frame.slice(start..end));
+            offset = end;
+        }
+        Ok(messages)
+    }
+}
+
+impl EventClient {
+    /// Split received binary messages into the logical messages packed inside them, or set None to stop.
+    ///
+    /// While this is set, on_message is called once for each logical message instead of once per frame.
+    /// Frames that can't be split are logged and dropped. Text messages aren't affected.
+    /// ```
+    /// client.set_framing(Some(Framing {
+    ///     prefix: PrefixSize::U16,
+    ///     endianness: Endianness::Little,
+    /// }));
+    /// ```
+    pub fn set_framing(&self, framing: Option<Framing>) {
+        self.listeners.borrow_mut().framing = framing;
+    }
+}
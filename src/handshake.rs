@@ -0,0 +1,139 @@
+//! Checking that the server speaks the application's protocol version before the application hears
+//! about the connection.
+//!
+//! With a [`Handshake`] set, the client sends its hello message as soon as the socket opens, and
+//! treats the first message it receives as the server's reply. The status stays
+//! [`Connecting`](crate::ConnectionStatus::Connecting) until the reply is accepted, so the
+//! on_connection handlers, [`EventClient::connected`], and messages queued with
+//! [`EventClient::try_send`] all wait for it. A rejected reply runs the on_version_mismatch
+//! handler and closes the connection.
+//! ```
+//! client.set_handshake(Some(Handshake {
+//!     hello: Message::Text(r#"{"version": 3, "features": ["deltas"]}"#.into()),
+//!     check: Box::new(|reply| match reply {
+//!         Message::Text(text) if &**text == "ok" => Ok(()),
+//!         other => Err(format!("{:?}", other)),
+//!     }),
+//! }));
+//! client.set_on_version_mismatch(Some(Box::new(|reason| {
+//!     show_update_prompt(reason);
+//! })));
+//! ```
+use crate::{close_code, ConnectionStatus, EventClient, Message};
+use std::rc::Rc;
+
+/// Checks the server's reply to the hello message, returning why it was rejected if it was
+pub type HandshakeCheck = Box<dyn Fn(&Message) -> Result<(), String>>;
+/// A handler called with the reason when the server's reply is rejected
+pub type VersionMismatchHandler = Box<dyn Fn(String)>;
+
+/// A version handshake, see [`EventClient::set_handshake`]
+pub struct Handshake {
+    /// Sent as soon as the socket opens, usually with the protocol version and capabilities
+    pub hello: Message,
+    /// Called with the first message received, which is accepted if this returns Ok
+    pub check: HandshakeCheck,
+}
+
+/// The handshake settings shared by every handle to a client
+#[derive(Default)]
+pub(crate) struct HandshakeState {
+    handshake: Option<Rc<Handshake>>,
+    /// Whether the hello was sent and the reply hasn't arrived
+    waiting: bool,
+    on_version_mismatch: Option<Rc<VersionMismatchHandler>>,
+}
+
+impl EventClient {
+    /// Exchange a hello message with the server when the connection opens, before reporting it as
+    /// connected, or set None to report it as soon as it opens.
+    ///
+    /// This applies to connections that haven't opened yet, so set it right after creating the client.
+    /// ```
+    /// client.set_handshake(Some(Handshake {
+    ///     hello: Message::Text("v2".into()),
+    ///     check: Box::new(|reply| match reply {
+    ///         Message::Text(version) if &**version == "v2" => Ok(()),
+    ///         _ => Err("The server doesn't speak v2".into()),
+    ///     }),
+    /// }));
+    /// ```
+    pub fn set_handshake(&self, handshake: Option<Handshake>) {
+        self.listeners.borrow_mut().handshake.handshake = handshake.map(Rc::new);
+    }
+    /// Set a handler for when the server's reply to the handshake is rejected, overwriting the
+    /// previous handler. The connection is closed right after it runs.
+    /// You can set [None](std::option) to disable the on_version_mismatch handler.
+    /// ```
+    /// client.set_on_version_mismatch(Some(Box::new(|reason| {
+    ///     error!("The server speaks another protocol version: {}", reason);
+    /// })));
+    /// ```
+    pub fn set_on_version_mismatch(&self, f: Option<VersionMismatchHandler>) {
+        self.listeners.borrow_mut().handshake.on_version_mismatch = f.map(Rc::new);
+    }
+    /// Whether the hello message was sent and the client is waiting for the server's reply
+    pub fn is_handshaking(&self) -> bool {
+        self.listeners.borrow().handshake.waiting
+    }
+    /// Send the hello message if there's a handshake, returning whether the connection has to wait for the reply
+    pub(crate) fn start_handshake(&self) -> bool {
+        let handshake = self.listeners.borrow().handshake.handshake.clone();
+        let handshake = match handshake {
+            Some(handshake) => handshake,
+            None => return false,
+        };
+        *self.status.borrow_mut() = ConnectionStatus::Connecting;
+        self.listeners.borrow_mut().handshake.waiting = true;
+        trace!(client: self, "Sending the handshake");
+        // Ahead of the rate limit and anything queued, which waits for the reply
+        if let Err(e) = self.send_now(&handshake.hello) {
+            error!(client: self, "{}", e);
+        }
+        true
+    }
+    /// Check a received message against the handshake if the client is waiting for the reply,
+    /// returning whether the message was the reply
+    pub(crate) fn answer_handshake(&self, message: &Message) -> bool {
+        let handshake = {
+            let mut listeners = self.listeners.borrow_mut();
+            if !listeners.handshake.waiting {
+                return false;
+            }
+            listeners.handshake.waiting = false;
+            listeners.handshake.handshake.clone()
+        };
+        let result = match handshake {
+            Some(handshake) => (handshake.check)(message),
+            // Removed while waiting, so there's nothing to check against
+            None => Ok(()),
+        };
+        match result {
+            Ok(()) => {
+                trace!(client: self, "The server accepted the handshake");
+                *self.status.borrow_mut() = ConnectionStatus::Connected;
+                self.wake_status();
+                self.run_connection_handlers();
+            }
+            Err(reason) => {
+                trace!(client: self, "The server's handshake was rejected: {}", reason);
+                let handler = self
+                    .listeners
+                    .borrow()
+                    .handshake
+                    .on_version_mismatch
+                    .clone();
+                match handler {
+                    Some(f) => f.as_ref()(reason),
+                    None => error!(client: self, "Protocol version mismatch: {}", reason),
+                }
+                if let Err(e) =
+                    self.close_with(close_code::NORMAL, Some("Protocol version mismatch"))
+                {
+                    error!(client: self, "{}", e);
+                }
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,89 @@
+//! A snapshot of a connection's health, to attach to bug reports or send to telemetry.
+//!
+//! [`EventClient::health`] collects the status, uptime, round trip time, buffered and queued
+//! amounts, dropped messages and how long the connection has been quiet into one [`Health`],
+//! which can be serialized with the `serde` feature. The round trip time is estimated from the
+//! samples passed to [`EventClient::record_rtt`], since the WebSocket API can't measure it itself.
+//! ```
+//! client.add_on_message(Box::new(|client, message| {
+//!     if let Some(sent_at) = pong_sent_at(&message) {
+//!         client.record_rtt(now() - sent_at);
+//!     }
+//! }));
+//! // Later
+//! report.attach("connection", serde_json::to_string(&client.health())?);
+//! ```
+use crate::timer::monotonic_ms;
+use crate::{ConnectionStatus, EventClient};
+
+/// How much each new sample moves the round trip time estimate, as in TCP
+const RTT_GAIN: f64 = 0.125;
+
+/// A snapshot of a connection's health, see [`EventClient::health`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Health {
+    /// The URL the client is connected to
+    pub url: String,
+    /// The connection status
+    pub status: ConnectionStatus,
+    /// How long the connection has been open in milliseconds, or None if it isn't open
+    pub uptime_ms: Option<f64>,
+    /// The smoothed round trip time in milliseconds, or None if no samples have been recorded
+    pub rtt_ms: Option<f64>,
+    /// How many times the connection has been reopened. This is only counted by a
+    /// [`SocketManager`](crate::SocketManager), so it's 0 for a client on its own.
+    pub reconnects: u32,
+    /// The bytes sent but not yet transmitted to the network
+    pub buffered_amount: u32,
+    /// The messages waiting to be sent
+    pub queued_messages: usize,
+    /// The messages dropped instead of sent
+    pub dropped_messages: u64,
+    /// How long since the last message arrived, or since the connection opened, in milliseconds
+    pub idle_ms: f64,
+}
+
+impl EventClient {
+    /// Take a snapshot of the connection's health
+    /// ```
+    /// let health = client.health();
+    /// if health.idle_ms > 10_000.0 {
+    ///     warn!("No messages for {}ms, rtt {:?}", health.idle_ms, health.rtt_ms);
+    /// }
+    /// ```
+    pub fn health(&self) -> Health {
+        let status = self.status();
+        let uptime_ms = match self.listeners.borrow().watchdog.opened_at() {
+            Some(opened_at) if status.is_connected() => Some(monotonic_ms() - opened_at),
+            _ => None,
+        };
+        Health {
+            url: self.url.borrow().clone(),
+            status,
+            uptime_ms,
+            rtt_ms: self.rtt(),
+            reconnects: 0,
+            buffered_amount: self.buffered_amount(),
+            queued_messages: self.queued_messages(),
+            dropped_messages: self.stats().dropped(),
+            idle_ms: self.idle_for(),
+        }
+    }
+    /// Add a round trip time sample in milliseconds, like the time between sending a ping and
+    /// receiving its pong, to the estimate in [`rtt`](Self::rtt)
+    /// ```
+    /// client.record_rtt(pong_received_at - ping_sent_at);
+    /// ```
+    pub fn record_rtt(&self, sample_ms: f64) {
+        let rtt = &mut self.listeners.borrow_mut().rtt_ms;
+        *rtt = Some(match *rtt {
+            Some(rtt) => rtt + (sample_ms - rtt) * RTT_GAIN,
+            None => sample_ms,
+        });
+    }
+    /// The smoothed round trip time in milliseconds, or None if no samples have been recorded
+    pub fn rtt(&self) -> Option<f64> {
+        self.listeners.borrow().rtt_ms
+    }
+}
@@ -0,0 +1,63 @@
+//! Telling clients apart in logs and stats, for apps with several connections open.
+//!
+//! Every client gets a connection id when it's created, counting up from 1, and can be given a
+//! label. Both are included in the crate's log messages about the client, like
+//! `[#3 chat] Sending the handshake`, and in its [`Stats`](crate::Stats).
+//! ```
+//! let chat = EventClient::new("wss://chat.example.com")?;
+//! chat.set_label(Some("chat"));
+//! let game = EventClient::new("wss://game.example.com")?;
+//! game.set_label(Some("game"));
+//! info!("chat is #{}, game is #{}", chat.connection_id(), game.connection_id());
+//! ```
+use crate::EventClient;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A client's connection id and label
+#[derive(Default)]
+pub(crate) struct Identity {
+    id: u64,
+    label: Option<String>,
+}
+
+impl Identity {
+    /// An identity with the next connection id
+    pub(crate) fn next() -> Self {
+        Self {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            label: None,
+        }
+    }
+}
+
+impl EventClient {
+    /// The client's connection id, which is unique among the clients created by this program
+    /// ```
+    /// info!("Opened connection #{}", client.connection_id());
+    /// ```
+    pub fn connection_id(&self) -> u64 {
+        self.listeners.borrow().identity.id
+    }
+    /// Name the client in logs and stats, or set None to remove the label
+    /// ```
+    /// client.set_label(Some("presence"));
+    /// ```
+    pub fn set_label(&self, label: Option<&str>) {
+        self.listeners.borrow_mut().identity.label = label.map(str::to_string);
+    }
+    /// The client's label, see [`set_label`](Self::set_label)
+    pub fn label(&self) -> Option<String> {
+        self.listeners.borrow().identity.label.clone()
+    }
+    /// The connection id and label, formatted to start log messages with
+    #[cfg(feature = "log")]
+    pub(crate) fn log_tag(&self) -> String {
+        let identity = &self.listeners.borrow().identity;
+        match &identity.label {
+            Some(label) => format!("#{} {}", identity.id, label),
+            None => format!("#{}", identity.id),
+        }
+    }
+}
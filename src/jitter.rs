@@ -0,0 +1,114 @@
+//! Holding received messages back for a fixed playback delay, to smooth out network jitter.
+//!
+//! Games that interpolate remote entities want updates at a steady pace, not in the bursts the
+//! network delivers them in. With a [`Playback`] set, a [`PollingClient`] only returns messages
+//! once their playback time has passed. If the server stamps its messages with the time they were
+//! sent, the playback times follow those stamps, so messages come out as evenly spaced as they went in.
+//! ```
+//! client.set_playback(Some(Playback {
+//!     delay_ms: 100.0,
+//!     timestamp: Some(Box::new(|message| match message {
+//!         Message::Binary(data) if data.len() >= 8 => {
+//!             Some(f64::from_be_bytes(data[..8].try_into().unwrap()))
+//!         }
+//!         _ => None,
+//!     })),
+//! }));
+//! ```
+use crate::timer::now_ms;
+use crate::{Message, PollingClient};
+use std::collections::VecDeque;
+
+/// Reads the time a message was sent from the message itself, in milliseconds on the server's clock
+pub type MessageTimestamp = Box<dyn Fn(&Message) -> Option<f64>>;
+
+/// When received messages are returned by a [`PollingClient`], see [`PollingClient::set_playback`]
+pub struct Playback {
+    /// How long messages are held, in milliseconds. This should cover the jitter of the connection.
+    pub delay_ms: f64,
+    /// Reads the time each message was sent, or None to time messages by when they arrive.
+    ///
+    /// Messages without a timestamp are timed by when they arrive.
+    pub timestamp: Option<MessageTimestamp>,
+}
+
+/// Received messages waiting for their playback time
+pub(crate) struct JitterBuffer {
+    playback: Playback,
+    /// Messages and their playback times, which never decrease so messages stay in order
+    held: VecDeque<(f64, Message)>,
+    /// The smallest difference seen between a message's arrival and its timestamp,
+    /// which is the clock offset plus the fastest trip through the network
+    offset: Option<f64>,
+}
+
+impl JitterBuffer {
+    pub(crate) fn new(playback: Playback) -> Self {
+        Self {
+            playback,
+            held: VecDeque::new(),
+            offset: None,
+        }
+    }
+    /// Hold a message that just arrived
+    pub(crate) fn push(&mut self, message: Message) {
+        let now = now_ms();
+        let sent = self.playback.timestamp.as_ref().and_then(|f| f(&message));
+        let arrived = match sent {
+            Some(sent) => {
+                let offset = match self.offset {
+                    Some(offset) => offset.min(now - sent),
+                    None => now - sent,
+                };
+                self.offset = Some(offset);
+                sent + offset
+            }
+            None => now,
+        };
+        let mut play_at = arrived + self.playback.delay_ms;
+        if let Some((last, _)) = self.held.back() {
+            play_at = play_at.max(*last);
+        }
+        self.held.push_back((play_at, message));
+    }
+    /// Move the messages whose playback time has passed into `data`
+    pub(crate) fn release(&mut self, data: &mut VecDeque<Message>) {
+        let now = now_ms();
+        while let Some((play_at, _)) = self.held.front() {
+            if *play_at > now {
+                break;
+            }
+            if let Some((_, message)) = self.held.pop_front() {
+                data.push_back(message);
+            }
+        }
+    }
+    /// Move every held message into `data`
+    pub(crate) fn flush(self, data: &mut VecDeque<Message>) {
+        data.extend(self.held.into_iter().map(|(_, message)| message));
+    }
+}
+
+impl PollingClient {
+    /// Hold received messages back until their playback time, or set None to return them as soon as they arrive.
+    ///
+    /// Messages already held when this is changed are returned straight away.
+    /// ```
+    /// client.set_playback(Some(Playback {
+    ///     delay_ms: 100.0,
+    ///     timestamp: None,
+    /// }));
+    /// ```
+    pub fn set_playback(&self, playback: Option<Playback>) {
+        let old = self.jitter.replace(playback.map(JitterBuffer::new));
+        if let Some(old) = old {
+            old.flush(&mut self.data.borrow_mut());
+        }
+    }
+    /// Move the held messages whose playback time has passed to the received messages
+    pub(crate) fn release_held(&self) {
+        if let Some(jitter) = &mut *self.jitter.borrow_mut() {
+            jitter.release(&mut self.data.borrow_mut());
+        }
+    }
+}
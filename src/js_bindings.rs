@@ -0,0 +1,201 @@
+//! `#[wasm_bindgen]` classes for driving the clients from JavaScript, enabled with the
+//! `js-bindings` feature.
+//!
+//! The classes are exported as `EventClient` and `PollingClient`, so the same wasm module can be
+//! used from Rust and from JS. Messages are strings or `Uint8Array`s, and statuses are the strings
+//! `"connecting"`, `"connected"`, `"error"` and `"disconnected"`. Rust code can hand a client it
+//! created to JS by converting it into one of the classes.
+//! ```js
+//! import { EventClient } from "./pkg/app.js";
+//!
+//! const client = new EventClient("wss://ws.ifelse.io");
+//! client.onOpen(() => client.sendString("Hello!"));
+//! client.onMessage((message) => console.log(message));
+//! ```
+//!
+//! The generated `.d.ts` declares `Message`, `ConnectionStatus`, `CloseDetails` and `ErrorDetails`
+//! types, and the getters, `receive` and the callbacks are typed with them instead of `string`,
+//! `Array<any>` and `Function`.
+use crate::js_handlers::message_value;
+use crate::{ConnectionStatus, EventClient, PollingClient};
+use js_sys::{Array, Function};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &'static str = r#"
+/** A text or binary message */
+export type Message = string | Uint8Array;
+/** The state of a connection */
+export type ConnectionStatus = "connecting" | "connected" | "error" | "disconnected";
+/** Passed to the `onClose` callback */
+export interface CloseDetails {
+    code: number;
+    reason: string;
+    wasClean: boolean;
+}
+/** Passed to the `onError` callback */
+export interface ErrorDetails {
+    message: string;
+}
+"#;
+
+fn js_error(e: impl ToString) -> JsValue {
+    js_sys::Error::new(&e.to_string()).into()
+}
+
+fn status_name(status: &ConnectionStatus) -> &'static str {
+    match status {
+        ConnectionStatus::Connecting => "connecting",
+        ConnectionStatus::Connected => "connected",
+        ConnectionStatus::Error(_) => "error",
+        ConnectionStatus::Disconnected(_) => "disconnected",
+    }
+}
+
+/// An [`EventClient`] exported to JS as `EventClient`
+#[wasm_bindgen(js_name = EventClient)]
+pub struct JsEventClient {
+    client: EventClient,
+}
+
+#[wasm_bindgen(js_class = EventClient)]
+impl JsEventClient {
+    /// Connect to a WebSocket URL
+    #[wasm_bindgen(constructor)]
+    pub fn new(url: &str) -> Result<JsEventClient, JsValue> {
+        EventClient::new(url).map(Self::from).map_err(js_error)
+    }
+    /// The URL the client is connected to
+    #[wasm_bindgen(getter)]
+    pub fn url(&self) -> String {
+        self.client.url.borrow().clone()
+    }
+    /// The connection status, as a string
+    #[wasm_bindgen(getter, unchecked_return_type = "ConnectionStatus")]
+    pub fn status(&self) -> String {
+        status_name(&self.client.status()).to_string()
+    }
+    /// Whether messages can be sent
+    #[wasm_bindgen(js_name = isOpen)]
+    pub fn is_open(&self) -> bool {
+        self.client.is_open()
+    }
+    /// Send a text message
+    #[wasm_bindgen(js_name = sendString)]
+    pub fn send_string(&self, message: &str) -> Result<(), JsValue> {
+        self.client.send_string(message).map_err(js_error)
+    }
+    /// Send a binary message
+    #[wasm_bindgen(js_name = sendBinary)]
+    pub fn send_binary(&self, message: &[u8]) -> Result<(), JsValue> {
+        self.client.send_binary(message.to_vec()).map_err(js_error)
+    }
+    /// Close the connection, with an optional close code and reason
+    pub fn close(&self, code: Option<u16>, reason: Option<String>) -> Result<(), JsValue> {
+        match code {
+            Some(code) => self.client.close_with(code, reason.as_deref()),
+            None => self.client.close(),
+        }
+        .map_err(js_error)
+    }
+    /// Set the function called with each message, a string or a `Uint8Array`
+    #[wasm_bindgen(js_name = onMessage)]
+    pub fn on_message(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "((message: Message) => void) | undefined")]
+        f: Option<Function>,
+    ) {
+        self.client.set_on_message_js(f);
+    }
+    /// Set the function called when the connection opens
+    #[wasm_bindgen(js_name = onOpen)]
+    pub fn on_open(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "(() => void) | undefined")] f: Option<Function>,
+    ) {
+        self.client.set_on_connection_js(f);
+    }
+    /// Set the function called with `{code, reason, wasClean}` when the connection closes
+    #[wasm_bindgen(js_name = onClose)]
+    pub fn on_close(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "((details: CloseDetails) => void) | undefined")]
+        f: Option<Function>,
+    ) {
+        self.client.set_on_close_js(f);
+    }
+    /// Set the function called with `{message}` when the connection fails
+    #[wasm_bindgen(js_name = onError)]
+    pub fn on_error(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "((details: ErrorDetails) => void) | undefined")]
+        f: Option<Function>,
+    ) {
+        self.client.set_on_error_js(f);
+    }
+}
+
+impl From<EventClient> for JsEventClient {
+    fn from(client: EventClient) -> Self {
+        Self { client }
+    }
+}
+
+/// A [`PollingClient`] exported to JS as `PollingClient`
+#[wasm_bindgen(js_name = PollingClient)]
+pub struct JsPollingClient {
+    client: PollingClient,
+}
+
+#[wasm_bindgen(js_class = PollingClient)]
+impl JsPollingClient {
+    /// Connect to a WebSocket URL
+    #[wasm_bindgen(constructor)]
+    pub fn new(url: &str) -> Result<JsPollingClient, JsValue> {
+        PollingClient::new(url).map(Self::from).map_err(js_error)
+    }
+    /// The URL the client is connected to
+    #[wasm_bindgen(getter)]
+    pub fn url(&self) -> String {
+        self.client.url.clone()
+    }
+    /// The connection status, as a string
+    #[wasm_bindgen(getter, unchecked_return_type = "ConnectionStatus")]
+    pub fn status(&self) -> String {
+        status_name(&self.client.status()).to_string()
+    }
+    /// Whether messages can be sent
+    #[wasm_bindgen(js_name = isOpen)]
+    pub fn is_open(&self) -> bool {
+        self.client.is_open()
+    }
+    /// Take the messages received since the last call, as an array of strings and `Uint8Array`s
+    #[wasm_bindgen(unchecked_return_type = "Message[]")]
+    pub fn receive(&mut self) -> Array {
+        self.client.receive().iter().map(message_value).collect()
+    }
+    /// Send a text message
+    #[wasm_bindgen(js_name = sendString)]
+    pub fn send_string(&self, message: &str) -> Result<(), JsValue> {
+        self.client.send_string(message).map_err(js_error)
+    }
+    /// Send a binary message
+    #[wasm_bindgen(js_name = sendBinary)]
+    pub fn send_binary(&self, message: &[u8]) -> Result<(), JsValue> {
+        self.client.send_binary(message.to_vec()).map_err(js_error)
+    }
+    /// Close the connection, with an optional close code and reason
+    pub fn close(&self, code: Option<u16>, reason: Option<String>) -> Result<(), JsValue> {
+        match code {
+            Some(code) => self.client.close_with(code, reason.as_deref()),
+            None => self.client.close(),
+        }
+        .map_err(js_error)
+    }
+}
+
+impl From<PollingClient> for JsPollingClient {
+    fn from(client: PollingClient) -> Self {
+        Self { client }
+    }
+}
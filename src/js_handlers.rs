@@ -0,0 +1,141 @@
+//! Handlers written in JavaScript, for apps that embed the client in a larger JS codebase.
+//!
+//! Each `set_on_*_js` method registers a JS function that runs alongside the Rust handlers. The
+//! function gets plain values that can be structured-cloned, like into a worker with
+//! `postMessage`: messages are strings or `Uint8Array`s, and close and error details are plain
+//! objects. Exceptions thrown by the function are logged.
+//! ```
+//! /// The connection stays open until JS calls `free()` on this, or it's garbage collected
+//! #[wasm_bindgen]
+//! pub struct Connection {
+//!     client: EventClient,
+//! }
+//!
+//! #[wasm_bindgen]
+//! pub fn connect(url: &str, on_message: js_sys::Function) -> Result<Connection, JsValue> {
+//!     let mut client = EventClient::new(url).map_err(|e| JsValue::from(e.to_string()))?;
+//!     client.set_on_message_js(Some(on_message));
+//!     Ok(Connection { client })
+//! }
+//! ```
+use crate::{CloseInfo, Describe, EventClient, HandlerId, Message, WeakEventClient};
+use js_sys::{Function, Object, Reflect, Uint8Array};
+use wasm_bindgen::JsValue;
+
+/// The handlers registered for the JS functions, so setting a new function replaces the old one
+#[derive(Default)]
+pub(crate) struct JsHandlers {
+    on_message: Option<HandlerId>,
+    on_connection: Option<HandlerId>,
+    on_close: Option<HandlerId>,
+    on_error: Option<HandlerId>,
+}
+
+/// A message as a string or a `Uint8Array`
+pub(crate) fn message_value(message: &Message) -> JsValue {
+    match message {
+        Message::Text(text) => JsValue::from_str(text),
+        Message::Binary(data) => Uint8Array::from(&data[..]).into(),
+    }
+}
+
+/// A plain object with the close event's details, named like the browser's `CloseEvent`
+pub(crate) fn close_value(info: &CloseInfo) -> JsValue {
+    let value = Object::new();
+    let fields: [(&str, JsValue); 3] = [
+        ("code", info.code.into()),
+        ("reason", info.reason.as_str().into()),
+        ("wasClean", info.was_clean.into()),
+    ];
+    for (name, field) in fields.iter() {
+        // Setting a property on a new plain object can't fail
+        Reflect::set(&value, &(*name).into(), field).ok();
+    }
+    value.into()
+}
+
+/// Call one of `client`'s JS handlers, logging what it throws
+fn call(client: &EventClient, f: &Function, args: &[JsValue]) {
+    let result = match args {
+        [] => f.call0(&JsValue::NULL),
+        [arg] => f.call1(&JsValue::NULL, arg),
+        _ => unreachable!("JS handlers take at most one argument"),
+    };
+    if let Err(e) = result {
+        error!(client: client, "A JS handler threw: {}", e.describe());
+    }
+}
+
+impl EventClient {
+    /// Store the handler registered for a JS function in `slot`, removing the one it replaces
+    fn replace_js_handler(
+        &self,
+        slot: fn(&mut JsHandlers) -> &mut Option<HandlerId>,
+        id: Option<HandlerId>,
+    ) {
+        let old = std::mem::replace(slot(&mut self.listeners.borrow_mut().js_handlers), id);
+        if let Some(old) = old {
+            self.remove_handler(old);
+        }
+    }
+    /// Set a JS function to call with each message, a string or a `Uint8Array`, overwriting the
+    /// previous function. It runs alongside the Rust on_message handlers.
+    /// You can set [None](std::option) to remove it.
+    /// ```
+    /// client.set_on_message_js(Some(on_message));
+    /// ```
+    pub fn set_on_message_js(&mut self, f: Option<Function>) {
+        let id = f.map(|f| {
+            self.add_on_message(Box::new(move |client, message| {
+                call(client, &f, &[message_value(&message)])
+            }))
+        });
+        self.replace_js_handler(|handlers| &mut handlers.on_message, id);
+    }
+    /// Set a JS function to call with no arguments when the connection opens, overwriting the
+    /// previous function. It runs alongside the Rust on_connection handlers.
+    /// You can set [None](std::option) to remove it.
+    /// ```
+    /// client.set_on_connection_js(Some(on_open));
+    /// ```
+    pub fn set_on_connection_js(&mut self, f: Option<Function>) {
+        let id = f.map(|f| self.add_on_connection(Box::new(move |client| call(client, &f, &[]))));
+        self.replace_js_handler(|handlers| &mut handlers.on_connection, id);
+    }
+    /// Set a JS function to call with `{code, reason, wasClean}` when the connection closes,
+    /// overwriting the previous function. It runs alongside the Rust on_close handlers.
+    /// You can set [None](std::option) to remove it.
+    /// ```
+    /// client.set_on_close_js(Some(on_close));
+    /// ```
+    pub fn set_on_close_js(&mut self, f: Option<Function>) {
+        let id = f.map(|f| {
+            let client = WeakEventClient::new(self);
+            self.add_on_close(Box::new(move |e| {
+                if let Some(client) = client.upgrade() {
+                    call(&client, &f, &[close_value(&CloseInfo::from(&e))]);
+                }
+            }))
+        });
+        self.replace_js_handler(|handlers| &mut handlers.on_close, id);
+    }
+    /// Set a JS function to call with `{message}` when the connection fails, overwriting the
+    /// previous function. It runs alongside the Rust on_error handlers.
+    /// You can set [None](std::option) to remove it.
+    /// ```
+    /// client.set_on_error_js(Some(on_error));
+    /// ```
+    pub fn set_on_error_js(&mut self, f: Option<Function>) {
+        let id = f.map(|f| {
+            let client = WeakEventClient::new(self);
+            self.add_on_error(Box::new(move |e| {
+                let value = Object::new();
+                Reflect::set(&value, &"message".into(), &e.message().into()).ok();
+                if let Some(client) = client.upgrade() {
+                    call(&client, &f, &[value.into()]);
+                }
+            }))
+        });
+        self.replace_js_handler(|handlers| &mut handlers.on_error, id);
+    }
+}
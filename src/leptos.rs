@@ -0,0 +1,100 @@
+//! Leptos integration, enabled with the `leptos` feature.
+//!
+//! [`use_websocket`] opens a connection owned by the current reactive owner (usually a component)
+//! and exposes it as signals. This uses `reactive_graph`, which Leptos re-exports its signals from,
+//! so the signals work anywhere Leptos expects a `ReadSignal`.
+//! ```
+//! #[component]
+//! fn Chat() -> impl IntoView {
+//!     let socket = use_websocket("wss://ws.ifelse.io");
+//!     view! {
+//!         <p>{move || format!("{:?}", socket.status.get())}</p>
+//!         <p>{move || format!("{:?}", socket.message.get())}</p>
+//!         <button on:click=move |_| { socket.send(Message::Text("Hello, World!".into())).ok(); }>
+//!             "Send"
+//!         </button>
+//!     }
+//! }
+//! ```
+use crate::{CloseInfo, ConnectionStatus, EventClient, Message, WebSocketError};
+use reactive_graph::owner::{on_cleanup, LocalStorage, StoredValue};
+use reactive_graph::signal::{signal, ReadSignal};
+use reactive_graph::traits::{Set, WithValue};
+
+/// A websocket connection exposed as signals.
+///
+/// This is `Copy`, so it can be moved into as many closures as needed.
+#[derive(Clone, Copy)]
+pub struct UseWebSocket {
+    /// The current connection status
+    pub status: ReadSignal<ConnectionStatus>,
+    /// The most recent message received from the server.
+    /// Effects run after the current task, so only the last of several messages received at once is seen.
+    pub message: ReadSignal<Option<Message>>,
+    client: StoredValue<Option<EventClient>, LocalStorage>,
+}
+
+impl UseWebSocket {
+    /// Send a message to the server
+    pub fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        self.client
+            .try_with_value(|client| match client {
+                Some(client) => match message {
+                    Message::Text(text) => client.send_string(&text),
+                    Message::Binary(data) => client.send_binary(data.into()),
+                },
+                None => Err(WebSocketError::SendError("Not connected".into())),
+            })
+            .unwrap_or_else(|| Err(WebSocketError::SendError("Connection was disposed".into())))
+    }
+    /// Close the connection
+    pub fn close(&self) -> Result<(), WebSocketError> {
+        self.client
+            .try_with_value(|client| match client {
+                Some(client) => client.close(),
+                None => Ok(()),
+            })
+            .unwrap_or(Ok(()))
+    }
+}
+
+/// Connect to a WebSocket URL, closing the connection when the current reactive owner is cleaned up.
+///
+/// The signals are set directly from the socket's handlers, which is safe to do outside of
+/// the reactive owner: effects and views reading them are scheduled as usual.
+pub fn use_websocket(url: &str) -> UseWebSocket {
+    let (status, set_status) = signal(ConnectionStatus::Connecting);
+    let (message, set_message) = signal(None);
+    let client = match EventClient::new(url) {
+        Ok(mut client) => {
+            client.set_on_connection(Some(Box::new(move |_| {
+                set_status.set(ConnectionStatus::Connected);
+            })));
+            client.set_on_error(Some(Box::new(move |_| {
+                set_status.set(ConnectionStatus::Error(CloseInfo::abnormal()));
+            })));
+            client.set_on_close(Some(Box::new(move |e| {
+                set_status.set(ConnectionStatus::closed(CloseInfo::from(&e)));
+            })));
+            client.set_on_message(Some(Box::new(move |_, m| {
+                set_message.set(Some(m));
+            })));
+            Some(client)
+        }
+        Err(e) => {
+            error!("{}", e);
+            set_status.set(ConnectionStatus::Error(CloseInfo::abnormal()));
+            None
+        }
+    };
+    let socket = UseWebSocket {
+        status,
+        message,
+        client: StoredValue::new_local(client),
+    };
+    // Cleanups run before the owner disposes of the stored client
+    on_cleanup(move || {
+        socket.close().ok();
+    });
+    socket
+}
@@ -1,12 +1,20 @@
 //! This crate offers 2 (wasm-only) websocket clients.
+//! The crate also compiles on other targets so shared code can type-check, but connecting will always fail there
+//! unless the `native` feature is enabled, which runs both clients on top of tokio-tungstenite instead.
 //! The first client offered is the [`EventClient`]. This client is event based and gives you the most control.
 //! ```
 //! use console_error_panic_hook;
 //! use console_log;
 //! use log::{error, info, Level};
+//! use std::cell::RefCell;
 //! use std::panic;
 //! use wasm_sockets::{self, WebSocketError};
 //!
+//! thread_local! {
+//!     // The app's state, which keeps the connection open for the life of the page
+//!     static CLIENT: RefCell<Option<wasm_sockets::EventClient>> = const { RefCell::new(None) };
+//! }
+//!
 //! fn main() -> Result<(), WebSocketError> {
 //!     panic::set_hook(Box::new(console_error_panic_hook::hook));
 //!     // console_log and log macros are used instead of println!
@@ -34,6 +42,8 @@
 //!     )));
 //!
 //!     info!("Connection successfully created");
+//!     // Dropping the client closes the connection, so keep it in the app's state
+//!     CLIENT.with(|cell| *cell.borrow_mut() = Some(client));
 //!     Ok(())
 //! }
 //! ```
@@ -46,7 +56,6 @@
 //! use std::cell::RefCell;
 //! use std::panic;
 //! use std::rc::Rc;
-//! #[cfg(target_arch = "wasm32")]
 //! use wasm_bindgen::prelude::*;
 //! use wasm_sockets::{self, ConnectionStatus, WebSocketError};
 //!
@@ -64,7 +73,7 @@
 //!     )?));
 //!
 //!     let f = Closure::wrap(Box::new(move || {
-//!         if client.borrow().status() == ConnectionStatus::Connected {
+//!         if client.borrow().is_open() {
 //!             info!("Sending message");
 //!             client.borrow().send_string("Hello, World!").unwrap();
 //!         }
@@ -84,42 +93,432 @@
 //!     fn setInterval(closure: &Closure<dyn Fn()>, time: u32) -> i32;
 //! }
 //! ```
-#[cfg(test)]
+// Declared first, so the logging macros can be used in every other module
+#[macro_use]
+mod logging;
+
+mod async_handler;
+mod backpressure;
+#[cfg(feature = "base64")]
+mod base64_bridge;
+#[cfg(feature = "bevy")]
+pub mod bevy;
+#[cfg(all(
+    feature = "blob",
+    not(all(feature = "native", not(target_arch = "wasm32")))
+))]
+mod blob;
+mod builder;
+mod chunked;
+pub mod close_code;
+mod coalesce;
+#[cfg(any(feature = "gloo-net", feature = "tungstenite"))]
+mod convert;
+mod ctx;
+#[cfg(feature = "decompress")]
+mod decompress;
+#[cfg(feature = "dioxus")]
+pub mod dioxus;
+mod early;
+#[cfg(feature = "egui")]
+pub mod egui;
+#[cfg(feature = "serde")]
+mod envelope;
+mod framing;
+mod handshake;
+mod health;
+mod identity;
+mod jitter;
+#[cfg(all(
+    feature = "js-bindings",
+    not(all(feature = "native", not(target_arch = "wasm32")))
+))]
+mod js_bindings;
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+mod js_handlers;
+#[cfg(feature = "leptos")]
+pub mod leptos;
+mod lines;
+mod manager;
+mod memory;
+mod message_stream;
+#[cfg(all(feature = "miniquad", target_arch = "wasm32"))]
+pub mod miniquad;
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+mod native;
+#[cfg(all(test, feature = "native", not(target_arch = "wasm32")))]
+mod native_tests;
+#[cfg(feature = "serde")]
+mod negotiate;
+mod outbound;
+pub mod prelude;
+mod protocol_error;
+mod race;
+mod remote;
+mod replay;
+mod schema;
+#[cfg(feature = "send-wrapper")]
+mod shared;
+mod simulate;
+mod size_limit;
+mod stats;
+mod status_stream;
+#[cfg(feature = "sycamore")]
+pub mod sycamore;
+mod sync;
+pub mod testing;
+#[cfg(all(test, target_arch = "wasm32"))]
 mod tests;
-use log::{error, trace};
+mod text;
+mod timer;
+#[cfg(feature = "serde")]
+mod typed;
+mod watchdog;
+#[cfg(feature = "yew")]
+pub mod yew;
 use std::cell::RefCell;
-use std::rc::Rc;
-use thiserror::Error;
-#[cfg(target_arch = "wasm32")]
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
-#[cfg(target_arch = "wasm32")]
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
 use wasm_bindgen::JsCast;
-use web_sys::{CloseEvent, ErrorEvent, MessageEvent, WebSocket};
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+use web_sys::{MessageEvent, WebSocket};
+
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+pub use native::{CloseEvent, ErrorEvent};
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub use web_sys::{CloseEvent, ErrorEvent};
 
-#[cfg(not(target_arch = "wasm32"))]
-compile_error!("wasm-sockets can only compile to WASM targets");
+pub use async_handler::AsyncMessageHandler;
+use async_handler::AsyncMessages;
+use backpressure::Backpressure;
+pub use backpressure::{BackpressureHandler, BackpressureLimits, BufferReport};
+#[cfg(all(
+    feature = "blob",
+    not(all(feature = "native", not(target_arch = "wasm32")))
+))]
+pub use blob::{BlobChunk, BlobChunkHandler, BLOB_CHUNK_SIZE};
+pub use builder::{BinaryType, EventClientBuilder};
+pub use bytes::Bytes;
+pub use chunked::{Progress, ProgressHandler, Reassembler, CHUNK_HEADER_LEN};
+pub use close_code::CloseCode;
+pub use coalesce::CoalesceKey;
+pub use ctx::{Ctx, CtxConnectionHandler, CtxMessageHandler};
+#[cfg(feature = "decompress")]
+pub use decompress::Decompression;
+#[cfg(feature = "serde")]
+pub use envelope::Envelope;
+pub use framing::{Endianness, FramedReader, FramedWriter, Framing, FramingError, PrefixSize};
+use handshake::HandshakeState;
+pub use handshake::{Handshake, HandshakeCheck, VersionMismatchHandler};
+pub use health::Health;
+use identity::Identity;
+use jitter::JitterBuffer;
+pub use jitter::{MessageTimestamp, Playback};
+#[cfg(all(
+    feature = "js-bindings",
+    not(all(feature = "native", not(target_arch = "wasm32")))
+))]
+pub use js_bindings::{JsEventClient, JsPollingClient};
+pub use manager::{
+    BroadcastError, ManagedEvent, ManagedEventHandler, Reconnect, ReconnectDecision,
+    ReconnectPolicy, RejectedHandler, RetryHintParser, SocketManager,
+};
+#[cfg(feature = "serde")]
+pub use negotiate::{CodecList, NegotiatedPollingClient, ProtocolCodecs};
+use outbound::Outbound;
+pub use outbound::{Batching, FlushTick, OverLimit, Priority, RateLimit, SendOutcome, SendTicket};
+pub use protocol_error::{ProtocolError, ProtocolErrorHandler};
+pub use race::RaceWinner;
+pub use remote::RemoteClient;
+pub use replay::{ConnectionEvent, RecordedEvent, Recorder, Recording, ReplayClient};
+pub use schema::{SchemaFallback, SchemaHandler, SchemaRegistry};
+#[cfg(feature = "send-wrapper")]
+pub use shared::{SharedEventClient, SharedPollingClient};
+pub use simulate::{NetworkConditions, SimulatedClient};
+pub use size_limit::{Oversize, SizeLimits};
+pub use stats::{DropReason, DroppedHandler, SizeHistogram, Stats};
+pub use sync::{SnapshotSync, SyncError, DEFAULT_SYNC_HISTORY};
+pub use text::{TextChunk, TextChunkHandler, TextPolicy, TEXT_CHUNK_SIZE};
+#[cfg(feature = "json")]
+pub use typed::Json;
+#[cfg(feature = "serde")]
+pub use typed::{
+    __variant_handler, decoding_handler, Codec, DecodeError, EncodeError, TypedPollingClient,
+};
+pub use watchdog::StaleHandler;
+use watchdog::Watchdog;
+
+/// The socket type backing an [`EventClient`]
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+type Socket = native::Connection;
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+type Socket = WebSocket;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConnectionStatus {
     /// Connecting to a server
     Connecting,
     /// Connected to a server
     Connected,
-    /// Disconnected from a server due to an error
-    Error,
-    /// Disconnected from a server without an error
-    Disconnected,
+    /// Disconnected from a server due to an error, or without a closing handshake.
+    /// Until the close arrives after an error, this holds [`CloseInfo::abnormal`].
+    Error(CloseInfo),
+    /// Disconnected from a server with a closing handshake
+    Disconnected(CloseInfo),
+}
+
+impl ConnectionStatus {
+    /// Whether the client is still connecting
+    pub fn is_connecting(&self) -> bool {
+        *self == ConnectionStatus::Connecting
+    }
+    /// Whether the client is connected, so messages can be sent
+    pub fn is_connected(&self) -> bool {
+        *self == ConnectionStatus::Connected
+    }
+    /// Whether the connection is over, either with or without an error.
+    /// A client never leaves these states, so a new one has to be created to reconnect.
+    pub fn is_terminal(&self) -> bool {
+        self.close_info().is_some()
+    }
+    /// Whether the connection ended with an error, rather than being closed by either side
+    pub fn is_error(&self) -> bool {
+        matches!(self, ConnectionStatus::Error(_))
+    }
+    /// The details of how the connection closed, once it has
+    /// ```
+    /// if let Some(info) = client.status().close_info() {
+    ///     info!("Closed with code {}: {}", info.code, info.reason);
+    /// }
+    /// ```
+    pub fn close_info(&self) -> Option<&CloseInfo> {
+        match self {
+            ConnectionStatus::Error(info) | ConnectionStatus::Disconnected(info) => Some(info),
+            _ => None,
+        }
+    }
+    /// The status after a connection closes, which is an error unless it closed cleanly
+    pub(crate) fn closed(info: CloseInfo) -> Self {
+        if info.was_clean {
+            ConnectionStatus::Disconnected(info)
+        } else {
+            ConnectionStatus::Error(info)
+        }
+    }
 }
 
 /// Message is a representation of a websocket message that can be sent or recieved
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Message {
-    /// A text message
-    Text(String),
-    /// A binary message
-    Binary(Vec<u8>),
+    /// A text message.
+    /// This is reference counted like [`Message::Binary`], so cloning a message doesn't copy the text.
+    Text(Arc<str>),
+    /// A binary message.
+    /// [`Bytes`] is reference counted, so cloning a message doesn't copy its contents.
+    Binary(Bytes),
 }
-#[cfg(target_arch = "wasm32")]
+
+/// The details of a closed connection
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CloseInfo {
+    /// The close code sent by the server
+    pub code: u16,
+    /// The close reason sent by the server
+    pub reason: String,
+    /// Whether the connection was closed with a closing handshake
+    pub was_clean: bool,
+}
+
+impl CloseInfo {
+    /// The details browsers report for a connection that failed without a close frame,
+    /// with code [`close_code::ABNORMAL`]
+    pub fn abnormal() -> Self {
+        Self {
+            code: close_code::ABNORMAL,
+            reason: String::new(),
+            was_clean: false,
+        }
+    }
+    /// The close code, sorted into the registered codes and the private ranges
+    /// ```
+    /// if info.close_code().is_retryable() {
+    ///     reconnect();
+    /// }
+    /// ```
+    pub fn close_code(&self) -> CloseCode {
+        self.code.into()
+    }
+    /// How long the server asked the client to wait before reconnecting, in milliseconds, if the
+    /// reason has a hint like `retry-after: 30` or `retry-after=500ms`.
+    /// The time is in seconds unless it ends in `ms`.
+    /// ```
+    /// if let Some(wait) = info.retry_after_ms() {
+    ///     show_banner(&format!("Server busy, retrying in {}s", wait / 1000.0));
+    /// }
+    /// ```
+    pub fn retry_after_ms(&self) -> Option<f64> {
+        // Lowercasing ASCII keeps the byte offsets the same
+        let reason = self.reason.to_ascii_lowercase();
+        let start = reason.find("retry-after")? + "retry-after".len();
+        let rest = reason[start..].trim_start_matches([':', '=', ' ']);
+        let end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let time: f64 = rest[..end].parse().ok()?;
+        if rest[end..].trim_start().starts_with("ms") {
+            Some(time)
+        } else {
+            Some(time * 1000.0)
+        }
+    }
+}
+
+impl From<&CloseEvent> for CloseInfo {
+    fn from(e: &CloseEvent) -> Self {
+        Self {
+            code: e.code(),
+            reason: e.reason(),
+            was_clean: e.was_clean(),
+        }
+    }
+}
+
+/// A handler bound to the on_error event
+pub type ErrorHandler = Box<dyn Fn(ErrorEvent)>;
+/// A handler bound to the on_connection event
+pub type ConnectionHandler = Box<dyn Fn(&EventClient)>;
+/// A handler bound to the on_message event
+pub type MessageHandler = Box<dyn Fn(&EventClient, Message)>;
+/// A handler bound to the on_close event
+pub type CloseHandler = Box<dyn Fn(CloseEvent)>;
+/// A handler that only runs for the next on_connection event
+pub type OnceConnectionHandler = Box<dyn FnOnce(&EventClient)>;
+/// A handler that only runs for the next on_message event
+pub type OnceMessageHandler = Box<dyn FnOnce(&EventClient, Message)>;
+/// A predicate choosing the messages a subscription receives, see [`EventClient::subscribe_filtered`]
+pub type MessageFilter = Box<dyn Fn(&Message) -> bool>;
+/// A handler bound to the raw message event of the underlying WebSocket
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub type RawMessageHandler = Box<dyn Fn(MessageEvent)>;
+/// A handler added to the underlying WebSocket as an event listener
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub type RawEventHandler = Box<dyn Fn(web_sys::Event)>;
+/// A handler that receives binary messages as ArrayBuffers, instead of them being copied into [`Message::Binary`]
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub type RawBinaryHandler = Box<dyn Fn(&EventClient, js_sys::ArrayBuffer)>;
+
+/// A single handler for every event of a connection, attached with [`WsClient::set_handler`].
+///
+/// Unlike the separate handler closures, the methods take `&mut self`, so state shared
+/// between events can live in plain fields instead of `Rc<RefCell<>>`s.
+/// Every method does nothing by default, so only the events you need have to be implemented.
+/// ```
+/// struct Chat {
+///     received: usize,
+/// }
+///
+/// impl WebSocketHandler for Chat {
+///     fn on_open(&mut self, client: &EventClient) {
+///         client.send_string("Hello, World!").unwrap();
+///     }
+///     fn on_message(&mut self, _client: &EventClient, message: Message) {
+///         self.received += 1;
+///         info!("Message #{}: {:#?}", self.received, message);
+///     }
+/// }
+///
+/// let chat = client.set_handler(Chat { received: 0 });
+/// ```
+pub trait WebSocketHandler {
+    /// Called when the connection opens
+    fn on_open(&mut self, _client: &EventClient) {}
+    /// Called when a message is received
+    fn on_message(&mut self, _client: &EventClient, _message: Message) {}
+    /// Called when the connection closes
+    fn on_close(&mut self, _event: CloseEvent) {}
+    /// Called when the connection fails
+    fn on_error(&mut self, _event: ErrorEvent) {}
+}
+
+/// The interface shared by every websocket client in this crate.
+///
+/// Code that only needs to send messages, check the connection status, or register handlers
+/// can accept any `impl WsClient` (or `&mut dyn WsClient`) instead of a concrete client type,
+/// so it works with [`EventClient`], [`PollingClient`], and test doubles alike.
+/// ```
+/// fn greet(client: &impl WsClient) {
+///     if client.status().is_connected() {
+///         client.send_string("Hello!").unwrap();
+///     }
+/// }
+/// ```
+pub trait WsClient {
+    /// Get the client's current connection status
+    fn status(&self) -> ConnectionStatus;
+    /// Send a text message to the server
+    fn send_string(&self, message: &str) -> Result<(), WebSocketError>;
+    /// Send a binary message to the server
+    fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError>;
+    /// Close the connection
+    fn close(&self) -> Result<(), WebSocketError>;
+    /// Close the connection with a custom close code and, optionally, a reason string
+    fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), WebSocketError>;
+    /// Set an on_error event handler, overwriting the previous handler
+    fn set_on_error(&mut self, f: Option<ErrorHandler>);
+    /// Set an on_connection event handler, overwriting the previous handler.
+    /// If the client is already connected, it's run right after the current task instead.
+    fn set_on_connection(&mut self, f: Option<ConnectionHandler>);
+    /// Set an on_message event handler, overwriting the previous handler
+    fn set_on_message(&mut self, f: Option<MessageHandler>);
+    /// Set an on_close event handler, overwriting the previous handler
+    fn set_on_close(&mut self, f: Option<CloseHandler>);
+    /// The [`EventClient`] underneath, if there is one, whose connection tags log messages
+    fn event_client(&self) -> Option<EventClient> {
+        None
+    }
+    /// Set all four event handlers to call a [`WebSocketHandler`], overwriting the previous handlers.
+    ///
+    /// The handler is returned so its state can still be read after it's attached.
+    /// ```
+    /// let chat = client.set_handler(Chat { received: 0 });
+    /// info!("Received {} messages", chat.borrow().received);
+    /// ```
+    fn set_handler<H: WebSocketHandler + 'static>(&mut self, handler: H) -> Rc<RefCell<H>>
+    where
+        Self: Sized,
+    {
+        let handler = Rc::new(RefCell::new(handler));
+        let handler_ref = handler.clone();
+        self.set_on_connection(Some(Box::new(move |client| {
+            handler_ref.borrow_mut().on_open(client);
+        })));
+        let handler_ref = handler.clone();
+        self.set_on_message(Some(Box::new(move |client, message| {
+            handler_ref.borrow_mut().on_message(client, message);
+        })));
+        let handler_ref = handler.clone();
+        self.set_on_close(Some(Box::new(move |e| {
+            handler_ref.borrow_mut().on_close(e);
+        })));
+        let handler_ref = handler.clone();
+        self.set_on_error(Some(Box::new(move |e| {
+            handler_ref.borrow_mut().on_error(e);
+        })));
+        handler
+    }
+}
+
+/// A message adapter added to a [`PollingClient`], which returns None to drop the message
+type MessageTransform = Box<dyn Fn(Message) -> Option<Message>>;
+
 pub struct PollingClient {
     /// The URL this client is connected to
     pub url: String,
@@ -127,10 +526,20 @@ pub struct PollingClient {
     pub event_client: EventClient,
     /// The current connection status
     pub status: Rc<RefCell<ConnectionStatus>>,
-    data: Rc<RefCell<Vec<Message>>>,
+    data: Rc<RefCell<VecDeque<Message>>>,
+    /// Messages held back until their playback time, see [`PollingClient::set_playback`]
+    jitter: Rc<RefCell<Option<JitterBuffer>>>,
+    coalesce_key: RefCell<Option<CoalesceKey>>,
+    /// Applied to each message in order before it's queued, see [`PollingClient::filter_map_messages`]
+    transforms: Rc<RefCell<Vec<MessageTransform>>>,
+    /// Limits on the messages waiting to be received, see [`PollingClient::set_backpressure_limits`]
+    backpressure: Rc<RefCell<Backpressure>>,
+    on_error: Rc<RefCell<Option<ErrorHandler>>>,
+    on_connection: Rc<RefCell<Option<Rc<ConnectionHandler>>>>,
+    on_message: Rc<RefCell<Option<MessageHandler>>>,
+    on_close: Rc<RefCell<Option<CloseHandler>>>,
 }
-#[cfg(target_arch = "wasm32")]
-// TODO: Replace unwraps and JsValue with custom error type
+// TODO: Replace unwraps with custom error type
 impl PollingClient {
     /// Create a new PollingClient and connect to a WebSocket URL
     ///
@@ -139,31 +548,93 @@ impl PollingClient {
     /// PollingClient::new("wss://ws.ifelse.io")?;
     /// ```
     pub fn new(url: &str) -> Result<Self, WebSocketError> {
+        Self::with_protocols(url, &[])
+    }
+    /// Create a new PollingClient and connect to a WebSocket URL, offering the server `protocols`
+    /// as subprotocols, see [`EventClient::with_protocols`]
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// ```
+    /// PollingClient::with_protocols("wss://game.example.com", &["game.v2", "game.v1"])?;
+    /// ```
+    pub fn with_protocols(url: &str, protocols: &[&str]) -> Result<Self, WebSocketError> {
         // Create connection
-        let mut client = EventClient::new(url)?;
-        let data = Rc::new(RefCell::new(vec![]));
+        let mut client = EventClient::with_protocols(url, protocols)?;
+        let data = Rc::new(RefCell::new(VecDeque::new()));
         let data_ref = data.clone();
+        let jitter: Rc<RefCell<Option<JitterBuffer>>> = Rc::new(RefCell::new(None));
+        let jitter_ref = jitter.clone();
+        let transforms: Rc<RefCell<Vec<MessageTransform>>> = Rc::new(RefCell::new(vec![]));
+        let transforms_ref = transforms.clone();
+        let backpressure: Rc<RefCell<Backpressure>> = Rc::default();
+        let backpressure_ref = backpressure.clone();
         let status = Rc::new(RefCell::new(ConnectionStatus::Connecting));
         let status_ref = status.clone();
+        let data_meter = data.clone();
+        client.listeners.borrow_mut().inbound_meter = Some(Rc::new(move || {
+            data_meter.borrow().iter().map(outbound::message_len).sum()
+        }));
+
+        // User handlers registered through WsClient run after the client's own bookkeeping
+        let on_error: Rc<RefCell<Option<ErrorHandler>>> = Rc::new(RefCell::new(None));
+        let on_connection: Rc<RefCell<Option<Rc<ConnectionHandler>>>> = Rc::new(RefCell::new(None));
+        let on_message: Rc<RefCell<Option<MessageHandler>>> = Rc::new(RefCell::new(None));
+        let on_close: Rc<RefCell<Option<CloseHandler>>> = Rc::new(RefCell::new(None));
 
-        client.set_on_connection(Some(Box::new(move |_client| {
+        let on_connection_ref = on_connection.clone();
+        client.set_on_connection(Some(Box::new(move |client| {
             *status_ref.borrow_mut() = ConnectionStatus::Connected;
+            let f = on_connection_ref.borrow().clone();
+            if let Some(f) = f {
+                f.as_ref()(client);
+            }
         })));
 
         let status_ref = status.clone();
+        let on_error_ref = on_error.clone();
 
-        client.set_on_error(Some(Box::new(move |_e| {
-            *status_ref.borrow_mut() = ConnectionStatus::Error;
+        client.set_on_error(Some(Box::new(move |e| {
+            *status_ref.borrow_mut() = ConnectionStatus::Error(CloseInfo::abnormal());
+            if let Some(f) = &*on_error_ref.borrow() {
+                f.as_ref()(e);
+            }
         })));
 
         let status_ref = status.clone();
+        let on_close_ref = on_close.clone();
 
-        client.set_on_close(Some(Box::new(move |_evt| {
-            *status_ref.borrow_mut() = ConnectionStatus::Disconnected;
+        client.set_on_close(Some(Box::new(move |evt| {
+            *status_ref.borrow_mut() = ConnectionStatus::closed(CloseInfo::from(&evt));
+            if let Some(f) = &*on_close_ref.borrow() {
+                f.as_ref()(evt);
+            }
         })));
 
-        client.set_on_message(Some(Box::new(move |_client: &EventClient, m: Message| {
-            data_ref.borrow_mut().push(m);
+        let on_message_ref = on_message.clone();
+        client.set_on_message(Some(Box::new(move |client: &EventClient, m: Message| {
+            let mut m = Some(m);
+            for transform in transforms_ref.borrow().iter() {
+                m = m.and_then(transform);
+            }
+            let m = match m {
+                Some(m) => m,
+                None => return,
+            };
+            match &mut *jitter_ref.borrow_mut() {
+                Some(jitter) => jitter.push(m.clone()),
+                None => {
+                    let other_bytes = client.queued_bytes() + client.held_bytes();
+                    let mut data = data_ref.borrow_mut();
+                    data.push_back(m.clone());
+                    let discarded = memory::trim(&mut data, other_bytes, client.memory_limit());
+                    drop(data);
+                    client.discarded_inbound(discarded);
+                    backpressure::queued(client, &backpressure_ref, &data_ref);
+                }
+            }
+            if let Some(f) = &*on_message_ref.borrow() {
+                f.as_ref()(client, m);
+            }
         })));
 
         Ok(Self {
@@ -171,16 +642,109 @@ impl PollingClient {
             event_client: client,
             status,
             data,
+            jitter,
+            coalesce_key: RefCell::new(None),
+            transforms,
+            backpressure,
+            on_error,
+            on_connection,
+            on_message,
+            on_close,
         })
     }
+    /// Transform every message as it arrives, before it reaches [`receive`](Self::receive) and the
+    /// on_message handler.
+    ///
+    /// Adapters run in the order they were added, so each gets what the previous one returned.
+    /// ```
+    /// let client = PollingClient::new("wss://ws.ifelse.io")?
+    ///     .map_messages(|message| decrypt(&key, message));
+    /// ```
+    pub fn map_messages(self, f: impl Fn(Message) -> Message + 'static) -> Self {
+        self.filter_map_messages(move |message| Some(f(message)))
+    }
+    /// Drop the messages `f` returns false for as they arrive, so they never reach
+    /// [`receive`](Self::receive) or the on_message handler
+    /// ```
+    /// let client = PollingClient::new("wss://ws.ifelse.io")?
+    ///     .filter_messages(|message| matches!(message, Message::Binary(_)));
+    /// ```
+    pub fn filter_messages(self, f: impl Fn(&Message) -> bool + 'static) -> Self {
+        self.filter_map_messages(move |message| Some(message).filter(&f))
+    }
+    /// Transform every message as it arrives, or drop it by returning None, see
+    /// [`map_messages`](Self::map_messages)
+    /// ```
+    /// let client = PollingClient::new("wss://ws.ifelse.io")?.filter_map_messages(|message| match message {
+    ///     Message::Text(text) => Some(Message::Text(text.trim().into())),
+    ///     binary => Some(binary),
+    /// });
+    /// ```
+    pub fn filter_map_messages(self, f: impl Fn(Message) -> Option<Message> + 'static) -> Self {
+        self.transforms.borrow_mut().push(Box::new(f));
+        self
+    }
     /// Get all new WebSocket messages that were received since this function was last called
     /// ```
     /// println!("New messages: {:#?}", client.receive());
     /// ```
     pub fn receive(&mut self) -> Vec<Message> {
-        let data = (*self.data.borrow()).clone();
-        (*self.data.borrow_mut()).clear();
-        data
+        self.prepare_received();
+        let messages = std::mem::take(&mut *self.data.borrow_mut()).into();
+        self.consumed();
+        messages
+    }
+    /// Move all new WebSocket messages into `messages`, like [`receive`](Self::receive) but without allocating a new Vec.
+    ///
+    /// Reuse the same Vec every frame (clearing it after handling the messages) to avoid allocating in a game loop.
+    /// ```
+    /// let mut messages = Vec::new();
+    /// loop {
+    ///     client.receive_into(&mut messages);
+    ///     for message in messages.drain(..) {
+    ///         info!("New Message: {:#?}", message);
+    ///     }
+    /// }
+    /// ```
+    pub fn receive_into(&mut self, messages: &mut Vec<Message>) {
+        self.prepare_received();
+        // Draining keeps the queue's allocation around for the next messages
+        messages.extend(self.data.borrow_mut().drain(..));
+        self.consumed();
+    }
+    /// Copy the contents of all new binary messages into `data`, pushing the range each message takes up to `ranges`.
+    ///
+    /// Text messages stay queued and are returned by the next call to [`receive`](Self::receive) or
+    /// [`receive_into`](Self::receive_into).
+    /// ```
+    /// let (mut data, mut ranges) = (Vec::new(), Vec::new());
+    /// loop {
+    ///     client.receive_binary_into(&mut data, &mut ranges);
+    ///     for range in ranges.drain(..) {
+    ///         decode(&data[range]);
+    ///     }
+    ///     data.clear();
+    /// }
+    /// ```
+    pub fn receive_binary_into(&mut self, data: &mut Vec<u8>, ranges: &mut Vec<Range<usize>>) {
+        self.prepare_received();
+        self.data.borrow_mut().retain(|message| match message {
+            Message::Binary(bytes) => {
+                let start = data.len();
+                data.extend_from_slice(bytes);
+                ranges.push(start..data.len());
+                false
+            }
+            Message::Text(_) => true,
+        });
+        self.consumed();
+    }
+    /// Release the held messages whose playback time has passed, then coalesce the waiting messages
+    fn prepare_received(&self) {
+        self.release_held();
+        if let Some(key) = &*self.coalesce_key.borrow() {
+            coalesce::coalesce(&mut self.data.borrow_mut(), key);
+        }
     }
     /// Get the client's current connection status
     /// ```
@@ -189,18 +753,31 @@ impl PollingClient {
     pub fn status(&self) -> ConnectionStatus {
         self.status.borrow().clone()
     }
+    /// Whether the client is connected, so messages can be sent
+    /// ```
+    /// if client.is_open() {
+    ///     client.send_string("Hello!")?;
+    /// }
+    /// ```
+    pub fn is_open(&self) -> bool {
+        self.status.borrow().is_connected()
+    }
+    /// The subprotocol the server selected, see [`EventClient::protocol`]
+    pub fn protocol(&self) -> String {
+        self.event_client.protocol()
+    }
     /// Send a text message to the server
     /// ```
     /// client.send_string("Hello server!")?;
     /// ```
-    pub fn send_string(&self, message: &str) -> Result<(), JsValue> {
+    pub fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
         self.event_client.send_string(message)
     }
     /// Send a binary message to the server
     /// ```
     /// client.send_binary(vec![0x2, 0xF])?;
     /// ```
-    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), JsValue> {
+    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
         self.event_client.send_binary(message)
     }
 
@@ -208,7 +785,7 @@ impl PollingClient {
     /// ```
     /// client.close()?;
     /// ```
-    pub fn close(&self) -> Result<(), JsValue> {
+    pub fn close(&self) -> Result<(), WebSocketError> {
         self.event_client.close()
     }
     /// Close the connection with a custom close code and, optionally, a reason string
@@ -216,39 +793,444 @@ impl PollingClient {
     /// The reason string must be at most 123 bytes long.
     ///
     /// ```
-    /// client.close_with(1001, Some("going away"))?;
+    /// client.close_with(close_code::GOING_AWAY, Some("going away"))?;
     /// ```
-    pub fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), JsValue> {
+    pub fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), WebSocketError> {
         self.event_client.close_with(code, reason)
     }
+    /// Close the connection once everything sent before has left, see [`EventClient::close_graceful`]
+    pub async fn close_graceful(
+        &self,
+        code: u16,
+        reason: Option<&str>,
+        linger_ms: f64,
+    ) -> Result<(), WebSocketError> {
+        self.event_client
+            .close_graceful(code, reason, linger_ms)
+            .await
+    }
+    /// Add an on_error event handler that runs alongside the others, see [`EventClient::add_on_error`]
+    pub fn add_on_error(&self, f: ErrorHandler) -> HandlerId {
+        self.event_client.add_on_error(f)
+    }
+    /// Add an on_connection event handler that runs alongside the others, see [`EventClient::add_on_connection`]
+    pub fn add_on_connection(&self, f: ConnectionHandler) -> HandlerId {
+        self.event_client.add_on_connection(f)
+    }
+    /// Add an on_message event handler that runs alongside the others, see [`EventClient::add_on_message`]
+    pub fn add_on_message(&self, f: MessageHandler) -> HandlerId {
+        self.event_client.add_on_message(f)
+    }
+    /// Add an on_close event handler that runs alongside the others, see [`EventClient::add_on_close`]
+    pub fn add_on_close(&self, f: CloseHandler) -> HandlerId {
+        self.event_client.add_on_close(f)
+    }
+    /// Add an on_message event handler that only runs for the next message, see [`EventClient::once_on_message`]
+    pub fn once_on_message(&self, f: OnceMessageHandler) -> HandlerId {
+        self.event_client.once_on_message(f)
+    }
+    /// Add an on_connection event handler that only runs once, see [`EventClient::once_on_connection`]
+    pub fn once_on_connection(&self, f: OnceConnectionHandler) -> HandlerId {
+        self.event_client.once_on_connection(f)
+    }
+    /// Add a handler that only receives the messages `filter` returns true for, see [`EventClient::subscribe_filtered`].
+    ///
+    /// Messages it matches aren't returned by [`receive`](PollingClient::receive).
+    pub fn subscribe_filtered(&self, filter: MessageFilter, f: MessageHandler) -> HandlerId {
+        self.event_client.subscribe_filtered(filter, f)
+    }
+    /// Remove a handler added with one of the `add_on_*` or `once_on_*` methods, see [`EventClient::remove_handler`]
+    pub fn remove_handler(&self, id: HandlerId) -> bool {
+        self.event_client.remove_handler(id)
+    }
 }
 
-#[derive(Debug, Clone, Error)]
+/// An error from connecting, sending, or closing. See [`kind`](Self::kind) to tell whether
+/// trying again might help.
+#[derive(Debug, Clone)]
 pub enum WebSocketError {
-    #[error("Failed to create websocket connection: {0}")]
+    /// The connection couldn't be made, like when the WebSocket couldn't be created or the
+    /// connection closed before opening. This is a network error, so it's transient.
     ConnectionCreationError(String),
+    /// The URL couldn't be parsed, has a scheme WebSockets can't use, or has a fragment.
+    /// This is an application error, so retrying the same URL won't help.
+    InvalidUrl(String),
+    /// The browser refused to connect, like to a `ws://` URL from an `https://` page, or to a
+    /// blocked port. This is a security error, which isn't transient.
+    SecurityError(String),
+    /// A message couldn't be sent, like when the connection isn't open yet.
+    /// This is a network error, so it's transient.
+    SendError(String),
+    /// The connection couldn't be closed, like with a close code or reason browsers don't allow.
+    /// This is an application error, so it isn't transient.
+    CloseError(String),
+    /// The WebSocket refused an event listener, see [`EventClient::add_raw_event_listener`]
+    ListenerError(String),
+    /// A message was over the outbound size limit, see [`EventClient::set_size_limits`]
+    MessageTooLarge {
+        /// The size of the message, in bytes
+        size: usize,
+        /// The limit, in bytes
+        max: usize,
+    },
+}
+
+impl fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConnectionCreationError(e) => {
+                write!(f, "Failed to create websocket connection: {}", e)
+            }
+            Self::InvalidUrl(e) => write!(f, "Invalid websocket URL: {}", e),
+            Self::SecurityError(e) => write!(f, "The browser refused to connect: {}", e),
+            Self::SendError(e) => write!(f, "Failed to send message: {}", e),
+            Self::MessageTooLarge { size, max } => write!(
+                f,
+                "Failed to send message: {} bytes is over the {} byte limit",
+                size, max
+            ),
+            Self::CloseError(e) => write!(f, "Failed to close connection: {}", e),
+            Self::ListenerError(e) => write!(f, "Failed to add event listener: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketError {}
+
+impl WebSocketError {
+    /// What sort of problem this is, see [`ErrorKind`]
+    /// ```
+    /// if let Err(e) = EventClient::new(&url) {
+    ///     if e.kind() == ErrorKind::Security {
+    ///         show_mixed_content_warning();
+    ///     }
+    /// }
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ConnectionCreationError(_) | Self::SendError(_) => ErrorKind::Network,
+            Self::SecurityError(_) => ErrorKind::Security,
+            Self::InvalidUrl(_)
+            | Self::CloseError(_)
+            | Self::ListenerError(_)
+            | Self::MessageTooLarge { .. } => ErrorKind::Application,
+        }
+    }
+    /// Whether trying again later might succeed, see [`ErrorKind::is_transient`]
+    /// ```
+    /// match client.send_string("Hello!") {
+    ///     Err(e) if e.is_transient() => retry_later(),
+    ///     result => result?,
+    /// }
+    /// ```
+    pub fn is_transient(&self) -> bool {
+        self.kind().is_transient()
+    }
+}
+
+/// What sort of problem an error is, so retry logic can decide what to do without matching
+/// on messages. See [`WebSocketError::kind`] and [`ProtocolError::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorKind {
+    /// The connection couldn't be made or used, like when the server is down or the network drops.
+    /// The errors passed to on_error handlers are all network errors.
+    Network,
+    /// The browser refused the connection, like a `ws://` URL from an `https://` page
+    Security,
+    /// A received message was malformed
+    Protocol,
+    /// The application asked for something that can't work, like an invalid URL or close code,
+    /// or a message over the size limit
+    Application,
+}
+
+impl ErrorKind {
+    /// Whether trying again later might succeed. Only network errors are transient, since the
+    /// others happen the same way every time.
+    pub fn is_transient(self) -> bool {
+        self == ErrorKind::Network
+    }
 }
 
+/// Turn an error from the underlying socket into a readable description
+trait Describe {
+    fn describe(self) -> String;
+}
+impl Describe for JsValue {
+    fn describe(self) -> String {
+        self.as_string().unwrap_or_else(|| format!("{:?}", self))
+    }
+}
+impl Describe for String {
+    fn describe(self) -> String {
+        self
+    }
+}
+
+/// Get the message of an error event.
+/// Browsers fire plain `Event`s for websocket errors, so the message is usually empty there.
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub(crate) fn error_message(e: &ErrorEvent) -> String {
+    js_sys::Reflect::get(e, &JsValue::from_str("message"))
+        .ok()
+        .and_then(|message| message.as_string())
+        .unwrap_or_default()
+}
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+pub(crate) fn error_message(e: &ErrorEvent) -> String {
+    e.message()
+}
+
+/// Open the underlying browser WebSocket, offering `protocols` as subprotocols
 #[cfg(target_arch = "wasm32")]
+fn create_websocket(url: &str, protocols: &[&str]) -> Result<WebSocket, WebSocketError> {
+    let ws = if protocols.is_empty() {
+        WebSocket::new(url)
+    } else {
+        let protocols: js_sys::Array = protocols.iter().map(|p| JsValue::from_str(p)).collect();
+        WebSocket::new_with_str_sequence(url, &protocols)
+    };
+    ws.map_err(|e| {
+        let field = |name: &str| {
+            js_sys::Reflect::get(&e, &JsValue::from_str(name))
+                .ok()
+                .and_then(|value| value.as_string())
+        };
+        let message = field("message").unwrap_or_else(|| e.clone().describe());
+        // The constructor throws a DOMException, named for what went wrong
+        match field("name").as_deref() {
+            Some("SyntaxError") => WebSocketError::InvalidUrl(message),
+            Some("SecurityError") => WebSocketError::SecurityError(message),
+            _ => WebSocketError::ConnectionCreationError(message),
+        }
+    })
+}
+/// WebSockets only exist in the browser, so on other targets every connection attempt fails.
+/// This lets crates shared between wasm and native builds type-check everywhere.
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "native")))]
+fn create_websocket(_url: &str, _protocols: &[&str]) -> Result<WebSocket, WebSocketError> {
+    Err(WebSocketError::ConnectionCreationError(
+        "WebSockets are only available on wasm32 targets".into(),
+    ))
+}
+
+/// An event based websocket client.
+///
+/// Every field is reference counted, so cloning an EventClient is cheap and gives another handle
+/// to the same connection and handlers. Clones can be moved into closures or stored elsewhere
+/// without wrapping the client in an `Rc<RefCell<>>`.
+///
+/// Dropping the last handle closes the connection and frees the client, since nothing is left
+/// to run its handlers. Keep a handle somewhere, like in your app's state, for as long as the
+/// connection should stay open.
+#[derive(Clone)]
 pub struct EventClient {
     /// The URL this client is connected to
     pub url: Rc<RefCell<String>>,
-    /// The raw web_sys WebSocket object this client is using.
-    /// Be careful when using this field, as it will be a different type depending on the compilation target.
-    connection: Rc<RefCell<web_sys::WebSocket>>,
+    /// The raw web_sys WebSocket object this client is using, see [`EventClient::raw`].
+    /// This is a different type depending on the compilation target.
+    connection: Rc<RefCell<Socket>>,
     /// The current connection status
     pub status: Rc<RefCell<ConnectionStatus>>,
     /// The function bound to the on_error event
-    pub on_error: Rc<RefCell<Option<Box<dyn Fn(ErrorEvent)>>>>,
+    pub on_error: Rc<RefCell<Option<ErrorHandler>>>,
     /// The function bound to the on_connection event
-    pub on_connection: Rc<RefCell<Option<Box<dyn Fn(&EventClient)>>>>,
+    pub on_connection: Rc<RefCell<Option<ConnectionHandler>>>,
     /// The function bound to the on_message event
-    pub on_message: Rc<RefCell<Option<Box<dyn Fn(&EventClient, Message)>>>>,
+    pub on_message: Rc<RefCell<Option<MessageHandler>>>,
     /// The function bound to the on_close event
-    pub on_close: Rc<RefCell<Option<Box<dyn Fn(CloseEvent)>>>>,
+    pub on_close: Rc<RefCell<Option<CloseHandler>>>,
+    listeners: Rc<RefCell<Listeners>>,
+    outbound: Rc<RefCell<Outbound>>,
+}
+
+/// Identifies a handler added with one of the `add_on_*` methods, so it can be removed later
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandlerId(u64);
+
+/// Added handlers for one event, in the order they were added
+type Added<F> = Vec<(HandlerId, Rc<F>)>;
+
+/// Copy out the added handlers, so they can add and remove handlers themselves while running
+fn snapshot<F>(added: &Added<F>) -> Vec<Rc<F>> {
+    added.iter().map(|(_, f)| f.clone()).collect()
+}
+
+/// A handler added with [`EventClient::subscribe_filtered`], and the messages it receives
+struct Subscription {
+    filter: MessageFilter,
+    handler: MessageHandler,
+}
+
+/// Remove an added handler, returning whether it was found
+fn remove<F>(added: &mut Added<F>, id: HandlerId) -> bool {
+    let len = added.len();
+    added.retain(|(i, _)| *i != id);
+    added.len() != len
+}
+
+/// Handlers added with the `add_on_*` methods, which run after the handler set for each event,
+/// and the raw listeners added to the underlying WebSocket
+#[derive(Default)]
+pub(crate) struct Listeners {
+    next_id: u64,
+    /// The connection id and label, see [`EventClient::set_label`]
+    identity: Identity,
+    on_error: Added<ErrorHandler>,
+    on_connection: Added<ConnectionHandler>,
+    /// Counts the calls to [`EventClient::set_on_connection`], so a late call is skipped if the handler was replaced
+    on_connection_set: u64,
+    on_message: Added<MessageHandler>,
+    on_close: Added<CloseHandler>,
+    subscriptions: Added<Subscription>,
+    framing: Option<Framing>,
+    /// Whether received text messages are split into lines, see [`EventClient::set_line_delimited`]
+    line_delimited: bool,
+    /// Messages received while paused, or None while messages are delivered
+    paused: Option<VecDeque<Message>>,
+    /// Messages received before a handler was registered, or None if they aren't held,
+    /// see [`EventClient::set_buffer_early_messages`]
+    early: Option<VecDeque<Message>>,
+    watchdog: Watchdog,
+    on_protocol_error: Option<Rc<ProtocolErrorHandler>>,
+    text_policy: TextPolicy,
+    schemas: Option<Rc<SchemaRegistry>>,
+    size_limits: SizeLimits,
+    async_messages: AsyncMessages,
+    /// Tasks waiting for the status to change, see [`EventClient::status_stream`]
+    status_wakers: Vec<std::task::Waker>,
+    /// Whether the close event has been dispatched
+    closed: bool,
+    stats: Stats,
+    /// The most bytes to hold in messages, see [`EventClient::set_memory_limit`]
+    memory_limit: Option<usize>,
+    /// Counts the bytes held by a [`PollingClient`] wrapping the client
+    inbound_meter: Option<memory::InboundMeter>,
+    /// The smoothed round trip time, see [`EventClient::record_rtt`]
+    rtt_ms: Option<f64>,
+    on_dropped: Option<Rc<DroppedHandler>>,
+    handshake: HandshakeState,
+    #[cfg(feature = "base64")]
+    base64_bridge: bool,
+    #[cfg(feature = "decompress")]
+    decompression: Option<Decompression>,
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    on_raw_message: Option<Rc<RawMessageHandler>>,
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    on_raw_binary: Option<Rc<RawBinaryHandler>>,
+    #[cfg(all(
+        feature = "blob",
+        not(all(feature = "native", not(target_arch = "wasm32")))
+    ))]
+    on_blob_chunk: Option<Rc<BlobChunkHandler>>,
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    on_text_chunk: Option<Rc<TextChunkHandler>>,
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    raw: Vec<RawListener>,
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    socket_callbacks: Option<SocketCallbacks>,
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    js_handlers: js_handlers::JsHandlers,
+    /// Whether socket events are handled on a microtask, see [`EventClient::set_deferred_dispatch`]
+    deferred_dispatch: bool,
+}
+
+/// The handlers set on the underlying WebSocket. They only hold weak handles to the client,
+/// so once every handle is dropped these are too, which removes them and closes the socket.
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+struct SocketCallbacks {
+    socket: WebSocket,
+    _onerror: Closure<dyn Fn(ErrorEvent)>,
+    _onclose: Closure<dyn Fn(CloseEvent)>,
+    _onopen: Closure<dyn Fn(JsValue)>,
+    _onmessage: Closure<dyn Fn(MessageEvent)>,
+}
+
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+impl Drop for SocketCallbacks {
+    fn drop(&mut self) {
+        self.socket.set_onerror(None);
+        self.socket.set_onclose(None);
+        self.socket.set_onopen(None);
+        self.socket.set_onmessage(None);
+        match self.socket.ready_state() {
+            WebSocket::CONNECTING | WebSocket::OPEN => {
+                if let Err(e) = self.socket.close_with_code(close_code::NORMAL) {
+                    error!("Failed to close a dropped client's connection: {:?}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A handle to an [`EventClient`] that doesn't keep it alive, for the WebSocket's event handlers,
+/// the native connection task, [`Reassembler`]s, and the client's own handlers that need it
+pub(crate) struct WeakEventClient {
+    url: Weak<RefCell<String>>,
+    connection: Weak<RefCell<Socket>>,
+    status: Weak<RefCell<ConnectionStatus>>,
+    on_error: Weak<RefCell<Option<ErrorHandler>>>,
+    on_connection: Weak<RefCell<Option<ConnectionHandler>>>,
+    on_message: Weak<RefCell<Option<MessageHandler>>>,
+    on_close: Weak<RefCell<Option<CloseHandler>>>,
+    listeners: Weak<RefCell<Listeners>>,
+    outbound: Weak<RefCell<Outbound>>,
+}
+
+impl WeakEventClient {
+    pub(crate) fn new(client: &EventClient) -> Self {
+        Self {
+            url: Rc::downgrade(&client.url),
+            connection: Rc::downgrade(&client.connection),
+            status: Rc::downgrade(&client.status),
+            on_error: Rc::downgrade(&client.on_error),
+            on_connection: Rc::downgrade(&client.on_connection),
+            on_message: Rc::downgrade(&client.on_message),
+            on_close: Rc::downgrade(&client.on_close),
+            listeners: Rc::downgrade(&client.listeners),
+            outbound: Rc::downgrade(&client.outbound),
+        }
+    }
+    /// Handle an event from the WebSocket, if there's still a handle to the client
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    fn handle_event(&self, f: impl FnOnce(&EventClient) + 'static) {
+        if let Some(client) = self.upgrade() {
+            client.handle_event(f);
+        }
+    }
+    /// The client, if there's still a handle to it
+    pub(crate) fn upgrade(&self) -> Option<EventClient> {
+        Some(EventClient {
+            url: self.url.upgrade()?,
+            connection: self.connection.upgrade()?,
+            status: self.status.upgrade()?,
+            on_error: self.on_error.upgrade()?,
+            on_connection: self.on_connection.upgrade()?,
+            on_message: self.on_message.upgrade()?,
+            on_close: self.on_close.upgrade()?,
+            listeners: self.listeners.upgrade()?,
+            outbound: self.outbound.upgrade()?,
+        })
+    }
+}
+
+/// An event listener added to the underlying WebSocket with [`EventClient::add_raw_event_listener`]
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+struct RawListener {
+    id: HandlerId,
+    event_name: String,
+    callback: Closure<dyn Fn(web_sys::Event)>,
+}
+
+impl Listeners {
+    fn next_id(&mut self) -> HandlerId {
+        self.next_id += 1;
+        HandlerId(self.next_id)
+    }
 }
 
-#[cfg(target_arch = "wasm32")]
 impl EventClient {
     /// Create a new EventClient and connect to a WebSocket URL
     ///
@@ -256,135 +1238,199 @@ impl EventClient {
     /// ```
     /// EventClient::new("wss://ws.ifelse.io")?;
     /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
     pub fn new(url: &str) -> Result<Self, WebSocketError> {
+        Self::with_protocols(url, &[])
+    }
+    /// Create a new EventClient and connect to a WebSocket URL, offering the server `protocols`
+    /// as subprotocols, in order of preference. The one the server selected is returned by
+    /// [`protocol`](Self::protocol) once the connection opens, and if it doesn't select one,
+    /// the connection fails.
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// ```
+    /// EventClient::with_protocols("wss://game.example.com", &["game.v2", "game.v1"])?;
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn with_protocols(url: &str, protocols: &[&str]) -> Result<Self, WebSocketError> {
         // Create connection
-        let ws: web_sys::WebSocket = match WebSocket::new(url) {
-            Ok(ws) => ws,
-            Err(_e) => Err(WebSocketError::ConnectionCreationError(
-                "Failed to connect".into(),
-            ))?,
-        };
+        let ws = create_websocket(url, protocols)?;
+        Ok(Self::from_socket(ws, url.to_string()))
+    }
+    /// Create a new EventClient from a WebSocket that was opened elsewhere, like by another library.
+    ///
+    /// This replaces the socket's event handlers and sets its binary type to ArrayBuffer,
+    /// which can be changed back with [`set_binary_type`](Self::set_binary_type).
+    /// If the socket is already open, on_connection handlers are run right after the task
+    /// that registers them, as they are for any client that's already connected.
+    /// ```
+    /// let ws = web_sys::WebSocket::new("wss://ws.ifelse.io")?;
+    /// let client = EventClient::from_websocket(ws);
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn from_websocket(ws: WebSocket) -> Self {
+        let url = ws.url();
+        Self::from_socket(ws, url)
+    }
+    /// Set how binary messages are received, which can be changed at any time.
+    ///
+    /// [`BinaryType::ArrayBuffer`] is the default, and is best for small messages.
+    /// [`BinaryType::Blob`] lets the browser keep large messages out of memory until they're read.
+    /// ```
+    /// client.set_binary_type(BinaryType::Blob);
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn set_binary_type(&self, binary_type: BinaryType) {
+        self.connection.borrow().set_binary_type(binary_type.into());
+    }
+    /// Run the handlers for socket events on a microtask, instead of inside the browser's event callback.
+    ///
+    /// This avoids reentrancy with UI frameworks that react to the same events, at the cost of one tick
+    /// of latency. Events still run in the order they arrived, and the status changes when its event's
+    /// handlers run. Native clients already run their handlers from their own task, so this has no
+    /// effect there.
+    /// ```
+    /// client.set_deferred_dispatch(true);
+    /// ```
+    pub fn set_deferred_dispatch(&self, deferred: bool) {
+        self.listeners.borrow_mut().deferred_dispatch = deferred;
+    }
+    /// Get the underlying WebSocket, as an escape hatch for browser APIs this crate doesn't wrap.
+    ///
+    /// This is a handle to the same socket, not a copy. Don't replace its event handlers
+    /// (`onmessage`, `onopen`, ...) or change its binary type, as this client relies on them;
+    /// use [`add_raw_event_listener`](Self::add_raw_event_listener) to listen for events instead.
+    /// ```
+    /// info!("Buffered: {} bytes", client.raw().buffered_amount());
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn raw(&self) -> WebSocket {
+        self.connection.borrow().clone()
+    }
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    fn from_socket(ws: WebSocket, url: String) -> Self {
         // For small binary messages, like CBOR, Arraybuffer is more efficient than Blob handling
-        ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
-
-        let status = Rc::new(RefCell::new(ConnectionStatus::Connecting));
-        let ref_status = status.clone();
+        ws.set_binary_type(BinaryType::default().into());
 
-        let on_error: Rc<RefCell<Option<Box<dyn Fn(ErrorEvent)>>>> = Rc::new(RefCell::new(None));
-        let on_error_ref = on_error.clone();
+        let status = match ws.ready_state() {
+            WebSocket::CONNECTING => ConnectionStatus::Connecting,
+            WebSocket::OPEN => ConnectionStatus::Connected,
+            // The close has already happened, so its details are lost
+            _ => ConnectionStatus::Error(CloseInfo::abnormal()),
+        };
+        let client = Self {
+            url: Rc::new(RefCell::new(url)),
+            connection: Rc::new(RefCell::new(ws)),
+            status: Rc::new(RefCell::new(status)),
+            on_error: Rc::new(RefCell::new(None)),
+            on_connection: Rc::new(RefCell::new(None)),
+            on_message: Rc::new(RefCell::new(None)),
+            on_close: Rc::new(RefCell::new(None)),
+            listeners: Rc::new(RefCell::new(Listeners {
+                identity: Identity::next(),
+                ..Listeners::default()
+            })),
+            outbound: Rc::new(RefCell::new(Outbound::default())),
+        };
+        let connection = client.connection.borrow();
 
+        let client_ref = WeakEventClient::new(&client);
         let onerror_callback = Closure::wrap(Box::new(move |e: ErrorEvent| {
-            *ref_status.borrow_mut() = ConnectionStatus::Error;
-            if let Some(f) = &*on_error_ref.borrow() {
-                f.as_ref()(e);
-            }
+            client_ref.handle_event(move |client| {
+                *client.status.borrow_mut() = ConnectionStatus::Error(CloseInfo::abnormal());
+                client.dispatch_error(e);
+            });
         }) as Box<dyn Fn(ErrorEvent)>);
-        ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-        onerror_callback.forget();
-
-        let on_close: Rc<RefCell<Option<Box<dyn Fn(CloseEvent)>>>> = Rc::new(RefCell::new(None));
-        let on_close_ref = on_close.clone();
-        let ref_status = status.clone();
+        connection.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
 
+        let client_ref = WeakEventClient::new(&client);
         let onclose_callback = Closure::wrap(Box::new(move |e: CloseEvent| {
-            *ref_status.borrow_mut() = ConnectionStatus::Disconnected;
-            if let Some(f) = &*on_close_ref.borrow() {
-                f.as_ref()(e);
-            }
+            client_ref.handle_event(move |client| {
+                *client.status.borrow_mut() = ConnectionStatus::closed(CloseInfo::from(&e));
+                client.dispatch_close(e);
+                client.release_socket_callbacks();
+            });
         }) as Box<dyn Fn(CloseEvent)>);
-        ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
-        onclose_callback.forget();
-
-        let on_connection: Rc<RefCell<Option<Box<dyn Fn(&EventClient)>>>> =
-            Rc::new(RefCell::new(None));
-        let on_connection_ref = on_connection.clone();
-
-        let on_message: Rc<RefCell<Option<Box<dyn Fn(&EventClient, Message)>>>> =
-            Rc::new(RefCell::new(None));
-        let on_message_ref = on_message.clone();
-
-        let ref_status = status.clone();
-
-        let connection = Rc::new(RefCell::new(ws));
-
-        let client = Rc::new(RefCell::new(Self {
-            url: Rc::new(RefCell::new(url.to_string())),
-            connection: connection.clone(),
-            on_error: on_error.clone(),
-            on_connection: on_connection.clone(),
-            status: status.clone(),
-            on_message: on_message.clone(),
-            on_close: on_close.clone(),
-        }));
-        let client_ref = client.clone();
+        connection.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
 
+        let client_ref = WeakEventClient::new(&client);
         let onopen_callback = Closure::wrap(Box::new(move |_| {
-            *ref_status.borrow_mut() = ConnectionStatus::Connected;
-            if let Some(f) = &*on_connection_ref.borrow() {
-                f.as_ref()(&*client_ref.clone().borrow());
-            }
+            client_ref.handle_event(|client| {
+                *client.status.borrow_mut() = ConnectionStatus::Connected;
+                client.dispatch_connection();
+            });
         }) as Box<dyn Fn(JsValue)>);
-        connection
-            .borrow_mut()
-            .set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-        onopen_callback.forget();
-
-        let client_ref = client;
+        connection.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
 
+        let client_ref = WeakEventClient::new(&client);
         let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
-            // Process different types of message data
-            if let Ok(abuf) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
-                // Received arraybuffer
-                trace!("message event, received arraybuffer: {:?}", abuf);
-                // Convert arraybuffer to vec
-                let array = js_sys::Uint8Array::new(&abuf).to_vec();
-                if let Some(f) = &*on_message_ref.borrow() {
-                    f.as_ref()(&*client_ref.clone().borrow(), Message::Binary(array));
-                }
-            } else if let Ok(blob) = e.data().dyn_into::<web_sys::Blob>() {
-                // Received blob data
-                trace!("message event, received blob: {:?}", blob);
-                let fr = web_sys::FileReader::new().unwrap();
-                let fr_c = fr.clone();
-                // create onLoadEnd callback
-                let cbref = on_message_ref.clone();
-                let cbfref = client_ref.clone();
-                let onloadend_cb = Closure::wrap(Box::new(move |_e: web_sys::ProgressEvent| {
-                    let array = js_sys::Uint8Array::new(&fr_c.result().unwrap()).to_vec();
-                    if let Some(f) = &*cbref.borrow() {
-                        f.as_ref()(&*cbfref.clone().borrow(), Message::Binary(array));
-                    }
-                })
-                    as Box<dyn Fn(web_sys::ProgressEvent)>);
-                fr.set_onloadend(Some(onloadend_cb.as_ref().unchecked_ref()));
-                fr.read_as_array_buffer(&blob).expect("blob not readable");
-                onloadend_cb.forget();
-            } else if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
-                if let Some(f) = &*on_message_ref.borrow() {
-                    f.as_ref()(&*client_ref.clone().borrow(), Message::Text(txt.into()));
-                }
-            } else {
-                // Got unknown data
-                panic!("Unknown data: {:#?}", e.data());
-            }
+            client_ref.handle_event(move |client| client.receive_event(e));
         }) as Box<dyn Fn(MessageEvent)>);
         // set message event handler on WebSocket
-        connection
-            .borrow()
-            .set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-        // forget the callback to keep it alive
-        onmessage_callback.forget();
+        connection.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
 
-        Ok(Self {
-            url: Rc::new(RefCell::new(url.to_string())),
-            connection,
-            on_error,
-            on_connection,
-            on_message,
-            on_close,
-            status,
-        })
+        drop(connection);
+        // Kept alive by the client until the connection closes or the client is dropped
+        client.listeners.borrow_mut().socket_callbacks = Some(SocketCallbacks {
+            socket: client.raw(),
+            _onerror: onerror_callback,
+            _onclose: onclose_callback,
+            _onopen: onopen_callback,
+            _onmessage: onmessage_callback,
+        });
+        client
+    }
+    /// Drop the handlers set on the WebSocket once the close event has been dispatched, as
+    /// they're no longer needed. It's done on the next tick, since the close handler is still running.
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    fn release_socket_callbacks(&self) {
+        let listeners = Rc::downgrade(&self.listeners);
+        timer::set_timeout(0.0, move || {
+            if let Some(listeners) = listeners.upgrade() {
+                listeners.borrow_mut().socket_callbacks = None;
+            }
+        });
+    }
+    /// Handle an event from the WebSocket now, or on a microtask if dispatch is deferred,
+    /// see [`set_deferred_dispatch`](Self::set_deferred_dispatch)
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    fn handle_event(&self, f: impl FnOnce(&EventClient) + 'static) {
+        if self.listeners.borrow().deferred_dispatch {
+            let client = self.clone();
+            // Tasks are run in the order they're spawned, so events keep their order
+            wasm_bindgen_futures::spawn_local(async move { f(&client) });
+        } else {
+            f(self);
+        }
     }
+    /// Decode a message event's data and run the handlers for it
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    fn receive_event(&self, e: MessageEvent) {
+        self.mark_received();
+        let raw = self.listeners.borrow().on_raw_message.clone();
+        if let Some(f) = raw {
+            f.as_ref()(e.clone());
+        }
+        // Process different types of message data
+        if let Ok(abuf) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
+            // Received arraybuffer
+            trace!(client: self, "message event, received arraybuffer: {:?}", abuf);
+            self.dispatch_binary(abuf);
+        } else if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
+            self.dispatch_text(txt);
+        } else {
+            #[cfg(feature = "blob")]
+            if let Ok(blob) = e.data().dyn_into::<web_sys::Blob>() {
+                // Received blob data
+                trace!(client: self, "message event, received blob: {:?}", blob);
+                self.receive_blob(blob);
+                return;
+            }
+            // Got unknown data
+            panic!("Unknown data: {:#?}", e.data());
+        }
+    }
+
     /// Set an on_error event handler.
     /// This handler will be run when the client disconnects from the server due to an error.
     /// This will overwrite the previous handler.
@@ -394,20 +1440,36 @@ impl EventClient {
     ///    panic!("Error: {:#?}", error);
     /// })));
     /// ```
-    pub fn set_on_error(&mut self, f: Option<Box<dyn Fn(ErrorEvent)>>) {
+    pub fn set_on_error(&mut self, f: Option<ErrorHandler>) {
         *self.on_error.borrow_mut() = f;
     }
     /// Set an on_connection event handler.
     /// This handler will be run when the client successfully connects to a server.
     /// This will overwrite the previous handler.
     /// You can set [None](std::option) to disable the on_connection handler.
+    /// If the client is already connected, it's run right after the current task instead, so
+    /// setup code isn't skipped when a fast server beats it.
     /// ```
     /// client.set_on_connection(Some(Box::new(|client| {
     ///     info!("Connected");
     /// })));
     /// ```
-    pub fn set_on_connection(&mut self, f: Option<Box<dyn Fn(&EventClient)>>) {
+    pub fn set_on_connection(&mut self, f: Option<ConnectionHandler>) {
         *self.on_connection.borrow_mut() = f;
+        let generation = {
+            let mut listeners = self.listeners.borrow_mut();
+            listeners.on_connection_set += 1;
+            listeners.on_connection_set
+        };
+        self.connect_late(move |client| {
+            // Skip it if it's been replaced since
+            if client.listeners.borrow().on_connection_set != generation {
+                return;
+            }
+            if let Some(f) = &*client.on_connection.borrow() {
+                f.as_ref()(client);
+            }
+        });
     }
     /// Set an on_message event handler.
     /// This handler will be run when the client receives a message from a server.
@@ -420,8 +1482,9 @@ impl EventClient {
     ///     },
     ///  )));
     /// ```
-    pub fn set_on_message(&mut self, f: Option<Box<dyn Fn(&EventClient, Message)>>) {
+    pub fn set_on_message(&mut self, f: Option<MessageHandler>) {
         *self.on_message.borrow_mut() = f;
+        self.replay_early();
     }
     /// Set an on_close event handler.
     /// This handler will be run when the client disconnects from a server without an error.
@@ -432,42 +1495,579 @@ impl EventClient {
     ///     info!("Closed");
     /// })));
     /// ```
-    pub fn set_on_close(&mut self, f: Option<Box<dyn Fn(CloseEvent)>>) {
+    pub fn set_on_close(&mut self, f: Option<CloseHandler>) {
         *self.on_close.borrow_mut() = f;
     }
 
+    /// Add an on_error event handler, which runs after the handler set with
+    /// [`set_on_error`](Self::set_on_error) and any handlers added before it.
+    /// ```
+    /// let id = client.add_on_error(Box::new(|error| {
+    ///     error!("Error: {:#?}", error);
+    /// }));
+    /// ```
+    pub fn add_on_error(&self, f: ErrorHandler) -> HandlerId {
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        listeners.on_error.push((id, Rc::new(f)));
+        id
+    }
+    /// Add an on_connection event handler, which runs after the handler set with
+    /// [`set_on_connection`](Self::set_on_connection) and any handlers added before it.
+    /// If the client is already connected, it's run right after the current task instead.
+    /// ```
+    /// let id = client.add_on_connection(Box::new(|client| {
+    ///     info!("Connected");
+    /// }));
+    /// ```
+    pub fn add_on_connection(&self, f: ConnectionHandler) -> HandlerId {
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        listeners.on_connection.push((id, Rc::new(f)));
+        drop(listeners);
+        self.connect_late_added(id);
+        id
+    }
+    /// Add an on_message event handler, which runs after the handler set with
+    /// [`set_on_message`](Self::set_on_message) and any handlers added before it.
+    /// ```
+    /// let id = client.add_on_message(Box::new(|c, m| {
+    ///     metrics.count(&m);
+    /// }));
+    /// ```
+    pub fn add_on_message(&self, f: MessageHandler) -> HandlerId {
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        listeners.on_message.push((id, Rc::new(f)));
+        drop(listeners);
+        self.replay_early();
+        id
+    }
+    /// Add an on_close event handler, which runs after the handler set with
+    /// [`set_on_close`](Self::set_on_close) and any handlers added before it.
+    /// ```
+    /// let id = client.add_on_close(Box::new(|_evt| {
+    ///     info!("Closed");
+    /// }));
+    /// ```
+    pub fn add_on_close(&self, f: CloseHandler) -> HandlerId {
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        listeners.on_close.push((id, Rc::new(f)));
+        id
+    }
+    /// Add an on_message event handler that only runs for the next message, then removes itself.
+    /// Remove it with [`remove_handler`](Self::remove_handler) to cancel it before then.
+    /// ```
+    /// client.send_string("ping")?;
+    /// client.once_on_message(Box::new(|c, reply| {
+    ///     info!("Reply: {:#?}", reply);
+    /// }));
+    /// ```
+    pub fn once_on_message(&self, f: OnceMessageHandler) -> HandlerId {
+        let f = RefCell::new(Some(f));
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        let handler: MessageHandler = Box::new(move |client, message| {
+            if let Some(f) = f.borrow_mut().take() {
+                client.remove_handler(id);
+                f(client, message);
+            }
+        });
+        listeners.on_message.push((id, Rc::new(handler)));
+        drop(listeners);
+        self.replay_early();
+        id
+    }
+    /// Add an on_connection event handler that only runs the next time the client connects, then removes itself.
+    /// If the client is already connected, it's run right after the current task instead.
+    /// Remove it with [`remove_handler`](Self::remove_handler) to cancel it before then.
+    /// ```
+    /// client.once_on_connection(Box::new(|c| {
+    ///     c.send_string("Hello!").unwrap();
+    /// }));
+    /// ```
+    pub fn once_on_connection(&self, f: OnceConnectionHandler) -> HandlerId {
+        let f = RefCell::new(Some(f));
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        let handler: ConnectionHandler = Box::new(move |client| {
+            if let Some(f) = f.borrow_mut().take() {
+                client.remove_handler(id);
+                f(client);
+            }
+        });
+        listeners.on_connection.push((id, Rc::new(handler)));
+        drop(listeners);
+        self.connect_late_added(id);
+        id
+    }
+    /// Add a handler that only receives the messages `filter` returns true for.
+    ///
+    /// Messages matched by any subscription are passed to every matching subscription, in the order
+    /// they were added, and not to the on_message handlers. Only the unmatched rest reach those.
+    /// ```
+    /// let id = client.subscribe_filtered(
+    ///     Box::new(|m| matches!(m, Message::Text(text) if text.starts_with("chat:"))),
+    ///     Box::new(|_client, m| chat.push(m)),
+    /// );
+    /// ```
+    pub fn subscribe_filtered(&self, filter: MessageFilter, f: MessageHandler) -> HandlerId {
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        listeners
+            .subscriptions
+            .push((id, Rc::new(Subscription { filter, handler: f })));
+        drop(listeners);
+        self.replay_early();
+        id
+    }
+    /// Remove a handler added with one of the `add_on_*` or `once_on_*` methods,
+    /// with [`subscribe_filtered`](Self::subscribe_filtered),
+    /// or with [`add_raw_event_listener`](Self::add_raw_event_listener).
+    /// Returns false if it was already removed.
+    /// ```
+    /// client.remove_handler(id);
+    /// ```
+    pub fn remove_handler(&self, id: HandlerId) -> bool {
+        let mut listeners = self.listeners.borrow_mut();
+        let removed = remove(&mut listeners.on_error, id)
+            || remove(&mut listeners.on_connection, id)
+            || remove(&mut listeners.on_message, id)
+            || remove(&mut listeners.on_close, id)
+            || remove(&mut listeners.subscriptions, id)
+            || listeners.watchdog.remove_handler(id);
+        #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+        if let Some(i) = listeners.raw.iter().position(|raw| raw.id == id) {
+            let raw = listeners.raw.remove(i);
+            self.connection
+                .borrow()
+                .remove_event_listener_with_callback(
+                    &raw.event_name,
+                    raw.callback.as_ref().unchecked_ref(),
+                )
+                .ok();
+            return true;
+        }
+        removed
+    }
+    /// Set a handler for the raw `MessageEvent`s of the underlying WebSocket, overwriting the previous handler.
+    ///
+    /// This runs before the message is decoded and the on_message handlers run, and gives access to
+    /// properties this crate doesn't surface, like `origin` and `lastEventId`.
+    /// You can set [None](std::option) to disable the raw message handler.
+    /// ```
+    /// client.set_on_raw_message(Some(Box::new(|e| {
+    ///     info!("Message from {}", e.origin());
+    /// })));
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn set_on_raw_message(&mut self, f: Option<RawMessageHandler>) {
+        self.listeners.borrow_mut().on_raw_message = f.map(Rc::new);
+    }
+    /// Set a handler that receives binary messages as ArrayBuffers, overwriting the previous handler.
+    ///
+    /// While this is set, binary messages skip the copy into wasm memory and the on_message handlers,
+    /// so large messages can be decoded straight from JS memory, like when uploading them to WebGL.
+    /// Text messages are still passed to on_message.
+    /// You can set [None](std::option) to go back to receiving [`Message::Binary`].
+    /// ```
+    /// client.set_on_raw_binary(Some(Box::new(|_client, buffer| {
+    ///     let view = js_sys::Uint8Array::new(&buffer);
+    ///     gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &view, GL::STATIC_DRAW);
+    /// })));
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn set_on_raw_binary(&mut self, f: Option<RawBinaryHandler>) {
+        self.listeners.borrow_mut().on_raw_binary = f.map(Rc::new);
+    }
+    /// Add a listener for any event of the underlying WebSocket, by its DOM event name.
+    ///
+    /// The listener runs alongside this crate's own handlers, and can be removed with
+    /// [`remove_handler`](Self::remove_handler).
+    /// ```
+    /// let id = client.add_raw_event_listener("message", Box::new(|e| {
+    ///     info!("Raw event: {:#?}", e);
+    /// }))?;
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn add_raw_event_listener(
+        &self,
+        event_name: &str,
+        f: RawEventHandler,
+    ) -> Result<HandlerId, WebSocketError> {
+        let callback = Closure::wrap(f);
+        self.connection
+            .borrow()
+            .add_event_listener_with_callback(event_name, callback.as_ref().unchecked_ref())
+            .map_err(|e| WebSocketError::ListenerError(e.describe()))?;
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        listeners.raw.push(RawListener {
+            id,
+            event_name: event_name.to_string(),
+            callback,
+        });
+        Ok(id)
+    }
+    /// Pass a received binary message to the raw binary handler, or copy it and run the message handlers
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    fn dispatch_binary(&self, buffer: js_sys::ArrayBuffer) {
+        let raw_binary = self.listeners.borrow().on_raw_binary.clone();
+        match raw_binary {
+            Some(f) => f.as_ref()(self, buffer),
+            None => {
+                // Check the size first, so oversize messages are never copied into wasm memory
+                let len = match self.inbound_len(buffer.byte_length() as usize) {
+                    Some(len) => len as u32,
+                    None => return,
+                };
+                // Convert arraybuffer to vec
+                let array =
+                    js_sys::Uint8Array::new_with_byte_offset_and_length(&buffer, 0, len).to_vec();
+                self.dispatch_message(Message::Binary(array.into()));
+            }
+        }
+    }
+    /// Run the handlers for an error event
+    pub(crate) fn dispatch_error(&self, e: ErrorEvent) {
+        self.wake_status();
+        if let Some(f) = &*self.on_error.borrow() {
+            f.as_ref()(e.clone());
+        }
+        let listeners = snapshot(&self.listeners.borrow().on_error);
+        for f in listeners {
+            f.as_ref()(e.clone());
+        }
+    }
+    /// Run the handlers for an open event
+    pub(crate) fn dispatch_connection(&self) {
+        self.mark_opened();
+        if self.start_handshake() {
+            return;
+        }
+        self.wake_status();
+        self.run_connection_handlers();
+    }
+    /// Run `f` after the current task if the client is already connected, for a handler
+    /// registered too late to see the connection open
+    fn connect_late(&self, f: impl FnOnce(&EventClient) + 'static) {
+        if !self.status().is_connected() {
+            return;
+        }
+        let client = self.clone();
+        timer::set_timeout(0.0, move || {
+            if client.status().is_connected() {
+                f(&client);
+            }
+        });
+    }
+    /// Run the added on_connection handler `id` if the client is already connected, and it's
+    /// still added by then
+    fn connect_late_added(&self, id: HandlerId) {
+        self.connect_late(move |client| {
+            let f = client
+                .listeners
+                .borrow()
+                .on_connection
+                .iter()
+                .find(|(i, _)| *i == id)
+                .map(|(_, f)| f.clone());
+            if let Some(f) = f {
+                f.as_ref()(client);
+            }
+        });
+    }
+    /// Run the handlers for a connection that's ready for the application
+    pub(crate) fn run_connection_handlers(&self) {
+        // Messages queued while connecting go before anything the handlers send
+        self.drain();
+        if let Some(f) = &*self.on_connection.borrow() {
+            f.as_ref()(self);
+        }
+        let listeners = snapshot(&self.listeners.borrow().on_connection);
+        for f in listeners {
+            f.as_ref()(self);
+        }
+    }
+    /// Stop passing received messages to the on_message handlers until [`resume`](EventClient::resume) is called.
+    ///
+    /// Messages received in the meantime are held, in order, so nothing is lost. Raw message
+    /// handlers and the other events aren't paused.
+    /// ```
+    /// client.pause();
+    /// load_level().await;
+    /// client.resume();
+    /// ```
+    pub fn pause(&self) {
+        let mut listeners = self.listeners.borrow_mut();
+        if listeners.paused.is_none() {
+            listeners.paused = Some(VecDeque::new());
+        }
+    }
+    /// Pass the messages held while paused to the on_message handlers, then go back to passing them on as they arrive
+    /// ```
+    /// client.resume();
+    /// ```
+    pub fn resume(&self) {
+        let held = self.listeners.borrow_mut().paused.take();
+        let mut held = match held {
+            Some(held) => held,
+            None => return,
+        };
+        while let Some(message) = held.pop_front() {
+            // A handler might pause again, in which case the rest stay held
+            if let Some(paused) = &mut self.listeners.borrow_mut().paused {
+                paused.push_front(message);
+                for message in held.into_iter().rev() {
+                    paused.push_front(message);
+                }
+                return;
+            }
+            self.dispatch_message(message);
+        }
+    }
+    /// Whether message delivery is paused
+    /// ```
+    /// assert!(!client.is_paused());
+    /// ```
+    pub fn is_paused(&self) -> bool {
+        self.listeners.borrow().paused.is_some()
+    }
+    /// Run the handlers for a received message, splitting it first if framing is set
+    pub(crate) fn dispatch_message(&self, message: Message) {
+        self.listeners
+            .borrow_mut()
+            .stats
+            .received_sizes
+            .record(&message);
+        let message = match self.limit_inbound(message) {
+            Some(message) => message,
+            None => return,
+        };
+        #[cfg(feature = "base64")]
+        let message = match self.decode_base64(message) {
+            Some(message) => message,
+            None => return,
+        };
+        #[cfg(feature = "decompress")]
+        let message = match self.decompress_inbound(message) {
+            Some(message) => message,
+            None => return,
+        };
+        if self.answer_handshake(&message) {
+            return;
+        }
+        if self.is_paused() {
+            let other_bytes = self.queued_bytes() + self.metered_bytes() + self.early_bytes();
+            let discarded = {
+                let mut listeners = self.listeners.borrow_mut();
+                let limit = listeners.memory_limit;
+                match &mut listeners.paused {
+                    Some(paused) => {
+                        paused.push_back(message);
+                        memory::trim(paused, other_bytes, limit)
+                    }
+                    None => 0,
+                }
+            };
+            self.discarded_inbound(discarded);
+            return;
+        }
+        let (framing, line_delimited) = {
+            let listeners = self.listeners.borrow();
+            (listeners.framing, listeners.line_delimited)
+        };
+        match (framing, message) {
+            (Some(framing), Message::Binary(frame)) => {
+                match FramedReader::new(framing).split(&frame) {
+                    Ok(messages) => {
+                        for message in messages {
+                            self.run_message_handlers(Message::Binary(message));
+                        }
+                    }
+                    Err(e) => self.dispatch_protocol_error(e.into()),
+                }
+            }
+            (_, Message::Text(text)) if line_delimited => {
+                for line in lines::split_lines(&text) {
+                    self.run_message_handlers(Message::Text(line.into()));
+                }
+            }
+            (_, message) => self.run_message_handlers(message),
+        }
+    }
+    fn run_message_handlers(&self, message: Message) {
+        self.catch_panic(|| self.run_message_handlers_unguarded(message));
+    }
+    fn run_message_handlers_unguarded(&self, message: Message) {
+        if self.hold_early(&message) {
+            return;
+        }
+        let subscriptions = snapshot(&self.listeners.borrow().subscriptions);
+        let mut matched = false;
+        for subscription in subscriptions {
+            if (subscription.filter)(&message) {
+                matched = true;
+                (subscription.handler)(self, message.clone());
+            }
+        }
+        if matched {
+            return;
+        }
+        let schemas = self.listeners.borrow().schemas.clone();
+        if let (Some(schemas), Message::Binary(data)) = (schemas, &message) {
+            schemas.dispatch(self, data.clone());
+            return;
+        }
+        if let Some(f) = &*self.on_message.borrow() {
+            f.as_ref()(self, message.clone());
+        }
+        let listeners = snapshot(&self.listeners.borrow().on_message);
+        for f in listeners {
+            f.as_ref()(self, message.clone());
+        }
+        self.dispatch_async(message);
+    }
+    /// Run the handlers for a close event
+    pub(crate) fn dispatch_close(&self, e: CloseEvent) {
+        self.listeners.borrow_mut().closed = true;
+        self.discard_queued();
+        self.wake_status();
+        if let Some(f) = &*self.on_close.borrow() {
+            f.as_ref()(e.clone());
+        }
+        let listeners = snapshot(&self.listeners.borrow().on_close);
+        for f in listeners {
+            f.as_ref()(e.clone());
+        }
+    }
+
+    /// Get the client's current connection status
+    /// ```
+    /// println!("Current status: {:#?}", client.status());
+    /// ```
+    pub fn status(&self) -> ConnectionStatus {
+        self.status.borrow().clone()
+    }
+    /// Whether the client is connected, so messages can be sent
+    /// ```
+    /// if client.is_open() {
+    ///     client.send_string("Hello!")?;
+    /// }
+    /// ```
+    pub fn is_open(&self) -> bool {
+        self.status.borrow().is_connected()
+    }
+    /// Get the number of bytes that have been sent but not yet transmitted to the network
+    /// ```
+    /// if client.buffered_amount() < 1 << 20 {
+    ///     client.send_binary(next_chunk)?;
+    /// }
+    /// ```
+    pub fn buffered_amount(&self) -> u32 {
+        self.connection.borrow().buffered_amount()
+    }
+    /// The subprotocol the server selected from the ones offered with
+    /// [`with_protocols`](Self::with_protocols), or an empty string if it didn't select one or
+    /// the connection hasn't opened yet
+    /// ```
+    /// if client.protocol() == "game.v1" {
+    ///     warn!("The server is out of date");
+    /// }
+    /// ```
+    pub fn protocol(&self) -> String {
+        self.connection.borrow().protocol()
+    }
     /// Send a text message to the server
     /// ```
     /// client.send_string("Hello server!")?;
     /// ```
-    pub fn send_string(&self, message: &str) -> Result<(), JsValue> {
-        self.connection.borrow().send_with_str(message)
+    pub fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        // Text can't be batched, so anything batched before it has to go first
+        self.flush()?;
+        self.send_limited(Message::Text(message.into()), Priority::Normal)
     }
     /// Send a binary message to the server
     /// ```
     /// client.send_binary(vec![0x2, 0xF])?;
     /// ```
-    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), JsValue> {
+    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        if self.batch(&message)? {
+            return Ok(());
+        }
+        self.flush()?;
+        self.send_limited(Message::Binary(message.into()), Priority::Normal)
+    }
+    /// Send a message straight to the socket
+    pub(crate) fn send_now(&self, message: &Message) -> Result<(), WebSocketError> {
+        #[cfg(feature = "base64")]
+        let encoded = self.encode_base64(message);
+        #[cfg(feature = "base64")]
+        let sent = encoded.as_ref().unwrap_or(message);
+        #[cfg(not(feature = "base64"))]
+        let sent = message;
+        let connection = self.connection.borrow();
+        match sent {
+            Message::Text(text) => connection.send_with_str(text),
+            Message::Binary(data) => connection.send_with_u8_array(data),
+        }
+        .map_err(|e| WebSocketError::SendError(e.describe()))?;
+        self.outbound.borrow_mut().wrote(message);
+        self.listeners.borrow_mut().stats.sent_sizes.record(message);
+        Ok(())
+    }
+    /// Send a Blob as a binary message, without copying it into wasm memory first
+    /// ```
+    /// client.send_blob(&blob)?;
+    /// ```
+    #[cfg(all(
+        feature = "blob",
+        not(all(feature = "native", not(target_arch = "wasm32")))
+    ))]
+    pub fn send_blob(&self, blob: &web_sys::Blob) -> Result<(), WebSocketError> {
+        #[cfg(feature = "base64")]
+        if self.base64_bridged() {
+            return Err(WebSocketError::SendError(
+                "Blobs can't be sent through the base64 bridge".into(),
+            ));
+        }
         self.connection
             .borrow()
-            .send_with_u8_array(message.as_slice())
+            .send_with_blob(blob)
+            .map_err(|e| WebSocketError::SendError(e.describe()))
+    }
+    /// Send a File, like one picked with an `<input type="file">`, as a binary message.
+    /// The browser reads the file itself, so it's never copied into wasm memory.
+    /// ```
+    /// client.send_file(&input.files().unwrap().get(0).unwrap())?;
+    /// ```
+    #[cfg(all(
+        feature = "blob",
+        not(all(feature = "native", not(target_arch = "wasm32")))
+    ))]
+    pub fn send_file(&self, file: &web_sys::File) -> Result<(), WebSocketError> {
+        self.send_blob(file)
     }
 
     /// Close the connection
     /// ```
     /// client.close()?;
     /// ```
-    pub fn close(&self) -> Result<(), JsValue> {
-        self.connection.borrow().close()
+    pub fn close(&self) -> Result<(), WebSocketError> {
+        self.connection
+            .borrow()
+            .close()
+            .map_err(|e| WebSocketError::CloseError(e.describe()))
     }
     /// Close the connection with a custom close code and, optionally, a reason string
     ///
     /// The reason string must be at most 123 bytes long.
     ///
     /// ```
-    /// client.close_with(1001, Some("going away"))?;
+    /// client.close_with(close_code::GOING_AWAY, Some("going away"))?;
     /// ```
-    pub fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), JsValue> {
+    pub fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), WebSocketError> {
         match reason {
             Some(reason) => self
                 .connection
@@ -475,5 +2075,84 @@ impl EventClient {
                 .close_with_code_and_reason(code, reason),
             None => self.connection.borrow().close_with_code(code),
         }
+        .map_err(|e| WebSocketError::CloseError(e.describe()))
+    }
+}
+
+impl WsClient for EventClient {
+    fn status(&self) -> ConnectionStatus {
+        EventClient::status(self)
+    }
+    fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        EventClient::send_string(self, message)
+    }
+    fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        EventClient::send_binary(self, message)
+    }
+    fn close(&self) -> Result<(), WebSocketError> {
+        EventClient::close(self)
+    }
+    fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), WebSocketError> {
+        EventClient::close_with(self, code, reason)
+    }
+    fn set_on_error(&mut self, f: Option<ErrorHandler>) {
+        EventClient::set_on_error(self, f)
+    }
+    fn set_on_connection(&mut self, f: Option<ConnectionHandler>) {
+        EventClient::set_on_connection(self, f)
+    }
+    fn set_on_message(&mut self, f: Option<MessageHandler>) {
+        EventClient::set_on_message(self, f)
+    }
+    fn set_on_close(&mut self, f: Option<CloseHandler>) {
+        EventClient::set_on_close(self, f)
+    }
+    fn event_client(&self) -> Option<EventClient> {
+        Some(self.clone())
+    }
+}
+
+/// Handlers set on a [`PollingClient`] are run in addition to its own buffering,
+/// so messages passed to an on_message handler are still returned by [`PollingClient::receive`].
+impl WsClient for PollingClient {
+    fn status(&self) -> ConnectionStatus {
+        PollingClient::status(self)
+    }
+    fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        PollingClient::send_string(self, message)
+    }
+    fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        PollingClient::send_binary(self, message)
+    }
+    fn close(&self) -> Result<(), WebSocketError> {
+        PollingClient::close(self)
+    }
+    fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), WebSocketError> {
+        PollingClient::close_with(self, code, reason)
+    }
+    fn set_on_error(&mut self, f: Option<ErrorHandler>) {
+        *self.on_error.borrow_mut() = f;
+    }
+    fn set_on_connection(&mut self, f: Option<ConnectionHandler>) {
+        let f = f.map(Rc::new);
+        *self.on_connection.borrow_mut() = f.clone();
+        if let Some(f) = f {
+            let on_connection = self.on_connection.clone();
+            self.event_client.connect_late(move |client| {
+                // Skip it if it's been replaced since
+                if matches!(&*on_connection.borrow(), Some(current) if Rc::ptr_eq(current, &f)) {
+                    f.as_ref()(client);
+                }
+            });
+        }
+    }
+    fn set_on_message(&mut self, f: Option<MessageHandler>) {
+        *self.on_message.borrow_mut() = f;
+    }
+    fn set_on_close(&mut self, f: Option<CloseHandler>) {
+        *self.on_close.borrow_mut() = f;
+    }
+    fn event_client(&self) -> Option<EventClient> {
+        Some(self.event_client.clone())
     }
 }
@@ -0,0 +1,52 @@
+//! Line-delimited text, for servers that stream newline-delimited JSON or pack several events into
+//! one text frame.
+//!
+//! With [`EventClient::set_line_delimited`], each line of a received text message is passed to
+//! on_message on its own. [`EventClient::send_lines`] joins lines into one text message for the
+//! other direction.
+//! ```
+//! client.set_line_delimited(true);
+//! client.send_lines(&[r#"{"op": "sub", "topic": "a"}"#, r#"{"op": "sub", "topic": "b"}"#])?;
+//! ```
+use crate::{EventClient, WebSocketError};
+
+/// The lines of a text message, without their line endings. Empty lines are skipped.
+pub(crate) fn split_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.is_empty())
+}
+
+impl EventClient {
+    /// Split received text messages into lines, passing each one to on_message on its own, or set
+    /// false to pass text messages whole.
+    ///
+    /// Lines can end with `\n` or `\r\n`, and empty lines are skipped. Binary messages aren't affected.
+    /// ```
+    /// client.set_line_delimited(true);
+    /// ```
+    pub fn set_line_delimited(&self, enabled: bool) {
+        self.listeners.borrow_mut().line_delimited = enabled;
+    }
+    /// Send lines as one text message, each ending with `\n`.
+    ///
+    /// Fails without sending anything if a line contains a line break.
+    /// ```
+    /// let events: Vec<String> = pending.iter().map(|e| e.to_json()).collect();
+    /// client.send_lines(&events)?;
+    /// ```
+    pub fn send_lines<S: AsRef<str>>(&self, lines: &[S]) -> Result<(), WebSocketError> {
+        let mut text = String::new();
+        for line in lines {
+            let line = line.as_ref();
+            if line.contains(['\n', '\r']) {
+                return Err(WebSocketError::SendError(
+                    "A line contains a line break".into(),
+                ));
+            }
+            text.push_str(line);
+            text.push('\n');
+        }
+        self.send_string(&text)
+    }
+}
@@ -0,0 +1,37 @@
+//! Logging macros that compile to nothing without the `log` feature.
+//!
+//! The arguments are still passed to `format_args!` when logging is disabled,
+//! so values that are only logged don't cause unused variable warnings.
+//!
+//! Messages about a client start with `client: <expr>,`, which tags them with its connection id
+//! and label, see [`EventClient::set_label`](crate::EventClient::set_label).
+
+macro_rules! trace {
+    (client: $client:expr, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::trace!("[{}] {}", $client.log_tag(), format_args!($($arg)+));
+        #[cfg(not(feature = "log"))]
+        let _ = (&$client, format_args!($($arg)+));
+    }};
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::trace!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+macro_rules! error {
+    (client: $client:expr, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::error!("[{}] {}", $client.log_tag(), format_args!($($arg)+));
+        #[cfg(not(feature = "log"))]
+        let _ = (&$client, format_args!($($arg)+));
+    }};
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::error!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
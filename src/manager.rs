@@ -0,0 +1,1056 @@
+//! Managing several named connections together, for apps that talk to more than one server.
+//!
+//! A [`SocketManager`] owns a connection for each name, like "game", "chat" and "telemetry".
+//! It reopens them with one shared [`Reconnect`] policy, keeps the messages they receive in one
+//! queue, and passes all of their events to one handler along with the connection's name.
+//! ```
+//! let manager = SocketManager::new();
+//! manager.set_reconnect(Some(Reconnect::default()));
+//! manager.add("game", "wss://game.example.com")?;
+//! manager.add("chat", "wss://chat.example.com")?;
+//! manager.set_on_event(Some(Box::new(|name, event| {
+//!     info!("{}: {:?}", name, event);
+//! })));
+//!
+//! // Later, once per frame
+//! for (name, message) in manager.receive() {
+//!     info!("{} sent {:?}", name, message);
+//! }
+//! ```
+use crate::timer::{monotonic_ms, set_timeout};
+use crate::{
+    close_code, CloseEvent, CloseInfo, ConnectionStatus, ErrorEvent, EventClient, Health, Message,
+    Priority, SendOutcome, WebSocketError,
+};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::{Rc, Weak};
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+use wasm_bindgen::{closure::Closure, JsCast};
+
+/// When connections are reopened after they close, see [`SocketManager::set_reconnect`]
+///
+/// The delay grows by `multiplier` after every attempt that fails, and starts over once a
+/// connection has stayed open for `stability_window_ms`. A server that accepts connections and
+/// then drops them straight away keeps getting longer delays, while a connection that recovers
+/// doesn't carry the long delay into its next drop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reconnect {
+    /// The delay before the first attempt, in milliseconds
+    pub initial_delay_ms: f64,
+    /// The longest delay between attempts, in milliseconds
+    pub max_delay_ms: f64,
+    /// How much the delay grows after each failed attempt
+    pub multiplier: f64,
+    /// How many attempts in a row to make before giving up, or None to keep trying
+    pub max_attempts: Option<u32>,
+    /// How long a connection has to stay open before the delay starts over, in milliseconds
+    pub stability_window_ms: f64,
+    /// Whether to wait as long as the server asks instead of the delay, when its close reason
+    /// has a [retry hint](CloseInfo::retry_after_ms) or it sent one in a
+    /// [control message](SocketManager::set_retry_hint_parser)
+    pub honor_retry_hints: bool,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: 500.0,
+            max_delay_ms: 30_000.0,
+            multiplier: 2.0,
+            max_attempts: None,
+            stability_window_ms: 5_000.0,
+            honor_retry_hints: true,
+        }
+    }
+}
+
+impl Reconnect {
+    /// The delay before attempt `attempt`, counting from 0, in milliseconds
+    pub fn delay_ms(&self, attempt: u32) -> f64 {
+        (self.initial_delay_ms * self.multiplier.powf(attempt as f64)).min(self.max_delay_ms)
+    }
+    /// Whether attempt `attempt`, counting from 0, should be made
+    fn allows(&self, attempt: u32) -> bool {
+        match self.max_attempts {
+            Some(max) => attempt < max,
+            None => true,
+        }
+    }
+}
+
+/// What to do about a connection that closed, returned by a [`ReconnectPolicy`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReconnectDecision {
+    /// Reopen it after the delay from the [`Reconnect`] policy, or leave it closed if there's none
+    /// or it's out of attempts
+    Backoff,
+    /// Reopen it after `after_ms` milliseconds
+    Retry {
+        /// How long to wait, in milliseconds
+        after_ms: f64,
+    },
+    /// Reopen it straight away at another URL, which is kept for later reconnects
+    RetryNewUrl(String),
+    /// Leave it closed
+    GiveUp,
+}
+
+/// Finds the retry hint in a control message, in milliseconds,
+/// see [`SocketManager::set_retry_hint_parser`]
+pub type RetryHintParser = Box<dyn Fn(&Message) -> Option<f64>>;
+
+/// Decides whether to reopen a connection, called with its name and how it closed,
+/// see [`SocketManager::set_reconnect_policy`]
+pub type ReconnectPolicy = Box<dyn Fn(&str, CloseInfo) -> ReconnectDecision>;
+
+/// An event from one of a [`SocketManager`]'s connections
+#[derive(Debug, Clone)]
+pub enum ManagedEvent {
+    /// The connection opened
+    Connected,
+    /// The connection received a message
+    Message(Message),
+    /// The connection had an error
+    Error(ErrorEvent),
+    /// The connection closed
+    Closed(CloseEvent),
+    /// The connection was handed over to `url`, see [`SocketManager::handover`]
+    HandedOver {
+        /// The URL of the new connection
+        url: String,
+    },
+    /// The connection went quiet for longer than the watchdog allows, and is being closed,
+    /// see [`SocketManager::set_watchdog`]
+    Stale,
+    /// The connection will be reopened after `delay_ms` milliseconds
+    Reconnecting {
+        /// The number of attempts made in a row before this one
+        attempt: u32,
+        /// How long until the attempt, in milliseconds
+        delay_ms: f64,
+    },
+}
+
+/// The connections a broadcast couldn't be sent on, see [`SocketManager::broadcast_string`]
+#[derive(Debug, Clone)]
+pub struct BroadcastError {
+    /// The name of each connection that failed, and why
+    pub failures: Vec<(String, WebSocketError)>,
+}
+
+impl fmt::Display for BroadcastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to broadcast to {} connections",
+            self.failures.len()
+        )?;
+        for (name, e) in &self.failures {
+            write!(f, "\n{}: {}", name, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BroadcastError {}
+
+/// A handler for the events of every connection in a [`SocketManager`], called with the connection's name
+pub type ManagedEventHandler = Box<dyn Fn(&str, ManagedEvent)>;
+
+/// A handler called with the name of a connection the server rejected, and how it was closed,
+/// see [`SocketManager::set_rejection_codes`]
+pub type RejectedHandler = Box<dyn Fn(&str, CloseInfo)>;
+
+/// Owns several named connections, and manages their lifecycles together.
+///
+/// Cloning this is cheap, and every clone manages the same connections.
+#[derive(Clone, Default)]
+pub struct SocketManager {
+    inner: Rc<RefCell<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// The connections, in the order they were added
+    sockets: Vec<Socket>,
+    reconnect: Option<Reconnect>,
+    reconnect_policy: Option<Rc<ReconnectPolicy>>,
+    retry_hint_parser: Option<Rc<RetryHintParser>>,
+    /// How long without activity before connections are closed, see [`SocketManager::set_idle_disconnect`]
+    idle_timeout_ms: Option<f64>,
+    /// When there was last activity, from [`monotonic_ms`]
+    last_active: f64,
+    /// Changed whenever the idle timeout restarts, so the checks scheduled before stop
+    idle_generation: u64,
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    page_visible: Option<PageVisible>,
+    watchdog: Option<f64>,
+    on_event: Option<Rc<ManagedEventHandler>>,
+    /// Close codes that mean the server won't accept the connection, so it isn't reopened
+    rejection_codes: Vec<u16>,
+    on_rejected: Option<Rc<RejectedHandler>>,
+    received: VecDeque<(String, Message)>,
+    next_id: u64,
+}
+
+impl Inner {
+    fn socket(&mut self, name: &str) -> Option<&mut Socket> {
+        self.sockets.iter_mut().find(|socket| socket.name == name)
+    }
+    /// The socket `name`, if it's still the connection `id` the event came from
+    fn current(&mut self, name: &str, id: u64) -> Option<&mut Socket> {
+        self.socket(name).filter(|socket| socket.id == id)
+    }
+}
+
+struct Socket {
+    name: String,
+    url: String,
+    client: EventClient,
+    /// Identifies the underlying connection, which changes every time it's reopened
+    id: u64,
+    /// The number of reconnect attempts made since the connection was last open
+    attempts: u32,
+    /// The number of times the connection has been reopened
+    reconnects: u32,
+    /// How long the server last asked to wait before reconnecting, in a control message
+    retry_hint: Option<f64>,
+    /// Whether the connection was closed on purpose, so it isn't reopened
+    closing: bool,
+    /// Whether it was closed for being idle, so it's reopened on the next activity
+    idle: bool,
+    /// Whether it's already waiting to be reopened
+    reconnecting: bool,
+    /// The connection it's being handed over to, see [`SocketManager::handover`]
+    handover: Option<Handover>,
+}
+
+/// A new connection that replaces a socket's connection once it opens
+struct Handover {
+    url: String,
+    client: EventClient,
+    id: u64,
+    resume: Option<Message>,
+}
+
+impl SocketManager {
+    /// Create a SocketManager without any connections
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Reopen connections that close, other than those closed with [`close`](Self::close),
+    /// or set None to leave them closed
+    /// ```
+    /// manager.set_reconnect(Some(Reconnect {
+    ///     max_attempts: Some(10),
+    ///     ..Reconnect::default()
+    /// }));
+    /// ```
+    pub fn set_reconnect(&self, reconnect: Option<Reconnect>) {
+        self.inner.borrow_mut().reconnect = reconnect;
+    }
+    /// Decide whether and when to reopen each connection that closes, or set None to always
+    /// follow the [`Reconnect`] policy.
+    ///
+    /// The policy is called on every close, other than closes made with [`close`](Self::close)
+    /// or with one of the [rejection codes](Self::set_rejection_codes). Connections closed by the
+    /// watchdog follow the `Reconnect` policy.
+    /// ```
+    /// manager.set_reconnect_policy(Some(Box::new(|_name, info| match info.code {
+    ///     close_code::TRY_AGAIN_LATER => ReconnectDecision::Retry { after_ms: 60_000.0 },
+    ///     4010 => ReconnectDecision::RetryNewUrl(info.reason),
+    ///     4011 => ReconnectDecision::GiveUp,
+    ///     _ => ReconnectDecision::Backoff,
+    /// })));
+    /// ```
+    pub fn set_reconnect_policy(&self, policy: Option<ReconnectPolicy>) {
+        self.inner.borrow_mut().reconnect_policy = policy.map(Rc::new);
+    }
+    /// Recognize control messages the server sends to say how long to wait before reconnecting,
+    /// for servers that can't put the hint in the close reason. `f` returns the time in
+    /// milliseconds for control messages, which aren't passed on, and None for other messages.
+    ///
+    /// The last hint received is used for the next reconnect, if the [`Reconnect`] policy
+    /// honors retry hints. A hint in the close reason takes precedence.
+    /// ```
+    /// manager.set_retry_hint_parser(Some(Box::new(|message| match message {
+    ///     Message::Text(text) => text.strip_prefix("BACKOFF ")?.parse().ok(),
+    ///     Message::Binary(_) => None,
+    /// })));
+    /// ```
+    pub fn set_retry_hint_parser(&self, f: Option<RetryHintParser>) {
+        self.inner.borrow_mut().retry_hint_parser = f.map(Rc::new);
+    }
+    /// Close every connection once there's been no activity for `timeout_ms` milliseconds, to save
+    /// battery on mobile devices, or set None to keep them open.
+    ///
+    /// Sends through the manager, calls to [`mark_active`](Self::mark_active), and the page
+    /// becoming visible count as activity. Connections closed for being idle are reopened on the
+    /// next activity, and a message sent then is queued until the connection opens.
+    /// ```
+    /// manager.set_idle_disconnect(Some(5.0 * 60_000.0));
+    /// ```
+    pub fn set_idle_disconnect(&self, timeout_ms: Option<f64>) {
+        {
+            let mut inner = self.inner.borrow_mut();
+            inner.idle_timeout_ms = timeout_ms;
+            // Start a new check, so a shorter timeout isn't waiting on the old one
+            inner.idle_generation = 0;
+            #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+            if timeout_ms.is_none() {
+                inner.page_visible = None;
+            } else if inner.page_visible.is_none() {
+                let weak = Rc::downgrade(&self.inner);
+                inner.page_visible = PageVisible::listen(move || {
+                    if let Some(manager) = upgrade(&weak) {
+                        manager.mark_active();
+                    }
+                });
+            }
+        }
+        self.mark_active();
+    }
+    /// Note user activity, like input, which keeps idle connections open and reopens those
+    /// closed for being idle, see [`set_idle_disconnect`](Self::set_idle_disconnect)
+    /// ```
+    /// canvas.on_pointer_down(move |_| manager.mark_active());
+    /// ```
+    pub fn mark_active(&self) {
+        self.wake();
+    }
+    /// Close connections that go `timeout_ms` milliseconds without receiving a message, or set None to stop.
+    ///
+    /// Connections closed by the watchdog are reopened straight away if there's a reconnect policy,
+    /// without waiting for a half-open connection to finish closing. See [`EventClient::set_watchdog`].
+    /// ```
+    /// manager.set_watchdog(Some(20_000.0));
+    /// ```
+    pub fn set_watchdog(&self, timeout_ms: Option<f64>) {
+        let clients = {
+            let mut inner = self.inner.borrow_mut();
+            inner.watchdog = timeout_ms;
+            inner
+                .sockets
+                .iter()
+                .map(|socket| socket.client.clone())
+                .collect::<Vec<_>>()
+        };
+        for client in clients {
+            client.set_watchdog(timeout_ms);
+        }
+    }
+    /// Stop reopening a connection that closes with one of `codes`, like an application's codes
+    /// for unauthorized or banned clients, and call the on_rejected handler instead.
+    ///
+    /// The connection stays managed, but isn't reopened until it's added again.
+    /// ```
+    /// manager.set_rejection_codes(&[4001, 4003]);
+    /// manager.set_on_rejected(Some(Box::new(|name, info| {
+    ///     show_login_prompt(name, &info.reason);
+    /// })));
+    /// ```
+    pub fn set_rejection_codes(&self, codes: &[u16]) {
+        self.inner.borrow_mut().rejection_codes = codes.to_vec();
+    }
+    /// Set a handler for connections closed with one of the
+    /// [rejection codes](Self::set_rejection_codes), overwriting the previous handler
+    pub fn set_on_rejected(&self, f: Option<RejectedHandler>) {
+        self.inner.borrow_mut().on_rejected = f.map(Rc::new);
+    }
+    /// Set a handler for the events of every connection, overwriting the previous handler
+    /// ```
+    /// manager.set_on_event(Some(Box::new(|name, event| {
+    ///     if let ManagedEvent::Closed(_) = event {
+    ///         warn!("Lost the {} connection", name);
+    ///     }
+    /// })));
+    /// ```
+    pub fn set_on_event(&self, f: Option<ManagedEventHandler>) {
+        self.inner.borrow_mut().on_event = f.map(Rc::new);
+    }
+    /// Connect to a WebSocket URL under `name`, closing any connection that already had the name
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// ```
+    /// manager.add("chat", "wss://chat.example.com")?;
+    /// ```
+    pub fn add(&self, name: &str, url: &str) -> Result<(), WebSocketError> {
+        self.remove(name);
+        let id = self.next_id();
+        let client = self.open(name, url, id)?;
+        self.inner.borrow_mut().sockets.push(Socket {
+            name: name.to_string(),
+            url: url.to_string(),
+            client,
+            id,
+            attempts: 0,
+            reconnects: 0,
+            retry_hint: None,
+            closing: false,
+            idle: false,
+            reconnecting: false,
+            handover: None,
+        });
+        Ok(())
+    }
+    /// Close the connection named `name` and stop managing it, returning false if there was none
+    /// ```
+    /// manager.remove("telemetry");
+    /// ```
+    pub fn remove(&self, name: &str) -> bool {
+        let socket = {
+            let mut inner = self.inner.borrow_mut();
+            match inner.sockets.iter().position(|socket| socket.name == name) {
+                Some(i) => inner.sockets.remove(i),
+                None => return false,
+            }
+        };
+        if let Err(e) = socket.client.close() {
+            error!(client: socket.client, "{}", e);
+        }
+        if let Some(handover) = socket.handover {
+            if let Err(e) = handover.client.close() {
+                error!(client: handover.client, "{}", e);
+            }
+        }
+        true
+    }
+    /// Move the connection named `name` to `url` without dropping it, like when the server
+    /// announces a restart.
+    ///
+    /// A second connection is opened to `url`. Once it opens, `resume` is sent on it, it takes
+    /// over sending and receiving, and the old connection is closed, so the status never leaves
+    /// Connected. If it fails to open, the old connection is kept. A later handover replaces one
+    /// that hasn't opened yet.
+    /// ```
+    /// manager.handover("game", &announced_url, Some(Message::Text(resume_cursor.into())))?;
+    /// ```
+    pub fn handover(
+        &self,
+        name: &str,
+        url: &str,
+        resume: Option<Message>,
+    ) -> Result<(), WebSocketError> {
+        self.find(name, WebSocketError::ConnectionCreationError)?;
+        let id = self.next_id();
+        let client = self.open(name, url, id)?;
+        let old = self.inner.borrow_mut().socket(name).and_then(|socket| {
+            socket.handover.replace(Handover {
+                url: url.to_string(),
+                client,
+                id,
+                resume,
+            })
+        });
+        if let Some(old) = old {
+            if let Err(e) = old.client.close() {
+                error!(client: old.client, "{}", e);
+            }
+        }
+        Ok(())
+    }
+    /// Get a handle to the connection named `name`.
+    ///
+    /// Reconnecting replaces the underlying client, so get a new handle after a reconnect
+    /// instead of keeping this one.
+    /// ```
+    /// let chat = manager.client("chat").unwrap();
+    /// ```
+    pub fn client(&self, name: &str) -> Option<EventClient> {
+        self.inner
+            .borrow_mut()
+            .socket(name)
+            .map(|socket| socket.client.clone())
+    }
+    /// The names of every connection, in the order they were added
+    pub fn names(&self) -> Vec<String> {
+        let inner = self.inner.borrow();
+        inner
+            .sockets
+            .iter()
+            .map(|socket| socket.name.clone())
+            .collect()
+    }
+    /// Get the connection status of the connection named `name`
+    /// ```
+    /// if manager.status("game") == Some(ConnectionStatus::Connected) {
+    ///     start_match();
+    /// }
+    /// ```
+    pub fn status(&self, name: &str) -> Option<ConnectionStatus> {
+        self.inner
+            .borrow_mut()
+            .socket(name)
+            .map(|socket| socket.client.status())
+    }
+    /// Take a snapshot of the health of the connection named `name`, including how many times
+    /// it's been reopened
+    /// ```
+    /// if let Some(health) = manager.health("game") {
+    ///     telemetry.send(&health);
+    /// }
+    /// ```
+    pub fn health(&self, name: &str) -> Option<Health> {
+        let (client, reconnects) = {
+            let mut inner = self.inner.borrow_mut();
+            let socket = inner.socket(name)?;
+            (socket.client.clone(), socket.reconnects)
+        };
+        Some(Health {
+            reconnects,
+            ..client.health()
+        })
+    }
+    /// Get the connection status of every connection, in the order they were added
+    pub fn statuses(&self) -> Vec<(String, ConnectionStatus)> {
+        let inner = self.inner.borrow();
+        inner
+            .sockets
+            .iter()
+            .map(|socket| (socket.name.clone(), socket.client.status()))
+            .collect()
+    }
+    /// Get all new messages from every connection since this function was last called, along with
+    /// the name of the connection that received them.
+    ///
+    /// Messages are kept until this is called, so call it regularly even when handling messages
+    /// with [`set_on_event`](Self::set_on_event).
+    pub fn receive(&self) -> Vec<(String, Message)> {
+        std::mem::take(&mut self.inner.borrow_mut().received).into()
+    }
+    /// Send a text message on the connection named `name`
+    /// ```
+    /// manager.send_string("chat", "Hello!")?;
+    /// ```
+    pub fn send_string(&self, name: &str, message: &str) -> Result<(), WebSocketError> {
+        self.send(name, Message::Text(message.into()))
+    }
+    /// Send a binary message on the connection named `name`
+    /// ```
+    /// manager.send_binary("game", input.encode())?;
+    /// ```
+    pub fn send_binary(&self, name: &str, message: Vec<u8>) -> Result<(), WebSocketError> {
+        self.send(name, Message::Binary(message.into()))
+    }
+    /// Close the connection named `name` without reopening it, while still managing it
+    /// ```
+    /// manager.close("chat")?;
+    /// ```
+    pub fn close(&self, name: &str) -> Result<(), WebSocketError> {
+        let client = self.find(name, WebSocketError::CloseError)?;
+        if let Some(socket) = self.inner.borrow_mut().socket(name) {
+            socket.closing = true;
+            socket.idle = false;
+        }
+        client.close()
+    }
+    /// Send a text message on every connection.
+    ///
+    /// The message is sent everywhere it can be, even if some connections fail.
+    /// ```
+    /// if let Err(e) = manager.broadcast_string("Hello everyone!") {
+    ///     for (name, e) in e.failures {
+    ///         warn!("{} missed it: {}", name, e);
+    ///     }
+    /// }
+    /// ```
+    pub fn broadcast_string(&self, message: &str) -> Result<(), BroadcastError> {
+        self.broadcast_string_where(|_| true, message)
+    }
+    /// Send a text message on every connection whose name `filter` returns true for
+    /// ```
+    /// manager.broadcast_string_where(|name| name.starts_with("peer-"), "Hello peers!")?;
+    /// ```
+    pub fn broadcast_string_where(
+        &self,
+        filter: impl Fn(&str) -> bool,
+        message: &str,
+    ) -> Result<(), BroadcastError> {
+        self.broadcast(filter, Message::Text(message.into()))
+    }
+    /// Send a binary message on every connection, see [`broadcast_string`](Self::broadcast_string)
+    /// ```
+    /// manager.broadcast_binary(state.encode())?;
+    /// ```
+    pub fn broadcast_binary(&self, message: Vec<u8>) -> Result<(), BroadcastError> {
+        self.broadcast_binary_where(|_| true, message)
+    }
+    /// Send a binary message on every connection whose name `filter` returns true for
+    /// ```
+    /// manager.broadcast_binary_where(|name| name != "telemetry", state.encode())?;
+    /// ```
+    pub fn broadcast_binary_where(
+        &self,
+        filter: impl Fn(&str) -> bool,
+        message: Vec<u8>,
+    ) -> Result<(), BroadcastError> {
+        self.broadcast(filter, Message::Binary(message.into()))
+    }
+    /// Close every connection without reopening them
+    /// ```
+    /// manager.close_all();
+    /// ```
+    pub fn close_all(&self) {
+        for name in self.names() {
+            if let Err(e) = self.close(&name) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// Send a message on the connection named `name`, reopening it first if it was closed for being idle
+    fn send(&self, name: &str, message: Message) -> Result<(), WebSocketError> {
+        let woken = self.wake();
+        let client = self.find(name, WebSocketError::SendError)?;
+        deliver(&client, woken.iter().any(|woken| woken == name), message)
+    }
+    /// Send a message on every client whose name `filter` returns true for, collecting the failures
+    fn broadcast(
+        &self,
+        filter: impl Fn(&str) -> bool,
+        message: Message,
+    ) -> Result<(), BroadcastError> {
+        let woken = self.wake();
+        // Copy the clients out, so a send can't conflict with handlers changing the connections
+        let clients: Vec<(String, EventClient)> = {
+            let inner = self.inner.borrow();
+            inner
+                .sockets
+                .iter()
+                .filter(|socket| filter(&socket.name))
+                .map(|socket| (socket.name.clone(), socket.client.clone()))
+                .collect()
+        };
+        let failures: Vec<(String, WebSocketError)> = clients
+            .into_iter()
+            .filter_map(|(name, client)| {
+                let woken = woken.contains(&name);
+                deliver(&client, woken, message.clone())
+                    .err()
+                    .map(|e| (name, e))
+            })
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(BroadcastError { failures })
+        }
+    }
+    /// Get the client named `name`, or an error saying there isn't one
+    fn find(
+        &self,
+        name: &str,
+        error: fn(String) -> WebSocketError,
+    ) -> Result<EventClient, WebSocketError> {
+        self.client(name)
+            .ok_or_else(|| error(format!("There is no connection named {}", name)))
+    }
+    fn next_id(&self) -> u64 {
+        let mut inner = self.inner.borrow_mut();
+        inner.next_id += 1;
+        inner.next_id
+    }
+    /// Open a connection whose events are passed back to the manager
+    fn open(&self, name: &str, url: &str, id: u64) -> Result<EventClient, WebSocketError> {
+        let client = EventClient::new(url)?;
+        client.set_label(Some(name));
+        client.set_watchdog(self.inner.borrow().watchdog);
+        // The clients are owned by the manager, so they only hold weak references back to it
+        let (inner, name_ref) = (Rc::downgrade(&self.inner), name.to_string());
+        client.add_on_connection(Box::new(move |_| {
+            if let Some(manager) = upgrade(&inner) {
+                manager.connected(&name_ref, id);
+            }
+        }));
+        let (inner, name_ref) = (Rc::downgrade(&self.inner), name.to_string());
+        client.add_on_message(Box::new(move |_, message| {
+            if let Some(manager) = upgrade(&inner) {
+                manager.received(&name_ref, id, message);
+            }
+        }));
+        let (inner, name_ref) = (Rc::downgrade(&self.inner), name.to_string());
+        client.add_on_error(Box::new(move |e| {
+            if let Some(manager) = upgrade(&inner) {
+                if manager.inner.borrow_mut().current(&name_ref, id).is_some() {
+                    manager.emit(&name_ref, ManagedEvent::Error(e));
+                }
+            }
+        }));
+        let (inner, name_ref) = (Rc::downgrade(&self.inner), name.to_string());
+        client.add_on_close(Box::new(move |e| {
+            if let Some(manager) = upgrade(&inner) {
+                manager.closed(&name_ref, id, e);
+            }
+        }));
+        let (inner, name_ref) = (Rc::downgrade(&self.inner), name.to_string());
+        client.add_on_stale(Box::new(move |_| {
+            if let Some(manager) = upgrade(&inner) {
+                manager.stale(&name_ref, id);
+            }
+        }));
+        Ok(client)
+    }
+    fn emit(&self, name: &str, event: ManagedEvent) {
+        let on_event = self.inner.borrow().on_event.clone();
+        if let Some(f) = on_event {
+            f.as_ref()(name, event);
+        }
+    }
+    fn connected(&self, name: &str, id: u64) {
+        if self.handed_over(name, id) {
+            return;
+        }
+        let window = {
+            let mut inner = self.inner.borrow_mut();
+            let window = inner
+                .reconnect
+                .map(|reconnect| reconnect.stability_window_ms);
+            if inner.current(name, id).is_none() {
+                return;
+            }
+            window
+        };
+        match window {
+            Some(window) if window > 0.0 => {
+                let inner = Rc::downgrade(&self.inner);
+                let name = name.to_string();
+                set_timeout(window, move || {
+                    if let Some(manager) = upgrade(&inner) {
+                        manager.stable(&name, id);
+                    }
+                });
+            }
+            _ => self.stable(name, id),
+        }
+        self.emit(name, ManagedEvent::Connected);
+    }
+    /// Start the reconnect delay over, if connection `id` is still open
+    fn stable(&self, name: &str, id: u64) {
+        if let Some(socket) = self.inner.borrow_mut().current(name, id) {
+            if socket.client.is_open() {
+                socket.attempts = 0;
+            }
+        }
+    }
+    /// Switch to the handover connection `id` if it's the one that opened
+    fn handed_over(&self, name: &str, id: u64) -> bool {
+        let (old, url) = {
+            let mut inner = self.inner.borrow_mut();
+            let socket = match inner.socket(name) {
+                Some(socket) => socket,
+                None => return false,
+            };
+            let handover = match socket.handover.take() {
+                Some(handover) if handover.id == id => handover,
+                other => {
+                    socket.handover = other;
+                    return false;
+                }
+            };
+            if let Some(resume) = handover.resume {
+                if let Err(e) = handover.client.send_with_priority(resume, Priority::High) {
+                    error!(client: handover.client, "{}", e);
+                }
+            }
+            let old = std::mem::replace(&mut socket.client, handover.client);
+            socket.id = handover.id;
+            socket.url = handover.url.clone();
+            socket.attempts = 0;
+            socket.reconnecting = false;
+            (old, handover.url)
+        };
+        if let Err(e) = old.close_with(close_code::NORMAL, Some("Handed over")) {
+            error!(client: old, "{}", e);
+        }
+        self.emit(name, ManagedEvent::HandedOver { url });
+        true
+    }
+    fn received(&self, name: &str, id: u64, message: Message) {
+        let parser = self.inner.borrow().retry_hint_parser.clone();
+        let hint = parser.and_then(|parser| parser.as_ref()(&message));
+        {
+            let mut inner = self.inner.borrow_mut();
+            let socket = match inner.current(name, id) {
+                Some(socket) => socket,
+                None => return,
+            };
+            if hint.is_some() {
+                socket.retry_hint = hint;
+                return;
+            }
+            inner
+                .received
+                .push_back((name.to_string(), message.clone()));
+        }
+        self.emit(name, ManagedEvent::Message(message));
+    }
+    fn closed(&self, name: &str, id: u64, e: CloseEvent) {
+        let failed =
+            self.inner
+                .borrow_mut()
+                .socket(name)
+                .and_then(|socket| match &socket.handover {
+                    Some(handover) if handover.id == id => socket.handover.take(),
+                    _ => None,
+                });
+        if let Some(handover) = failed {
+            error!(
+                "Couldn't hand {} over to {}, keeping the old connection",
+                name, handover.url
+            );
+            return;
+        }
+        if self.inner.borrow_mut().current(name, id).is_none() {
+            return;
+        }
+        let info = CloseInfo::from(&e);
+        self.emit(name, ManagedEvent::Closed(e));
+        if !self.inner.borrow().rejection_codes.contains(&info.code) {
+            self.schedule_reconnect(name, id, Some(info));
+            return;
+        }
+        trace!(
+            "{} was rejected with code {}, not reopening it",
+            name,
+            info.code
+        );
+        let on_rejected = {
+            let mut inner = self.inner.borrow_mut();
+            if let Some(socket) = inner.current(name, id) {
+                socket.closing = true;
+            }
+            inner.on_rejected.clone()
+        };
+        if let Some(f) = on_rejected {
+            f.as_ref()(name, info);
+        }
+    }
+    fn stale(&self, name: &str, id: u64) {
+        if self.inner.borrow_mut().current(name, id).is_none() {
+            return;
+        }
+        self.emit(name, ManagedEvent::Stale);
+        self.schedule_reconnect(name, id, None);
+    }
+    /// Note activity, reopening the connections closed for being idle, and return their names
+    fn wake(&self) -> Vec<String> {
+        let (idle, generation, timeout_ms) = {
+            let mut inner = self.inner.borrow_mut();
+            inner.last_active = monotonic_ms();
+            let idle: Vec<(String, String)> = inner
+                .sockets
+                .iter_mut()
+                .filter(|socket| socket.idle)
+                .map(|socket| {
+                    socket.idle = false;
+                    (socket.name.clone(), socket.url.clone())
+                })
+                .collect();
+            if idle.is_empty() && inner.idle_generation != 0 {
+                // The scheduled check is still running
+                return vec![];
+            }
+            inner.idle_generation += 1;
+            (idle, inner.idle_generation, inner.idle_timeout_ms)
+        };
+        let mut woken = vec![];
+        for (name, url) in idle {
+            let id = self.next_id();
+            match self.open(&name, &url, id) {
+                Ok(client) => {
+                    if let Some(socket) = self.inner.borrow_mut().socket(&name) {
+                        socket.client = client;
+                        socket.id = id;
+                        socket.closing = false;
+                        // A reconnect it was waiting on was dropped when it went idle
+                        socket.reconnecting = false;
+                        socket.attempts = 0;
+                    }
+                    woken.push(name);
+                }
+                Err(e) => error!("{}", e),
+            }
+        }
+        if let Some(timeout_ms) = timeout_ms {
+            self.schedule_idle_check(generation, timeout_ms);
+        }
+        woken
+    }
+    fn schedule_idle_check(&self, generation: u64, delay_ms: f64) {
+        let inner = Rc::downgrade(&self.inner);
+        set_timeout(delay_ms, move || {
+            if let Some(manager) = upgrade(&inner) {
+                manager.check_idle(generation);
+            }
+        });
+    }
+    /// Close every connection if there's been no activity for the idle timeout, or check again later
+    fn check_idle(&self, generation: u64) {
+        let clients = {
+            let mut inner = self.inner.borrow_mut();
+            let timeout_ms = match inner.idle_timeout_ms {
+                Some(timeout_ms) if inner.idle_generation == generation => timeout_ms,
+                _ => return,
+            };
+            let idle_ms = monotonic_ms() - inner.last_active;
+            if idle_ms < timeout_ms {
+                drop(inner);
+                return self.schedule_idle_check(generation, timeout_ms - idle_ms);
+            }
+            // Nothing's scheduled now, so the next activity starts a new check
+            inner.idle_generation = 0;
+            inner
+                .sockets
+                .iter_mut()
+                .filter(|socket| !socket.closing)
+                .filter_map(|socket| {
+                    socket.closing = true;
+                    socket.idle = true;
+                    // One waiting to reconnect is already closed, and stays that way now
+                    if socket.reconnecting {
+                        None
+                    } else {
+                        Some(socket.client.clone())
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        trace!("Closing {} idle connections", clients.len());
+        for client in clients {
+            if let Err(e) = client.close_with(close_code::NORMAL, Some("Idle")) {
+                error!(client: client, "{}", e);
+            }
+        }
+    }
+    /// Reopen a connection that closed or went stale, if the reconnect policy allows it.
+    /// `info` is how it closed, or None if it went stale.
+    fn schedule_reconnect(&self, name: &str, id: u64, info: Option<CloseInfo>) {
+        let (reconnect, policy, attempt, hint) = {
+            let mut inner = self.inner.borrow_mut();
+            let (reconnect, policy) = (inner.reconnect, inner.reconnect_policy.clone());
+            match inner.current(name, id) {
+                Some(socket) if !socket.closing && !socket.reconnecting => {
+                    (reconnect, policy, socket.attempts, socket.retry_hint.take())
+                }
+                _ => return,
+            }
+        };
+        let hint = info.as_ref().and_then(CloseInfo::retry_after_ms).or(hint);
+        let decision = match (policy, info) {
+            (Some(policy), Some(info)) => policy.as_ref()(name, info),
+            _ => ReconnectDecision::Backoff,
+        };
+        let (delay_ms, url) = match decision {
+            ReconnectDecision::Backoff => match reconnect {
+                Some(reconnect) if reconnect.allows(attempt) => match hint {
+                    Some(hint) if reconnect.honor_retry_hints => (hint, None),
+                    _ => (reconnect.delay_ms(attempt), None),
+                },
+                _ => return,
+            },
+            ReconnectDecision::Retry { after_ms } => (after_ms, None),
+            ReconnectDecision::RetryNewUrl(url) => (0.0, Some(url)),
+            ReconnectDecision::GiveUp => return,
+        };
+        {
+            let mut inner = self.inner.borrow_mut();
+            // The policy might have closed or removed the connection
+            let socket = match inner.current(name, id) {
+                Some(socket) if !socket.closing && !socket.reconnecting => socket,
+                _ => return,
+            };
+            socket.attempts += 1;
+            socket.reconnecting = true;
+            if let Some(url) = url {
+                socket.url = url;
+            }
+        }
+        self.emit(name, ManagedEvent::Reconnecting { attempt, delay_ms });
+        let inner = Rc::downgrade(&self.inner);
+        let name = name.to_string();
+        set_timeout(delay_ms, move || {
+            if let Some(manager) = upgrade(&inner) {
+                manager.reopen(&name, id);
+            }
+        });
+    }
+    /// Replace a closed or stale connection with a new one to its URL
+    fn reopen(&self, name: &str, id: u64) {
+        let url = match self.inner.borrow_mut().current(name, id) {
+            Some(socket) if !socket.closing => socket.url.clone(),
+            _ => return,
+        };
+        let new_id = self.next_id();
+        match self.open(name, &url, new_id) {
+            Ok(client) => {
+                if let Some(socket) = self.inner.borrow_mut().current(name, id) {
+                    socket.client = client;
+                    socket.id = new_id;
+                    socket.reconnecting = false;
+                    socket.reconnects += 1;
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Send a message on a client, queueing it until the connection opens if it was just reopened
+fn deliver(client: &EventClient, woken: bool, message: Message) -> Result<(), WebSocketError> {
+    if woken {
+        return match client.try_send(message) {
+            SendOutcome::Rejected(e) => Err(e),
+            _ => Ok(()),
+        };
+    }
+    match message {
+        Message::Text(text) => client.send_string(&text),
+        Message::Binary(data) => client.send_binary(data.into()),
+    }
+}
+
+/// A `visibilitychange` listener on the page, removed when it's dropped
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+struct PageVisible {
+    document: web_sys::EventTarget,
+    callback: Closure<dyn Fn()>,
+}
+
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+impl PageVisible {
+    /// Call `f` whenever the page becomes visible, or return None where there's no page, like in workers
+    fn listen(f: impl Fn() + 'static) -> Option<Self> {
+        let document = js_sys::Reflect::get(&js_sys::global(), &"document".into()).ok()?;
+        if document.is_undefined() {
+            return None;
+        }
+        let document: web_sys::EventTarget = document.unchecked_into();
+        let document_ref = document.clone();
+        let callback = Closure::<dyn Fn()>::new(move || {
+            let state = js_sys::Reflect::get(&document_ref, &"visibilityState".into());
+            if state.ok().and_then(|state| state.as_string()).as_deref() == Some("visible") {
+                f();
+            }
+        });
+        document
+            .add_event_listener_with_callback("visibilitychange", callback.as_ref().unchecked_ref())
+            .ok()?;
+        Some(Self { document, callback })
+    }
+}
+
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+impl Drop for PageVisible {
+    fn drop(&mut self) {
+        let _ = self.document.remove_event_listener_with_callback(
+            "visibilitychange",
+            self.callback.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+fn upgrade(inner: &Weak<RefCell<Inner>>) -> Option<SocketManager> {
+    inner.upgrade().map(|inner| SocketManager { inner })
+}
@@ -0,0 +1,99 @@
+//! Capping the memory a client holds in messages, so a backgrounded tab can't grow without bound.
+//!
+//! Messages pile up in the outbound queue while the rate limit or a full socket buffer holds them
+//! back, and inbound while delivery is [paused](EventClient::pause), before a handler is
+//! [registered](EventClient::set_buffer_early_messages), or while a [`PollingClient`](crate::PollingClient)
+//! isn't received from. The bytes held are counted in [`EventClient::stats`], and with a memory
+//! limit set, new outbound messages that don't fit are dropped, and the oldest received messages
+//! are discarded to make room for new ones.
+//! ```
+//! client.set_memory_limit(Some(64 << 20));
+//! // Later
+//! let stats = client.stats();
+//! info!("{} bytes queued, {} bytes waiting", stats.queued_outbound_bytes, stats.buffered_inbound_bytes);
+//! ```
+use crate::outbound::message_len;
+use crate::{EventClient, Message};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// Counts the bytes held by a [`PollingClient`](crate::PollingClient) wrapping the client
+pub(crate) type InboundMeter = Rc<dyn Fn() -> usize>;
+
+/// Discard the oldest messages in `queue` until it fits in `limit` alongside `other_bytes`
+/// held elsewhere, returning how many were discarded
+pub(crate) fn trim(queue: &mut VecDeque<Message>, other_bytes: usize, limit: Option<usize>) -> u64 {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return 0,
+    };
+    let mut bytes = other_bytes + queue.iter().map(message_len).sum::<usize>();
+    let mut discarded = 0;
+    while bytes > limit {
+        match queue.pop_front() {
+            Some(message) => bytes -= message_len(&message),
+            None => break,
+        }
+        discarded += 1;
+    }
+    discarded
+}
+
+impl EventClient {
+    /// Limit the bytes held in queued and unhandled messages, or set None to remove the limit.
+    ///
+    /// Messages that would be queued to send over the limit are dropped, and reported to the
+    /// on_dropped handler. Received messages over the limit are discarded oldest first.
+    /// Messages sent straight away, and the socket's own buffer, don't count.
+    /// ```
+    /// client.set_memory_limit(Some(16 << 20));
+    /// ```
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.listeners.borrow_mut().memory_limit = limit;
+    }
+    /// The memory limit, see [`set_memory_limit`](Self::set_memory_limit)
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.listeners.borrow().memory_limit
+    }
+    /// The bytes held in queued messages and received messages waiting to be handled
+    /// ```
+    /// if client.buffered_bytes() > 8 << 20 {
+    ///     request_lower_send_rate();
+    /// }
+    /// ```
+    pub fn buffered_bytes(&self) -> usize {
+        self.queued_bytes() + self.held_bytes() + self.metered_bytes()
+    }
+    /// Whether queueing `len` more bytes would go over the memory limit
+    pub(crate) fn over_memory_limit(&self, len: usize) -> bool {
+        match self.memory_limit() {
+            Some(limit) => self.buffered_bytes() + len > limit,
+            None => false,
+        }
+    }
+    /// The bytes of the messages held while paused, or until a handler is registered
+    pub(crate) fn held_bytes(&self) -> usize {
+        let paused = match &self.listeners.borrow().paused {
+            Some(paused) => paused.iter().map(message_len).sum(),
+            None => 0,
+        };
+        paused + self.early_bytes()
+    }
+    /// The bytes held by a [`PollingClient`](crate::PollingClient) wrapping the client
+    pub(crate) fn metered_bytes(&self) -> usize {
+        let meter = self.listeners.borrow().inbound_meter.clone();
+        meter.map_or(0, |meter| meter())
+    }
+    /// Count received messages discarded to stay under the memory limit
+    pub(crate) fn discarded_inbound(&self, count: u64) {
+        if count == 0 {
+            return;
+        }
+        trace!(
+            client: self,
+            "Discarded {} received messages over the memory limit",
+            count
+        );
+        self.listeners.borrow_mut().stats.discarded_inbound += count;
+    }
+}
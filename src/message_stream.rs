@@ -0,0 +1,83 @@
+//! Receiving messages from async code, as a [`Stream`] that works with the usual stream combinators.
+//! ```
+//! let mut messages = client
+//!     .message_stream()
+//!     .filter(|message| ready(matches!(message, Message::Binary(_))));
+//! while let Some(message) = messages.next().await {
+//!     world.apply(message);
+//! }
+//! ```
+use crate::{EventClient, HandlerId, Message};
+use futures_core::Stream;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// Messages received since the stream was last polled, and the task waiting for them
+#[derive(Default)]
+struct Received {
+    messages: VecDeque<Message>,
+    waker: Option<Waker>,
+}
+
+struct MessageStream {
+    client: EventClient,
+    id: HandlerId,
+    received: Rc<RefCell<Received>>,
+}
+
+impl Stream for MessageStream {
+    type Item = Message;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut received = self.received.borrow_mut();
+        if let Some(message) = received.messages.pop_front() {
+            return Poll::Ready(Some(message));
+        }
+        let mut listeners = self.client.listeners.borrow_mut();
+        if listeners.closed {
+            return Poll::Ready(None);
+        }
+        received.waker = Some(cx.waker().clone());
+        // Woken when the connection closes
+        listeners.status_wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for MessageStream {
+    fn drop(&mut self) {
+        self.client.remove_handler(self.id);
+    }
+}
+
+impl EventClient {
+    /// Get a stream of the messages received from now on, which ends once the connection has closed.
+    ///
+    /// Messages are queued until the stream is polled, alongside the other on_message handlers.
+    /// Dropping the stream stops the queueing.
+    /// ```
+    /// let mut messages = client.message_stream();
+    /// while let Some(message) = messages.next().await {
+    ///     info!("{:?}", message);
+    /// }
+    /// ```
+    pub fn message_stream(&self) -> impl Stream<Item = Message> {
+        let received = Rc::new(RefCell::new(Received::default()));
+        let received_ref = received.clone();
+        let id = self.add_on_message(Box::new(move |_client, message| {
+            let mut received = received_ref.borrow_mut();
+            received.messages.push_back(message);
+            if let Some(waker) = received.waker.take() {
+                waker.wake();
+            }
+        }));
+        MessageStream {
+            client: self.clone(),
+            id,
+            received,
+        }
+    }
+}
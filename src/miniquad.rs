@@ -0,0 +1,174 @@
+//! A backend for macroquad and miniquad, enabled with the `miniquad` feature on wasm32.
+//!
+//! Macroquad loads wasm with its own `gl.js` loader, which doesn't run wasm-bindgen glue, so the
+//! regular clients can't be used there. This [`PollingClient`] has the same API, but talks to the
+//! browser through a miniquad plugin instead. Add the plugin after `gl.js` and `sapp_jsutils.js`:
+//! ```html
+//! <script src="gl.js"></script>
+//! <script src="sapp_jsutils.js"></script>
+//! <script src="wasm_sockets.js"></script>
+//! <script>load("game.wasm");</script>
+//! ```
+//! `wasm_sockets.js` is in the `js` folder of this crate, and `sapp_jsutils.js` comes with the
+//! `sapp-jsutils` crate. Don't use anything outside this module in a macroquad build, as it would
+//! need the wasm-bindgen glue.
+//! ```
+//! let mut client = wasm_sockets::miniquad::PollingClient::new("wss://ws.ifelse.io")?;
+//! loop {
+//!     for message in client.receive() {
+//!         info!("New Message: {:#?}", message);
+//!     }
+//!     next_frame().await;
+//! }
+//! ```
+use crate::{CloseInfo, ConnectionStatus, Message, WebSocketError};
+use sapp_jsutils::JsObject;
+
+extern "C" {
+    fn wasm_sockets_connect(url: JsObject) -> i32;
+    fn wasm_sockets_status(id: i32) -> i32;
+    fn wasm_sockets_close_info(id: i32) -> JsObject;
+    fn wasm_sockets_send(id: i32, data: JsObject) -> i32;
+    fn wasm_sockets_try_recv(id: i32) -> JsObject;
+    fn wasm_sockets_close(id: i32, code: i32, reason: JsObject) -> i32;
+    fn wasm_sockets_free(id: i32);
+}
+
+/// Checked by the plugin loader against the version in `wasm_sockets.js`
+#[no_mangle]
+pub extern "C" fn wasm_sockets_crate_version() -> u32 {
+    2
+}
+
+/// A polling websocket client for macroquad and miniquad.
+///
+/// Messages are buffered by the plugin until [`receive`](Self::receive) is called.
+/// The connection is closed when the client is dropped.
+pub struct PollingClient {
+    /// The URL this client is connected to
+    pub url: String,
+    id: i32,
+}
+
+impl PollingClient {
+    /// Create a new PollingClient and connect to a WebSocket URL
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// ```
+    /// PollingClient::new("wss://ws.ifelse.io")?;
+    /// ```
+    pub fn new(url: &str) -> Result<Self, WebSocketError> {
+        let id = unsafe { wasm_sockets_connect(JsObject::string(url)) };
+        if id < 0 {
+            return Err(WebSocketError::ConnectionCreationError(
+                "Failed to connect".into(),
+            ));
+        }
+        Ok(Self {
+            url: url.to_string(),
+            id,
+        })
+    }
+    /// Get all new WebSocket messages that were received since this function was last called
+    /// ```
+    /// println!("New messages: {:#?}", client.receive());
+    /// ```
+    pub fn receive(&mut self) -> Vec<Message> {
+        let mut messages = vec![];
+        loop {
+            let received = unsafe { wasm_sockets_try_recv(self.id) };
+            if received.is_nil() {
+                return messages;
+            }
+            if received.field_u32("text") == 1 {
+                let mut text = String::new();
+                received.field("data").to_string(&mut text);
+                messages.push(Message::Text(text.into()));
+            } else {
+                let mut data = vec![];
+                received.field("data").to_byte_buffer(&mut data);
+                messages.push(Message::Binary(data.into()));
+            }
+        }
+    }
+    /// Get the client's current connection status
+    /// ```
+    /// println!("Current status: {:#?}", client.status());
+    /// ```
+    pub fn status(&self) -> ConnectionStatus {
+        match unsafe { wasm_sockets_status(self.id) } {
+            0 => ConnectionStatus::Connecting,
+            1 => ConnectionStatus::Connected,
+            2 => ConnectionStatus::Error(self.close_info()),
+            _ => ConnectionStatus::Disconnected(self.close_info()),
+        }
+    }
+    /// The details of the close from the plugin, or those of an abnormal close until one arrives
+    fn close_info(&self) -> CloseInfo {
+        let close = unsafe { wasm_sockets_close_info(self.id) };
+        if close.is_nil() {
+            return CloseInfo::abnormal();
+        }
+        let mut reason = String::new();
+        close.field("reason").to_string(&mut reason);
+        CloseInfo {
+            code: close.field_u32("code") as u16,
+            reason,
+            was_clean: close.field_u32("was_clean") == 1,
+        }
+    }
+    /// Send a text message to the server
+    /// ```
+    /// client.send_string("Hello server!")?;
+    /// ```
+    pub fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        self.send(JsObject::string(message))
+    }
+    /// Send a binary message to the server
+    /// ```
+    /// client.send_binary(vec![0x2, 0xF])?;
+    /// ```
+    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        self.send(JsObject::buffer(&message))
+    }
+    fn send(&self, data: JsObject) -> Result<(), WebSocketError> {
+        match unsafe { wasm_sockets_send(self.id, data) } {
+            0 => Ok(()),
+            _ => Err(WebSocketError::SendError(
+                "The connection isn't open".into(),
+            )),
+        }
+    }
+
+    /// Close the connection
+    /// ```
+    /// client.close()?;
+    /// ```
+    pub fn close(&self) -> Result<(), WebSocketError> {
+        self.close_code(-1, JsObject::string(""))
+    }
+    /// Close the connection with a custom close code and, optionally, a reason string
+    ///
+    /// The reason string must be at most 123 bytes long.
+    ///
+    /// ```
+    /// client.close_with(close_code::GOING_AWAY, Some("going away"))?;
+    /// ```
+    pub fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), WebSocketError> {
+        self.close_code(code as i32, JsObject::string(reason.unwrap_or("")))
+    }
+    fn close_code(&self, code: i32, reason: JsObject) -> Result<(), WebSocketError> {
+        match unsafe { wasm_sockets_close(self.id, code, reason) } {
+            0 => Ok(()),
+            _ => Err(WebSocketError::CloseError(
+                "Invalid close code or reason".into(),
+            )),
+        }
+    }
+}
+
+impl Drop for PollingClient {
+    fn drop(&mut self) {
+        unsafe { wasm_sockets_free(self.id) };
+    }
+}
@@ -0,0 +1,322 @@
+//! A native backend built on tokio-tungstenite, enabled with the `native` feature.
+//!
+//! Native clients are driven by a task spawned with [`tokio::task::spawn_local`], so they must be
+//! created from inside a [`tokio::task::LocalSet`]. Handlers run on that same thread, just like
+//! they run on the browser's event loop on wasm.
+use crate::{
+    close_code, BinaryType, CloseInfo, ConnectionStatus, EventClient, Identity, Listeners, Message,
+    Outbound, WeakEventClient, WebSocketError,
+};
+use futures_util::{SinkExt, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message as TungsteniteMessage;
+
+/// Native stand-in for [`web_sys::CloseEvent`], with the same accessors
+#[derive(Debug, Clone)]
+pub struct CloseEvent {
+    code: u16,
+    reason: String,
+    was_clean: bool,
+}
+
+impl CloseEvent {
+    /// The close code sent by the server
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+    /// The close reason sent by the server
+    pub fn reason(&self) -> String {
+        self.reason.clone()
+    }
+    /// Whether the connection was closed with a closing handshake
+    pub fn was_clean(&self) -> bool {
+        self.was_clean
+    }
+}
+
+/// Native stand-in for [`web_sys::ErrorEvent`], with the same accessors
+#[derive(Debug, Clone)]
+pub struct ErrorEvent {
+    message: String,
+}
+
+impl ErrorEvent {
+    /// A description of the error
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+/// The native counterpart of [`web_sys::WebSocket`].
+/// Outgoing frames are handed to the connection task through a channel.
+pub struct Connection {
+    outgoing: UnboundedSender<TungsteniteMessage>,
+    status: Rc<RefCell<ConnectionStatus>>,
+    /// Bytes queued but not yet written to the socket
+    buffered: Rc<Cell<u32>>,
+    /// The subprotocol the server selected
+    protocol: Rc<RefCell<String>>,
+}
+
+impl Connection {
+    fn queue(&self, message: TungsteniteMessage) -> Result<(), String> {
+        // Browsers refuse to send before the connection opens, and silently discard
+        // anything sent after it closes
+        if *self.status.borrow() == ConnectionStatus::Connecting {
+            return Err("InvalidStateError: Still in CONNECTING state".into());
+        }
+        let len = message.len() as u32;
+        if self.outgoing.send(message).is_ok() {
+            self.buffered.set(self.buffered.get().saturating_add(len));
+        }
+        Ok(())
+    }
+    pub fn buffered_amount(&self) -> u32 {
+        self.buffered.get()
+    }
+    pub fn protocol(&self) -> String {
+        self.protocol.borrow().clone()
+    }
+    pub fn send_with_str(&self, data: &str) -> Result<(), String> {
+        self.queue(TungsteniteMessage::text(data))
+    }
+    pub fn send_with_u8_array(&self, data: &[u8]) -> Result<(), String> {
+        self.queue(TungsteniteMessage::binary(data.to_vec()))
+    }
+    pub fn close(&self) -> Result<(), String> {
+        let _ = self.outgoing.send(TungsteniteMessage::Close(None));
+        Ok(())
+    }
+    pub fn close_with_code(&self, code: u16) -> Result<(), String> {
+        self.close_with_code_and_reason(code, "")
+    }
+    pub fn close_with_code_and_reason(&self, code: u16, reason: &str) -> Result<(), String> {
+        // Apply the same validation browsers do
+        if code != close_code::NORMAL && !(3000..=4999).contains(&code) {
+            return Err(format!(
+                "InvalidAccessError: The code must be either 1000, or between 3000 and 4999. {} is neither.",
+                code
+            ));
+        }
+        if reason.len() > 123 {
+            return Err(
+                "SyntaxError: The close reason must not be greater than 123 UTF-8 bytes.".into(),
+            );
+        }
+        let _ = self
+            .outgoing
+            .send(TungsteniteMessage::Close(Some(CloseFrame {
+                code: CloseCode::from(code),
+                reason: reason.into(),
+            })));
+        Ok(())
+    }
+}
+
+impl EventClient {
+    /// Create a new EventClient and connect to a WebSocket URL
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// This must be called from inside a [`tokio::task::LocalSet`].
+    /// ```
+    /// EventClient::new("wss://ws.ifelse.io")?;
+    /// ```
+    pub fn new(url: &str) -> Result<Self, WebSocketError> {
+        Self::with_protocols(url, &[])
+    }
+    /// Create a new EventClient and connect to a WebSocket URL, offering the server `protocols`
+    /// as subprotocols, in order of preference
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// This must be called from inside a [`tokio::task::LocalSet`].
+    /// ```
+    /// EventClient::with_protocols("wss://game.example.com", &["game.v2", "game.v1"])?;
+    /// ```
+    pub fn with_protocols(url: &str, protocols: &[&str]) -> Result<Self, WebSocketError> {
+        let mut request = match url.into_client_request() {
+            Ok(request) => request,
+            Err(e) => Err(WebSocketError::InvalidUrl(e.to_string()))?,
+        };
+        if !protocols.is_empty() {
+            let header = match protocols.join(", ").parse() {
+                Ok(header) => header,
+                Err(e) => Err(WebSocketError::InvalidUrl(format!(
+                    "Invalid subprotocol: {}",
+                    e
+                )))?,
+            };
+            request
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", header);
+        }
+        let (outgoing, outgoing_rx) = unbounded_channel();
+        let status = Rc::new(RefCell::new(ConnectionStatus::Connecting));
+
+        let client = Self {
+            url: Rc::new(RefCell::new(url.to_string())),
+            connection: Rc::new(RefCell::new(Connection {
+                outgoing,
+                status: status.clone(),
+                buffered: Rc::new(Cell::new(0)),
+                protocol: Rc::default(),
+            })),
+            status,
+            on_error: Rc::new(RefCell::new(None)),
+            on_connection: Rc::new(RefCell::new(None)),
+            on_message: Rc::new(RefCell::new(None)),
+            on_close: Rc::new(RefCell::new(None)),
+            listeners: Rc::new(RefCell::new(Listeners {
+                identity: Identity::next(),
+                ..Listeners::default()
+            })),
+            outbound: Rc::new(RefCell::new(Outbound::default())),
+        };
+        tokio::task::spawn_local(run(request, WeakEventClient::new(&client), outgoing_rx));
+
+        Ok(client)
+    }
+    /// Binary messages always arrive as bytes natively, so this does nothing
+    pub fn set_binary_type(&self, _binary_type: BinaryType) {}
+}
+
+/// Drive a connection until it closes, dispatching events to the client's handlers.
+///
+/// The task only holds a weak handle, so once every handle to the client is dropped, the
+/// outgoing channel closes and the connection is closed like a browser would.
+async fn run(
+    request: Request,
+    client: WeakEventClient,
+    mut outgoing: UnboundedReceiver<TungsteniteMessage>,
+) {
+    let connected = tokio_tungstenite::connect_async(request).await;
+    let stream = {
+        let client = match client.upgrade() {
+            Some(client) => client,
+            // Dropping the stream closes it
+            None => return,
+        };
+        let stream = match connected {
+            Ok((stream, response)) => {
+                let protocol = response
+                    .headers()
+                    .get("Sec-WebSocket-Protocol")
+                    .and_then(|protocol| protocol.to_str().ok())
+                    .unwrap_or_default();
+                *client.connection.borrow().protocol.borrow_mut() = protocol.to_string();
+                stream
+            }
+            Err(e) => {
+                fail(&client, e.to_string());
+                return;
+            }
+        };
+        *client.status.borrow_mut() = ConnectionStatus::Connected;
+        client.dispatch_connection();
+        stream
+    };
+
+    let (mut write, mut read) = stream.split();
+    let mut close_frame = None;
+    loop {
+        tokio::select! {
+            frame = read.next() => {
+                let client = match client.upgrade() {
+                    Some(client) => client,
+                    None => return close_dropped(&mut write).await,
+                };
+                match frame {
+                    Some(Ok(TungsteniteMessage::Text(text))) => {
+                        client.mark_received();
+                        client.dispatch_message(Message::Text(text.as_str().into()));
+                    }
+                    Some(Ok(TungsteniteMessage::Binary(data))) => {
+                        trace!(client: client, "message event, received binary: {:?}", data);
+                        client.mark_received();
+                        client.dispatch_message(Message::Binary(data));
+                    }
+                    Some(Ok(TungsteniteMessage::Close(frame))) => close_frame = frame,
+                    // Pings are answered by tungstenite itself
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        fail(&client, e.to_string());
+                        return;
+                    }
+                    None => break,
+                }
+            },
+            message = outgoing.recv() => {
+                let message = match message {
+                    Some(message) => message,
+                    // The connection was dropped along with the client
+                    None => return close_dropped(&mut write).await,
+                };
+                let len = message.len() as u32;
+                let sent = write.send(message).await;
+                let client = match client.upgrade() {
+                    Some(client) => client,
+                    None => return close_dropped(&mut write).await,
+                };
+                if let Err(e) = sent {
+                    fail(&client, e.to_string());
+                    return;
+                }
+                let buffered = client.connection.borrow().buffered.clone();
+                buffered.set(buffered.get().saturating_sub(len));
+            }
+        }
+    }
+
+    let client = match client.upgrade() {
+        Some(client) => client,
+        None => return,
+    };
+    let e = match close_frame {
+        Some(frame) => CloseEvent {
+            code: frame.code.into(),
+            reason: frame.reason.to_string(),
+            was_clean: true,
+        },
+        None => CloseEvent {
+            code: close_code::NO_STATUS,
+            reason: String::new(),
+            was_clean: true,
+        },
+    };
+    *client.status.borrow_mut() = ConnectionStatus::closed(CloseInfo::from(&e));
+    client.dispatch_close(e);
+}
+
+/// Close a connection whose client was dropped, with a normal close like browsers send
+async fn close_dropped<S>(write: &mut S)
+where
+    S: SinkExt<TungsteniteMessage> + Unpin,
+{
+    let frame = CloseFrame {
+        code: CloseCode::from(close_code::NORMAL),
+        reason: "".into(),
+    };
+    // Nothing is left to report a failure to
+    write
+        .send(TungsteniteMessage::Close(Some(frame)))
+        .await
+        .ok();
+}
+
+/// Report a failed connection the same way browsers do: an error event followed by an unclean close
+fn fail(client: &EventClient, message: String) {
+    *client.status.borrow_mut() = ConnectionStatus::Error(CloseInfo::abnormal());
+    client.dispatch_error(ErrorEvent { message });
+    let e = CloseEvent {
+        code: close_code::ABNORMAL,
+        reason: String::new(),
+        was_clean: false,
+    };
+    *client.status.borrow_mut() = ConnectionStatus::closed(CloseInfo::from(&e));
+    client.dispatch_close(e);
+}
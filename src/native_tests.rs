@@ -0,0 +1,180 @@
+//! Tests for the native backend, which need the `echo-server` binary running:
+//! ```text
+//! cargo run --target x86_64-unknown-linux-gnu --features echo-server --bin echo-server
+//! cargo test --target x86_64-unknown-linux-gnu --features native --lib
+//! ```
+use crate as wasm_sockets;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::task::LocalSet;
+use wasm_sockets::testing::wait_for_status;
+use wasm_sockets::{close_code, ConnectionStatus, EventClient, Message, WebSocketError};
+
+/// The echo server the tests connect to, which is where the `echo-server` binary listens by default
+const ECHO_URL: &str = match option_env!("WASM_SOCKETS_ECHO_URL") {
+    Some(url) => url,
+    None => "ws://127.0.0.1:9001",
+};
+
+/// Wait up to a second for `f` to return true
+async fn wait_until(f: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if f() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("Timed out");
+}
+
+#[tokio::test]
+async fn connection_task() {
+    LocalSet::new()
+        .run_until(async {
+            let mut client = EventClient::new(ECHO_URL).unwrap();
+            assert_eq!(client.status(), ConnectionStatus::Connecting);
+            let received = Rc::new(RefCell::new(vec![]));
+            let received_ref = received.clone();
+            client.set_on_message(Some(Box::new(move |_client, message| {
+                received_ref.borrow_mut().push(message)
+            })));
+            let closes = Rc::new(RefCell::new(vec![]));
+            let closes_ref = closes.clone();
+            client.set_on_close(Some(Box::new(move |e| {
+                closes_ref
+                    .borrow_mut()
+                    .push((e.code(), e.reason(), e.was_clean()))
+            })));
+            wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert_eq!(client.protocol(), "");
+
+            client.send_string("Hello").unwrap();
+            client.send_binary(vec![1, 2, 3]).unwrap();
+            wait_until(|| received.borrow().len() == 2).await;
+            assert_eq!(
+                *received.borrow(),
+                [
+                    Message::Text("Hello".into()),
+                    Message::Binary(vec![1, 2, 3].into())
+                ]
+            );
+
+            // The server sends the close frame back
+            client.close_with(4000, Some("Done")).unwrap();
+            wait_until(|| !closes.borrow().is_empty()).await;
+            assert_eq!(*closes.borrow(), [(4000, "Done".to_string(), true)]);
+            assert!(client.status().is_terminal());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn selected_protocol() {
+    LocalSet::new()
+        .run_until(async {
+            let client = EventClient::with_protocols(ECHO_URL, &["v2", "v1"]).unwrap();
+            wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+                .await
+                .unwrap();
+            // The echo server selects the first one offered
+            assert_eq!(client.protocol(), "v2");
+            client.close().unwrap();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn close_code_validation() {
+    LocalSet::new()
+        .run_until(async {
+            let client = EventClient::new(ECHO_URL).unwrap();
+            wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+                .await
+                .unwrap();
+            // Browsers only allow 1000 and 3000-4999
+            for code in [close_code::GOING_AWAY, close_code::ABNORMAL, 2999, 5000] {
+                assert!(matches!(
+                    client.close_with(code, None),
+                    Err(WebSocketError::CloseError(_))
+                ));
+            }
+            let reason = "a".repeat(124);
+            assert!(matches!(
+                client.close_with(close_code::NORMAL, Some(&reason)),
+                Err(WebSocketError::CloseError(_))
+            ));
+            // Nothing was sent, so it's still open
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(client.status(), ConnectionStatus::Connected);
+
+            client
+                .close_with(close_code::NORMAL, Some(&reason[..123]))
+                .unwrap();
+            wait_until(|| client.status().is_terminal()).await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn failed_connection() {
+    LocalSet::new()
+        .run_until(async {
+            // Nothing listens on port 1
+            let mut client = EventClient::new("ws://127.0.0.1:1").unwrap();
+            let events = Rc::new(RefCell::new(vec![]));
+            let events_ref = events.clone();
+            client.set_on_error(Some(Box::new(move |_e| {
+                events_ref.borrow_mut().push("error".to_string())
+            })));
+            let events_ref = events.clone();
+            client.set_on_close(Some(Box::new(move |e| {
+                events_ref
+                    .borrow_mut()
+                    .push(format!("close {} {}", e.code(), e.was_clean()))
+            })));
+            wait_until(|| events.borrow().len() == 2).await;
+            // An error, then an unclean close, like in browsers
+            assert_eq!(*events.borrow(), ["error", "close 1006 false"]);
+            assert!(client.status().is_terminal());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn buffered_amount() {
+    LocalSet::new()
+        .run_until(async {
+            let client = EventClient::new(ECHO_URL).unwrap();
+            wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert_eq!(client.buffered_amount(), 0);
+            // Counted until the connection task writes it
+            client.send_binary(vec![0; 64 * 1024]).unwrap();
+            client.send_string("Hello").unwrap();
+            assert_eq!(client.buffered_amount(), 64 * 1024 + 5);
+            wait_until(|| client.buffered_amount() == 0).await;
+            client.close().unwrap();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn dropped_client() {
+    LocalSet::new()
+        .run_until(async {
+            let client = EventClient::new(ECHO_URL).unwrap();
+            wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+                .await
+                .unwrap();
+            let listeners = Rc::downgrade(&client.listeners);
+            let connection = Rc::downgrade(&client.connection);
+            drop(client);
+            // The connection task only holds a weak handle, so it ends and frees the client
+            wait_until(|| listeners.upgrade().is_none() && connection.upgrade().is_none()).await;
+        })
+        .await;
+}
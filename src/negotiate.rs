@@ -0,0 +1,194 @@
+//! Choosing a [`Codec`] by the subprotocol the server selected.
+//!
+//! [`ProtocolCodecs`] pairs each offered subprotocol with a codec, and a [`NegotiatedPollingClient`]
+//! offers them all and decodes and encodes with whichever the server picked. A server can move
+//! from one format to another gradually, selecting the new protocol once it supports it, without
+//! clients needing an update at the same time.
+//! ```
+//! let codecs = ProtocolCodecs::new()
+//!     .codec::<Cbor>("game.cbor")
+//!     .codec::<Json>("game.json");
+//! let mut client = NegotiatedPollingClient::<ServerMessage, _>::new("wss://game.example.com", codecs)?;
+//! // Once per frame
+//! for message in client.receive().into_iter().flatten() {
+//!     world.apply(message);
+//! }
+//! ```
+use crate::{
+    Codec, ConnectionStatus, DecodeError, EncodeError, Message, PollingClient, WebSocketError,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+/// A list of codecs, built up by [`ProtocolCodecs::codec`] as `(Newest, (..., (Oldest, ())))`
+pub trait CodecList {
+    /// Decode a message with the codec `index` places from the start of the list
+    fn decode_with<T: DeserializeOwned>(index: usize, message: &Message) -> Result<T, DecodeError>;
+    /// Encode a value with the codec `index` places from the start of the list
+    fn encode_with<T: Serialize + ?Sized>(index: usize, value: &T) -> Result<Message, EncodeError>;
+}
+
+impl CodecList for () {
+    fn decode_with<T: DeserializeOwned>(
+        _index: usize,
+        _message: &Message,
+    ) -> Result<T, DecodeError> {
+        Err(DecodeError::new("No codecs are registered"))
+    }
+    fn encode_with<T: Serialize + ?Sized>(
+        _index: usize,
+        _value: &T,
+    ) -> Result<Message, EncodeError> {
+        Err(EncodeError::new("No codecs are registered"))
+    }
+}
+
+impl<C: Codec, L: CodecList> CodecList for (C, L) {
+    fn decode_with<T: DeserializeOwned>(index: usize, message: &Message) -> Result<T, DecodeError> {
+        match index {
+            0 => C::decode(message),
+            _ => L::decode_with(index - 1, message),
+        }
+    }
+    fn encode_with<T: Serialize + ?Sized>(index: usize, value: &T) -> Result<Message, EncodeError> {
+        match index {
+            0 => C::encode(value),
+            _ => L::encode_with(index - 1, value),
+        }
+    }
+}
+
+/// A codec for each subprotocol offered to the server, in order of preference
+/// ```
+/// let codecs = ProtocolCodecs::new()
+///     .codec::<Json>("json")
+///     .codec::<Cbor>("cbor");
+/// client.add_on_message(Box::new(move |client, message| {
+///     match codecs.decode::<ServerMessage>(&client.protocol(), &message) {
+///         Ok(message) => world.apply(message),
+///         Err(e) => warn!("{}", e),
+///     }
+/// }));
+/// ```
+pub struct ProtocolCodecs<L> {
+    /// The subprotocols, in the order they were registered
+    protocols: Vec<String>,
+    codecs: PhantomData<fn() -> L>,
+}
+
+impl ProtocolCodecs<()> {
+    /// Start an empty list of codecs
+    pub fn new() -> Self {
+        Self {
+            protocols: vec![],
+            codecs: PhantomData,
+        }
+    }
+}
+
+impl Default for ProtocolCodecs<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L: CodecList> ProtocolCodecs<L> {
+    /// Use the codec `C` when the server selects `protocol`. The first codec registered is also
+    /// used for any other protocol, like the empty one before the connection opens.
+    pub fn codec<C: Codec>(mut self, protocol: &str) -> ProtocolCodecs<(C, L)> {
+        self.protocols.push(protocol.to_string());
+        ProtocolCodecs {
+            protocols: self.protocols,
+            codecs: PhantomData,
+        }
+    }
+    /// The subprotocols to offer, in the order they were registered
+    pub fn protocols(&self) -> Vec<&str> {
+        self.protocols.iter().map(String::as_str).collect()
+    }
+    /// Where the codec for `protocol` is in the list, which is in the reverse of the order they
+    /// were registered
+    fn index(&self, protocol: &str) -> usize {
+        let position = self.protocols.iter().position(|p| p == protocol);
+        self.protocols.len().saturating_sub(1) - position.unwrap_or(0)
+    }
+    /// Decode a message with the codec for `protocol`
+    pub fn decode<T: DeserializeOwned>(
+        &self,
+        protocol: &str,
+        message: &Message,
+    ) -> Result<T, DecodeError> {
+        L::decode_with(self.index(protocol), message)
+    }
+    /// Encode a value with the codec for `protocol`
+    pub fn encode<T: Serialize + ?Sized>(
+        &self,
+        protocol: &str,
+        value: &T,
+    ) -> Result<Message, EncodeError> {
+        L::encode_with(self.index(protocol), value)
+    }
+}
+
+/// A [`PollingClient`] that offers a subprotocol for each of its codecs, and decodes the messages
+/// it receives into `T` with the codec for the one the server selected
+pub struct NegotiatedPollingClient<T, L> {
+    client: PollingClient,
+    codecs: ProtocolCodecs<L>,
+    types: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned, L: CodecList> NegotiatedPollingClient<T, L> {
+    /// Create a new NegotiatedPollingClient and connect to a WebSocket URL, offering the
+    /// subprotocols of `codecs`
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// ```
+    /// let codecs = ProtocolCodecs::new().codec::<Json>("json");
+    /// NegotiatedPollingClient::<ServerMessage, _>::new("wss://ws.ifelse.io", codecs)?;
+    /// ```
+    pub fn new(url: &str, codecs: ProtocolCodecs<L>) -> Result<Self, WebSocketError> {
+        let client = PollingClient::with_protocols(url, &codecs.protocols())?;
+        Ok(Self {
+            client,
+            codecs,
+            types: PhantomData,
+        })
+    }
+    /// Receive the decoded messages, in order, along with an error for each one that couldn't be decoded
+    pub fn receive(&mut self) -> Vec<Result<T, DecodeError>> {
+        let protocol = self.protocol();
+        self.client
+            .receive()
+            .iter()
+            .map(|message| self.codecs.decode(&protocol, message))
+            .collect()
+    }
+    /// Encode a value with the codec for the selected subprotocol and send it
+    /// ```
+    /// client.send(&ClientMessage::Ready)?;
+    /// ```
+    pub fn send<U: Serialize + ?Sized>(&self, value: &U) -> Result<(), WebSocketError> {
+        match self.codecs.encode(&self.protocol(), value)? {
+            Message::Text(text) => self.client.send_string(&text),
+            Message::Binary(data) => self.client.send_binary(data.to_vec()),
+        }
+    }
+    /// The subprotocol the server selected, see [`EventClient::protocol`](crate::EventClient::protocol)
+    pub fn protocol(&self) -> String {
+        self.client.protocol()
+    }
+    /// The client underneath, for everything else
+    pub fn client(&self) -> &PollingClient {
+        &self.client
+    }
+    /// Get the client's current connection status
+    pub fn status(&self) -> ConnectionStatus {
+        self.client.status()
+    }
+    /// Unwrap the client, to receive raw messages again
+    pub fn into_inner(self) -> PollingClient {
+        self.client
+    }
+}
@@ -0,0 +1,702 @@
+//! Processing of outgoing messages before they reach the socket.
+//!
+//! A [rate limit](EventClient::set_rate_limit) caps how many messages and bytes are sent per second,
+//! so a bug in a game loop can't flood the server. When messages have to queue, those sent with a
+//! higher [`Priority`] go first.
+//!
+//! With batching enabled, small binary messages sent during a frame are packed into one binary
+//! message using [length-prefixed framing](crate::Framing), and sent together on the next tick.
+//! This saves the per-message overhead for games that send many tiny updates per frame.
+//! The server has to split the batches apart again, like [`EventClient::set_framing`] does.
+//!
+//! [`EventClient::send_tracked`] returns a [`SendTicket`], which resolves once the message has
+//! left the socket's buffer, for when something has to wait until a message is on its way.
+//! [`EventClient::try_send`] says whether a message went out right away, was queued, or was
+//! rejected, for callers that handle each differently.
+//! ```
+//! client.set_batching(Some(Batching::default()));
+//! for update in updates {
+//!     // These are all sent as one message on the next animation frame
+//!     client.send_binary(update.encode())?;
+//! }
+//! ```
+use crate::chunked::{DRAIN_POLL_MS, MAX_BUFFERED};
+use crate::timer::{now_ms, request_animation_frame, set_timeout, sleep};
+use crate::{
+    ConnectionStatus, DropReason, EventClient, FramedWriter, Framing, Message, WebSocketError,
+};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// When batched messages are sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlushTick {
+    /// Before the browser paints the next frame, or after 16ms where there are no animation frames
+    AnimationFrame,
+    /// After this many milliseconds
+    Interval(f64),
+}
+
+/// Settings for batching outgoing messages, see [`EventClient::set_batching`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Batching {
+    /// How messages are packed into a batch
+    pub framing: Framing,
+    /// When batches are sent
+    pub tick: FlushTick,
+    /// Binary messages larger than this many bytes are sent on their own
+    pub max_message_size: usize,
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self {
+            framing: Framing::default(),
+            tick: FlushTick::AnimationFrame,
+            max_message_size: 1024,
+        }
+    }
+}
+
+/// What happens to messages sent over the rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverLimit {
+    /// Queue them, and send them in order as soon as the limit allows
+    Queue,
+    /// Silently drop them
+    Drop,
+    /// Drop them and return [`WebSocketError::SendError`]
+    Error,
+}
+
+/// A limit on outgoing messages, see [`EventClient::set_rate_limit`]
+///
+/// Each limit allows bursts of up to one second's worth of messages or bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// How many messages can be sent per second, or None for no limit
+    pub messages_per_sec: Option<f64>,
+    /// How many bytes can be sent per second, or None for no limit
+    pub bytes_per_sec: Option<f64>,
+    /// What happens to messages over the limit
+    pub over_limit: OverLimit,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            messages_per_sec: None,
+            bytes_per_sec: None,
+            over_limit: OverLimit::Queue,
+        }
+    }
+}
+
+/// A token bucket that refills at `rate` tokens per second, up to `rate` tokens
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+}
+
+impl Bucket {
+    fn new(rate: f64) -> Self {
+        Self { rate, tokens: rate }
+    }
+    fn refill(&mut self, elapsed_ms: f64) {
+        self.tokens = (self.tokens + self.rate * elapsed_ms / 1000.0).min(self.rate);
+    }
+    /// How many tokens have to be available to take `amount`.
+    /// Anything larger than the bucket only waits for a full bucket, so it isn't stuck forever.
+    fn needed(&self, amount: f64) -> f64 {
+        amount.min(self.rate)
+    }
+    /// How long until `amount` can be taken, in milliseconds
+    fn wait_ms(&self, amount: f64) -> f64 {
+        ((self.needed(amount) - self.tokens) / self.rate * 1000.0).max(0.0)
+    }
+}
+
+/// The buckets for a [`RateLimit`]
+struct Limiter {
+    limit: RateLimit,
+    messages: Option<Bucket>,
+    bytes: Option<Bucket>,
+    updated: f64,
+}
+
+impl Limiter {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            messages: limit.messages_per_sec.map(Bucket::new),
+            bytes: limit.bytes_per_sec.map(Bucket::new),
+            updated: now_ms(),
+        }
+    }
+    fn refill(&mut self) {
+        let now = now_ms();
+        let elapsed = now - self.updated;
+        self.updated = now;
+        for bucket in self.messages.iter_mut().chain(self.bytes.iter_mut()) {
+            bucket.refill(elapsed);
+        }
+    }
+    /// Take the tokens for a message of `len` bytes, returning false if the limit doesn't allow it yet
+    fn try_take(&mut self, len: usize) -> bool {
+        self.refill();
+        if self.wait_ms(len) > 0.0 {
+            return false;
+        }
+        if let Some(bucket) = &mut self.messages {
+            bucket.tokens -= 1.0;
+        }
+        if let Some(bucket) = &mut self.bytes {
+            bucket.tokens -= len as f64;
+        }
+        true
+    }
+    /// How long until a message of `len` bytes can be sent, in milliseconds
+    fn wait_ms(&self, len: usize) -> f64 {
+        let messages = self.messages.map_or(0.0, |bucket| bucket.wait_ms(1.0));
+        let bytes = self.bytes.map_or(0.0, |bucket| bucket.wait_ms(len as f64));
+        messages.max(bytes)
+    }
+}
+
+/// How urgently a message should be sent, see [`EventClient::send_with_priority`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Priority {
+    /// Input and other messages that shouldn't wait behind other traffic
+    High,
+    /// The priority of messages sent with `send_string` and `send_binary`
+    #[default]
+    Normal,
+    /// Telemetry and other messages that can wait
+    Low,
+}
+
+/// The priority of each lane, highest first
+const LANES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+/// The outgoing state shared by every handle to a client
+#[derive(Default)]
+pub(crate) struct Outbound {
+    batching: Option<Batching>,
+    batch: Option<FramedWriter>,
+    flush_scheduled: bool,
+    limiter: Option<Limiter>,
+    /// Messages waiting to be sent, oldest first, in a lane for each [`Priority`]
+    lanes: [VecDeque<Queued>; 3],
+    drain_scheduled: bool,
+    /// The bytes of every message sent or queued to be sent, which [`SendTicket`]s count towards
+    accepted: u64,
+    /// The bytes of every message passed to the socket, or discarded after it was accepted
+    written: u64,
+    /// The number of messages dropped by the rate limit
+    dropped: u64,
+    /// Whether the connection is closing gracefully, so new messages are refused
+    closing: bool,
+}
+
+impl Outbound {
+    /// The highest priority lane with messages waiting
+    fn next_lane(&self) -> Option<usize> {
+        self.lanes.iter().position(|lane| !lane.is_empty())
+    }
+    /// Discard the queued messages whose time to live has run out, returning them
+    fn discard_expired(&mut self) -> Vec<Message> {
+        let now = now_ms();
+        let mut discarded = vec![];
+        for lane in &mut self.lanes {
+            let (expired, kept) = std::mem::take(lane)
+                .into_iter()
+                .partition(|queued| match queued.expires_at {
+                    Some(expires_at) => expires_at <= now,
+                    None => false,
+                });
+            *lane = kept;
+            discarded.extend(expired.into_iter().map(|queued: Queued| queued.message));
+        }
+        if !discarded.is_empty() {
+            // Counted as written, so send tickets don't wait for them
+            self.written += discarded.iter().map(message_len).sum::<usize>() as u64;
+        }
+        discarded
+    }
+    /// Take the rate limit's tokens for a message of `len` bytes, returning false if it isn't allowed yet
+    fn try_take(&mut self, len: usize) -> bool {
+        match &mut self.limiter {
+            Some(limiter) => limiter.try_take(len),
+            None => true,
+        }
+    }
+    /// Count a message that was passed to the socket
+    pub(crate) fn wrote(&mut self, message: &Message) {
+        self.written += message_len(message) as u64;
+    }
+}
+
+/// Resolves once a message sent with [`EventClient::send_tracked`] has left the socket's buffer.
+///
+/// The browser doesn't say when a message has actually been sent, so this watches the socket's
+/// [`buffered_amount`](EventClient::buffered_amount) instead: once the bytes sent before and with
+/// the message have drained from it, they've been handed to the network stack. It resolves to an
+/// error if the connection ends first.
+#[must_use = "a ticket does nothing unless it's awaited or checked"]
+pub struct SendTicket {
+    client: EventClient,
+    /// The number of accepted bytes that have to be written and drained
+    target: u64,
+}
+
+impl SendTicket {
+    /// Whether the message has left the socket's buffer, for checking from a game loop
+    pub fn is_sent(&self) -> bool {
+        let written = self.client.outbound.borrow().written;
+        written.saturating_sub(self.client.buffered_amount() as u64) >= self.target
+    }
+}
+
+impl Future for SendTicket {
+    type Output = Result<(), WebSocketError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.is_sent() {
+            return Poll::Ready(Ok(()));
+        }
+        if self.client.status().is_terminal() {
+            return Poll::Ready(Err(WebSocketError::SendError(
+                "The connection closed before the message was sent".into(),
+            )));
+        }
+        let waker = cx.waker().clone();
+        set_timeout(DRAIN_POLL_MS, move || waker.wake());
+        Poll::Pending
+    }
+}
+
+/// What happened to a message sent with [`EventClient::try_send`]
+#[derive(Debug, Clone)]
+pub enum SendOutcome {
+    /// The message was passed to the socket right away
+    SentImmediately,
+    /// The message is queued behind the rate limit, other queued messages, or a full buffer,
+    /// and will be sent as soon as they allow
+    Queued,
+    /// The connection hasn't opened yet, so the message is queued until it does.
+    /// It's dropped if the connection fails instead.
+    QueuedWhileConnecting,
+    /// The message won't be sent
+    Rejected(WebSocketError),
+}
+
+/// What happened to a message that wasn't refused
+enum Accepted {
+    Sent,
+    Queued,
+    /// Dropped by the rate limit with [`OverLimit::Drop`], or to stay under the memory limit
+    Dropped(DropReason),
+}
+
+/// A message waiting in a lane
+struct Queued {
+    message: Message,
+    /// When the message is discarded instead of sent, from [`now_ms`]
+    expires_at: Option<f64>,
+}
+
+pub(crate) fn message_len(message: &Message) -> usize {
+    match message {
+        Message::Text(text) => text.len(),
+        Message::Binary(data) => data.len(),
+    }
+}
+
+impl EventClient {
+    /// Limit how many messages and bytes are sent per second, or set None to remove the limit.
+    ///
+    /// This applies to [`send_string`](EventClient::send_string),
+    /// [`send_binary`](EventClient::send_binary) and [`send_with_priority`](EventClient::send_with_priority).
+    /// A batch counts as one message. Messages already queued are sent as the new limit allows.
+    /// ```
+    /// client.set_rate_limit(Some(RateLimit {
+    ///     messages_per_sec: Some(30.0),
+    ///     bytes_per_sec: Some(64.0 * 1024.0),
+    ///     over_limit: OverLimit::Drop,
+    /// }));
+    /// ```
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        self.outbound.borrow_mut().limiter = limit.map(Limiter::new);
+        self.drain();
+    }
+    /// The number of messages waiting to be sent, in every priority
+    /// ```
+    /// if client.queued_messages() > 100 {
+    ///     warn!("Sending faster than the rate limit");
+    /// }
+    /// ```
+    pub fn queued_messages(&self) -> usize {
+        self.discard_expired();
+        self.outbound.borrow().lanes.iter().map(VecDeque::len).sum()
+    }
+    /// The bytes of the messages waiting to be sent
+    pub(crate) fn queued_bytes(&self) -> usize {
+        let outbound = self.outbound.borrow();
+        outbound
+            .lanes
+            .iter()
+            .flatten()
+            .map(|queued| message_len(&queued.message))
+            .sum()
+    }
+    /// Send a message ahead of, or behind, other queued messages.
+    ///
+    /// Messages queue while the rate limit holds them back with [`OverLimit::Queue`], and low
+    /// priority messages also queue while more than 1 MiB is waiting in the socket's buffer.
+    /// Queued messages are sent highest priority first, and in the order they were sent within a priority.
+    /// [`send_string`](EventClient::send_string) and [`send_binary`](EventClient::send_binary)
+    /// send with [`Priority::Normal`]. These messages are never [batched](EventClient::set_batching).
+    /// ```
+    /// client.send_with_priority(Message::Binary(input.encode().into()), Priority::High)?;
+    /// client.send_with_priority(Message::Text(stats.to_json().into()), Priority::Low)?;
+    /// ```
+    pub fn send_with_priority(
+        &self,
+        message: Message,
+        priority: Priority,
+    ) -> Result<(), WebSocketError> {
+        // Batched messages have normal priority, so only high priority messages go ahead of them
+        if priority != Priority::High {
+            self.flush()?;
+        }
+        self.send_limited(message, priority)
+    }
+    /// Send a message with normal priority, returning a ticket that resolves once it has left the socket's buffer.
+    ///
+    /// Fails if the message was dropped by the rate limit with [`OverLimit::Drop`].
+    /// ```
+    /// client.send_tracked(Message::Text("goodbye".into()))?.await?;
+    /// client.close()?;
+    /// ```
+    pub fn send_tracked(&self, message: Message) -> Result<SendTicket, WebSocketError> {
+        self.flush()?;
+        let dropped = self.outbound.borrow().dropped;
+        self.send_limited(message, Priority::Normal)?;
+        let outbound = self.outbound.borrow();
+        if outbound.dropped != dropped {
+            return Err(WebSocketError::SendError(
+                "The message was dropped by the rate limit".into(),
+            ));
+        }
+        Ok(SendTicket {
+            client: self.clone(),
+            target: outbound.accepted,
+        })
+    }
+    /// Send a message like [`send_with_priority`](Self::send_with_priority), but discard it if it's
+    /// still queued after `ttl_ms` milliseconds.
+    ///
+    /// This is for messages that are worse than useless once they're old, like movement inputs
+    /// held back by the rate limit or a full buffer.
+    /// ```
+    /// client.send_with_ttl(Message::Binary(input.encode().into()), Priority::High, 500.0)?;
+    /// ```
+    pub fn send_with_ttl(
+        &self,
+        message: Message,
+        priority: Priority,
+        ttl_ms: f64,
+    ) -> Result<(), WebSocketError> {
+        if priority != Priority::High {
+            self.flush()?;
+        }
+        self.send_limited_until(message, priority, Some(now_ms() + ttl_ms), false)
+            .map(|_| ())
+    }
+    /// Send a message with normal priority, saying whether it was sent right away, queued, or rejected.
+    ///
+    /// Unlike [`send_binary`](Self::send_binary), messages sent while the connection is still opening
+    /// are queued until it opens, and this never batches. Messages dropped by the rate limit with
+    /// [`OverLimit::Drop`] are rejected.
+    /// ```
+    /// match client.try_send(Message::Binary(input.encode().into())) {
+    ///     SendOutcome::Rejected(_) => pending_inputs.push(input),
+    ///     _ => {}
+    /// }
+    /// ```
+    pub fn try_send(&self, message: Message) -> SendOutcome {
+        if let Err(e) = self.flush() {
+            return SendOutcome::Rejected(e);
+        }
+        match self.send_limited_until(message, Priority::Normal, None, true) {
+            Ok(Accepted::Sent) => SendOutcome::SentImmediately,
+            Ok(Accepted::Queued) if self.status() == ConnectionStatus::Connecting => {
+                SendOutcome::QueuedWhileConnecting
+            }
+            Ok(Accepted::Queued) => SendOutcome::Queued,
+            Ok(Accepted::Dropped(DropReason::MemoryLimit)) => SendOutcome::Rejected(
+                WebSocketError::SendError("The message would go over the memory limit".into()),
+            ),
+            Ok(Accepted::Dropped(_)) => SendOutcome::Rejected(WebSocketError::SendError(
+                "The message was dropped by the rate limit".into(),
+            )),
+            Err(e) => SendOutcome::Rejected(e),
+        }
+    }
+    /// Close the connection once everything sent before has left, waiting at most `linger_ms` milliseconds.
+    ///
+    /// New messages are refused from the start. Then the current batch is sent, and this waits for
+    /// the queued messages to be sent and the socket's buffer to drain, so a final message isn't
+    /// lost to the close. Whatever is left after `linger_ms` is dropped.
+    /// ```
+    /// client.send_string("goodbye")?;
+    /// client.close_graceful(close_code::NORMAL, Some("logged out"), 2000.0).await?;
+    /// ```
+    pub async fn close_graceful(
+        &self,
+        code: u16,
+        reason: Option<&str>,
+        linger_ms: f64,
+    ) -> Result<(), WebSocketError> {
+        if let Err(e) = self.flush() {
+            error!(client: self, "{}", e);
+        }
+        self.outbound.borrow_mut().closing = true;
+        let deadline = now_ms() + linger_ms;
+        while self.is_open()
+            && (self.queued_messages() > 0 || self.buffered_amount() > 0)
+            && now_ms() < deadline
+        {
+            sleep(DRAIN_POLL_MS).await;
+        }
+        self.close_with(code, reason)
+    }
+    /// Whether messages of `priority` have to wait for the socket's buffer to drain
+    fn buffer_full(&self, priority: Priority) -> bool {
+        priority == Priority::Low && self.buffered_amount() > MAX_BUFFERED
+    }
+    /// Send a message if nothing holds it back, otherwise queue it or apply the rate limit's [`OverLimit`] policy
+    pub(crate) fn send_limited(
+        &self,
+        message: Message,
+        priority: Priority,
+    ) -> Result<(), WebSocketError> {
+        self.send_limited_until(message, priority, None, false)
+            .map(|_| ())
+    }
+    /// Like [`send_limited`](Self::send_limited), but a queued message is discarded once `expires_at` passes.
+    /// With `queue_while_connecting`, a message sent before the connection opens is queued until it does.
+    fn send_limited_until(
+        &self,
+        message: Message,
+        priority: Priority,
+        expires_at: Option<f64>,
+        queue_while_connecting: bool,
+    ) -> Result<Accepted, WebSocketError> {
+        self.check_outbound(&message)?;
+        if self.outbound.borrow().closing {
+            return Err(WebSocketError::SendError(
+                "The connection is closing".into(),
+            ));
+        }
+        let buffer_full = self.buffer_full(priority)
+            || (queue_while_connecting && self.status() == ConnectionStatus::Connecting);
+        self.discard_expired();
+        let over_memory_limit = self.over_memory_limit(message_len(&message));
+        let mut outbound = self.outbound.borrow_mut();
+        // Nothing can overtake queued messages of the same or a higher priority
+        let waiting = buffer_full
+            || outbound.lanes[..=priority as usize]
+                .iter()
+                .any(|lane| !lane.is_empty());
+        let len = message_len(&message) as u64;
+        if !waiting && outbound.try_take(len as usize) {
+            drop(outbound);
+            self.send_now(&message)?;
+            self.outbound.borrow_mut().accepted += len;
+            return Ok(Accepted::Sent);
+        }
+        let over_limit = match &outbound.limiter {
+            Some(limiter) if !waiting => limiter.limit.over_limit,
+            _ => OverLimit::Queue,
+        };
+        match over_limit {
+            OverLimit::Queue if over_memory_limit => {
+                trace!(client: self, "Dropped a message over the memory limit");
+                drop(outbound);
+                self.report_dropped(message, DropReason::MemoryLimit);
+                Ok(Accepted::Dropped(DropReason::MemoryLimit))
+            }
+            OverLimit::Queue => {
+                outbound.lanes[priority as usize].push_back(Queued {
+                    message,
+                    expires_at,
+                });
+                outbound.accepted += len;
+                drop(outbound);
+                self.schedule_drain();
+                Ok(Accepted::Queued)
+            }
+            OverLimit::Drop => {
+                trace!(client: self, "Dropped a message over the rate limit");
+                outbound.dropped += 1;
+                drop(outbound);
+                self.report_dropped(message, DropReason::RateLimit);
+                Ok(Accepted::Dropped(DropReason::RateLimit))
+            }
+            OverLimit::Error => Err(WebSocketError::SendError(
+                "The message is over the rate limit".into(),
+            )),
+        }
+    }
+    /// Discard the queued messages whose time to live has run out, and report them
+    fn discard_expired(&self) {
+        let expired = self.outbound.borrow_mut().discard_expired();
+        if !expired.is_empty() {
+            trace!(client: self, "Discarded {} expired messages", expired.len());
+        }
+        for message in expired {
+            self.report_dropped(message, DropReason::Expired);
+        }
+    }
+    /// Send queued messages once the next one is allowed
+    fn schedule_drain(&self) {
+        let mut outbound = self.outbound.borrow_mut();
+        if outbound.drain_scheduled {
+            return;
+        }
+        let lane = match outbound.next_lane() {
+            Some(lane) => lane,
+            None => return,
+        };
+        let wait_ms = if self.buffer_full(LANES[lane]) {
+            DRAIN_POLL_MS
+        } else {
+            match (&outbound.limiter, outbound.lanes[lane].front()) {
+                (Some(limiter), Some(queued)) => limiter.wait_ms(message_len(&queued.message)),
+                _ => 0.0,
+            }
+        };
+        outbound.drain_scheduled = true;
+        let client = self.clone();
+        set_timeout(wait_ms, move || {
+            client.outbound.borrow_mut().drain_scheduled = false;
+            client.drain();
+        });
+    }
+    /// Send as many queued messages as are allowed, highest priority first.
+    /// Nothing is sent until the connection opens, which drains the queue again.
+    pub(crate) fn drain(&self) {
+        if self.status() == ConnectionStatus::Connecting {
+            return;
+        }
+        loop {
+            self.discard_expired();
+            let message = {
+                let mut outbound = self.outbound.borrow_mut();
+                let lane = match outbound.next_lane() {
+                    Some(lane) if !self.buffer_full(LANES[lane]) => lane,
+                    _ => break,
+                };
+                let len = outbound.lanes[lane]
+                    .front()
+                    .map_or(0, |queued| message_len(&queued.message));
+                if !outbound.try_take(len) {
+                    break;
+                }
+                outbound.lanes[lane]
+                    .pop_front()
+                    .map(|queued| queued.message)
+            };
+            if let Some(Err(e)) = message.map(|message| self.send_now(&message)) {
+                // The connection is gone, so the rest of the queue can't be sent either
+                error!(client: self, "{}", e);
+                self.discard_queued();
+                return;
+            }
+        }
+        self.schedule_drain();
+    }
+    /// Drop every queued message, reporting them as dropped because the connection closed
+    pub(crate) fn discard_queued(&self) {
+        let mut lanes = std::mem::take(&mut self.outbound.borrow_mut().lanes);
+        for queued in lanes.iter_mut().flat_map(|lane| lane.drain(..)) {
+            self.report_dropped(queued.message, DropReason::Closed);
+        }
+    }
+    /// Batch small binary messages and send them together on every tick, or set None to send every message right away.
+    ///
+    /// Text messages and binary messages over [`Batching::max_message_size`] aren't batched, but
+    /// anything batched before them is sent first, so messages always arrive in the order they were sent.
+    /// Turning batching off sends the current batch.
+    /// ```
+    /// client.set_batching(Some(Batching {
+    ///     tick: FlushTick::Interval(50.0),
+    ///     ..Batching::default()
+    /// }));
+    /// ```
+    pub fn set_batching(&self, batching: Option<Batching>) {
+        if let Err(e) = self.flush() {
+            error!(client: self, "{}", e);
+        }
+        let mut outbound = self.outbound.borrow_mut();
+        outbound.batching = batching;
+        outbound.batch = batching.map(|batching| FramedWriter::new(batching.framing));
+    }
+    /// Send the current batch right away, instead of waiting for the next tick
+    /// ```
+    /// client.flush()?;
+    /// ```
+    pub fn flush(&self) -> Result<(), WebSocketError> {
+        let frame = match &mut self.outbound.borrow_mut().batch {
+            Some(batch) if !batch.is_empty() => batch.finish(),
+            _ => return Ok(()),
+        };
+        self.send_limited(Message::Binary(frame.into()), Priority::Normal)
+    }
+    /// Add a binary message to the current batch, returning false if it has to be sent on its own
+    pub(crate) fn batch(&self, message: &[u8]) -> Result<bool, WebSocketError> {
+        let mut outbound = self.outbound.borrow_mut();
+        let batching = match outbound.batching {
+            // Sending fails while the socket isn't open, which shouldn't be hidden until the next tick
+            Some(batching)
+                if message.len() <= batching.max_message_size
+                    && self.is_open()
+                    && !outbound.closing =>
+            {
+                batching
+            }
+            _ => return Ok(false),
+        };
+        // A batch that would go over the size limit is left for the message to be sent on its own
+        let max_outbound = self.listeners.borrow().size_limits.max_outbound;
+        if let (Some(max), Some(batch)) = (max_outbound, &outbound.batch) {
+            if batch.len() + batching.framing.prefix.len() + message.len() > max {
+                return Ok(false);
+            }
+        }
+        if let Some(batch) = &mut outbound.batch {
+            batch
+                .push(message)
+                .map_err(|e| WebSocketError::SendError(e.to_string()))?;
+        }
+        if !outbound.flush_scheduled {
+            outbound.flush_scheduled = true;
+            let client = self.clone();
+            let flush = move || {
+                client.outbound.borrow_mut().flush_scheduled = false;
+                if let Err(e) = client.flush() {
+                    error!(client: client, "{}", e);
+                }
+            };
+            match batching.tick {
+                FlushTick::AnimationFrame => request_animation_frame(flush),
+                FlushTick::Interval(delay_ms) => set_timeout(delay_ms, flush),
+            }
+        }
+        Ok(true)
+    }
+}
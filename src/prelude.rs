@@ -0,0 +1,14 @@
+//! The items most code using this crate needs, to import in one line.
+//! ```
+//! use wasm_sockets::prelude::*;
+//!
+//! let client = EventClient::new("wss://ws.ifelse.io")?;
+//! client.add_on_message(Box::new(|_client, message: Message| {
+//!     info!("{:?}", message);
+//! }));
+//! ```
+pub use crate::{
+    BinaryType, CloseEvent, CloseInfo, ConnectionStatus, Ctx, ErrorEvent, ErrorKind, EventClient,
+    EventClientBuilder, HandlerId, Message, PollingClient, WebSocketError, WebSocketHandler,
+    WsClient,
+};
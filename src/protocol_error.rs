@@ -0,0 +1,145 @@
+//! Errors in the messages a connection receives, as opposed to errors of the connection itself.
+//!
+//! These don't close the connection, so they're passed to their own handler instead of on_error.
+//! ```
+//! client.set_on_protocol_error(Some(Box::new(|e| {
+//!     warn!("Skipped a message: {}", e);
+//! })));
+//! ```
+use crate::{ErrorKind, EventClient, FramingError};
+use std::fmt;
+use std::rc::Rc;
+
+/// A problem with a received message, see [`EventClient::set_on_protocol_error`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// A binary message couldn't be split with the client's framing, see [`EventClient::set_framing`]
+    Framing(FramingError),
+    /// A text message wasn't valid Unicode, with the invalid characters replaced by U+FFFD,
+    /// see [`EventClient::set_text_policy`]
+    InvalidText(String),
+    /// A message couldn't be decoded by the decoder registered for its tag, see [`SchemaRegistry::register_decoder`](crate::SchemaRegistry::register_decoder)
+    Decode {
+        /// The message's tag
+        tag: u8,
+        /// Why it couldn't be decoded
+        error: String,
+    },
+    /// A message couldn't be decoded by a [`Codec`](crate::Codec), see [`decoding_handler`](crate::decoding_handler)
+    Codec(String),
+    /// A text message wasn't valid base64, and was skipped, see [`EventClient::set_base64_bridge`]
+    Base64(String),
+    /// A Blob message couldn't be read, and was skipped, with the reason the browser gave
+    BlobRead(String),
+    /// A compressed message couldn't be decompressed, or was too large once it was, and was
+    /// skipped, see [`EventClient::set_decompression`]
+    Decompress(String),
+    /// A message was over the inbound size limit, and was dropped or truncated, see [`EventClient::set_size_limits`]
+    TooLarge {
+        /// The size of the message, in bytes
+        size: usize,
+        /// The limit, in bytes
+        max: usize,
+    },
+    /// A message handler panicked, with the panic's message.
+    ///
+    /// Panics can only be caught with the `native` feature, since they abort the program on wasm.
+    HandlerPanic(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Framing(e) => write!(f, "Failed to split a framed message: {}", e),
+            Self::InvalidText(_) => write!(f, "Received a text message that isn't valid Unicode"),
+            Self::Decode { tag, error } => {
+                write!(f, "Failed to decode a message tagged {}: {}", tag, error)
+            }
+            Self::Codec(e) => write!(f, "Failed to decode a message: {}", e),
+            Self::Base64(e) => write!(f, "Failed to decode a base64 message: {}", e),
+            Self::BlobRead(e) => write!(f, "Failed to read a Blob message: {}", e),
+            Self::Decompress(e) => write!(f, "Failed to decompress a message: {}", e),
+            Self::TooLarge { size, max } => write!(
+                f,
+                "Received a {} byte message, over the {} byte limit",
+                size, max
+            ),
+            Self::HandlerPanic(e) => write!(f, "A message handler panicked: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Framing(e) => Some(e),
+            Self::InvalidText(_)
+            | Self::Decode { .. }
+            | Self::Codec(_)
+            | Self::Base64(_)
+            | Self::BlobRead(_)
+            | Self::Decompress(_)
+            | Self::TooLarge { .. }
+            | Self::HandlerPanic(_) => None,
+        }
+    }
+}
+
+impl ProtocolError {
+    /// What sort of problem this is, see [`ErrorKind`].
+    /// A panicking handler is an application error, and the rest are protocol errors.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::HandlerPanic(_) => ErrorKind::Application,
+            _ => ErrorKind::Protocol,
+        }
+    }
+}
+
+impl From<FramingError> for ProtocolError {
+    fn from(e: FramingError) -> Self {
+        Self::Framing(e)
+    }
+}
+
+/// A handler bound to the on_protocol_error event
+pub type ProtocolErrorHandler = Box<dyn Fn(ProtocolError)>;
+
+impl EventClient {
+    /// Set a handler for problems with received messages, overwriting the previous handler.
+    ///
+    /// The message is skipped, and the connection stays open. Without a handler, these are logged.
+    /// You can set [None](std::option) to go back to logging them.
+    /// ```
+    /// client.set_on_protocol_error(Some(Box::new(|e| {
+    ///     report_to_server(e.to_string());
+    /// })));
+    /// ```
+    pub fn set_on_protocol_error(&mut self, f: Option<ProtocolErrorHandler>) {
+        self.listeners.borrow_mut().on_protocol_error = f.map(Rc::new);
+    }
+    /// Run the handler for a protocol error, or log it if there isn't one
+    pub(crate) fn dispatch_protocol_error(&self, e: ProtocolError) {
+        let handler = self.listeners.borrow().on_protocol_error.clone();
+        match handler {
+            Some(f) => f.as_ref()(e),
+            None => error!(client: self, "{}", e),
+        }
+    }
+    /// Run message handlers, passing a panic on as a protocol error where panics can be caught
+    pub(crate) fn catch_panic(&self, f: impl FnOnce()) {
+        #[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+        if let Err(panic) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+            let message = match panic.downcast::<String>() {
+                Ok(message) => *message,
+                Err(panic) => match panic.downcast::<&str>() {
+                    Ok(message) => message.to_string(),
+                    Err(_) => "Unknown panic".to_string(),
+                },
+            };
+            self.dispatch_protocol_error(ProtocolError::HandlerPanic(message));
+        }
+        #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+        f();
+    }
+}
@@ -0,0 +1,96 @@
+//! Racing connections to several equivalent endpoints, like regional relays, and keeping the first to open.
+//!
+//! Attempts start one after another, a stagger delay apart, so a slow or unreachable endpoint
+//! doesn't hold up the others. When an attempt fails, the next one starts right away.
+//! ```
+//! let winner = EventClient::race(&["wss://eu.example.com", "wss://us.example.com"], 250.0).await?;
+//! info!("Connected to {}", winner.url);
+//! storage.set_item("preferred_relay", &winner.url)?;
+//! ```
+use crate::timer::{now_ms, sleep};
+use crate::{EventClient, WebSocketError};
+
+/// How often the attempts are checked, in milliseconds
+const POLL_MS: f64 = 10.0;
+
+/// The connection that won a race, see [`EventClient::race`]
+pub struct RaceWinner {
+    /// The open client
+    pub client: EventClient,
+    /// The position of the winning URL in the list raced
+    pub index: usize,
+    /// The winning URL, to try first next time
+    pub url: String,
+}
+
+fn failed(attempt: &Option<EventClient>) -> bool {
+    match attempt {
+        Some(client) => client.status().is_terminal(),
+        None => true,
+    }
+}
+
+impl EventClient {
+    /// Connect to several equivalent URLs, starting a new attempt every `stagger_ms` milliseconds,
+    /// and return the first connection to open.
+    ///
+    /// The other attempts are closed. URLs are tried in order, so put the preferred one first.
+    /// The winner is already open, so on_connection handlers registered on it are run right
+    /// after the current task.
+    /// Fails once every attempt has failed.
+    /// ```
+    /// let winner = EventClient::race(&relays, 300.0).await?;
+    /// winner.client.send_string("Hello!")?;
+    /// ```
+    pub async fn race(urls: &[&str], stagger_ms: f64) -> Result<RaceWinner, WebSocketError> {
+        // None for attempts that couldn't be started
+        let mut attempts: Vec<Option<EventClient>> = vec![];
+        let mut last_error = None;
+        let mut next_start = now_ms();
+        loop {
+            let now = now_ms();
+            if attempts.len() < urls.len() && (now >= next_start || attempts.iter().all(failed)) {
+                match EventClient::new(urls[attempts.len()]) {
+                    Ok(client) => attempts.push(Some(client)),
+                    Err(e) => {
+                        last_error = Some(e);
+                        attempts.push(None);
+                    }
+                }
+                next_start = now + stagger_ms;
+                continue;
+            }
+            let winner = attempts.iter().position(|attempt| match attempt {
+                Some(client) => client.is_open(),
+                None => false,
+            });
+            if let Some(index) = winner {
+                let mut client = None;
+                for (i, attempt) in attempts.into_iter().enumerate() {
+                    match attempt {
+                        Some(attempt) if i == index => client = Some(attempt),
+                        Some(attempt) => {
+                            if let Err(e) = attempt.close() {
+                                error!(client: attempt, "{}", e);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                return Ok(RaceWinner {
+                    client: client.expect("winner not found"),
+                    index,
+                    url: urls[index].to_string(),
+                });
+            }
+            if attempts.len() == urls.len() && attempts.iter().all(failed) {
+                return Err(last_error.unwrap_or_else(|| {
+                    WebSocketError::ConnectionCreationError(
+                        "Every connection attempt failed".into(),
+                    )
+                }));
+            }
+            sleep(POLL_MS).await;
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! A handle for using a client from another thread, for builds with wasm threads (`+atomics`).
+//!
+//! A browser WebSocket belongs to the thread that created it, so the client itself has to stay
+//! there. [`EventClient::remote`] returns a [`RemoteClient`], a `Send + Sync` handle that can be
+//! moved into a worker thread, like any other value passed to a spawned thread. Messages and status
+//! changes are handed over through shared memory, and the owning thread performs the worker's
+//! sends on a short timer.
+//! ```
+//! let remote = client.remote();
+//! wasm_thread::spawn(move || loop {
+//!     for message in remote.receive() {
+//!         remote.send_string("Got it").ok();
+//!     }
+//! });
+//! ```
+use crate::timer::set_timeout;
+use crate::{ConnectionStatus, EventClient, HandlerId, Message, WebSocketError};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+
+/// How often the owning thread hands messages over, in milliseconds
+const FLUSH_INTERVAL_MS: f64 = 4.0;
+
+/// A `Send + Sync` handle to an [`EventClient`] owned by another thread, with a [`PollingClient`](crate::PollingClient)-like API.
+///
+/// Cloning this is cheap. Messages are handed over until every handle is dropped or the
+/// connection closes.
+#[derive(Clone)]
+pub struct RemoteClient {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// The state handed between the owning thread and the remote handles
+struct Shared {
+    status: ConnectionStatus,
+    incoming: VecDeque<Message>,
+    outgoing: VecDeque<Command>,
+}
+
+/// Something a remote handle asked the owning thread to do
+enum Command {
+    Send(Message),
+    Close(Option<u16>, Option<String>),
+}
+
+impl RemoteClient {
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Get all new WebSocket messages that were handed over since this function was last called
+    pub fn receive(&self) -> Vec<Message> {
+        std::mem::take(&mut self.lock().incoming).into()
+    }
+    /// Get the client's connection status, as of the last hand over
+    pub fn status(&self) -> ConnectionStatus {
+        self.lock().status.clone()
+    }
+    /// Queue a text message to be sent by the owning thread
+    pub fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        self.send(Message::Text(message.into()))
+    }
+    /// Queue a binary message to be sent by the owning thread
+    pub fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        self.send(Message::Binary(message.into()))
+    }
+    fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        let mut shared = self.lock();
+        if !shared.status.is_connected() {
+            return Err(WebSocketError::SendError("Not connected".into()));
+        }
+        shared.outgoing.push_back(Command::Send(message));
+        Ok(())
+    }
+    /// Ask the owning thread to close the connection
+    pub fn close(&self) {
+        self.lock().outgoing.push_back(Command::Close(None, None));
+    }
+    /// Ask the owning thread to close the connection with a custom close code and, optionally, a reason string.
+    /// An invalid code or reason is logged by the owning thread.
+    pub fn close_with(&self, code: u16, reason: Option<&str>) {
+        self.lock()
+            .outgoing
+            .push_back(Command::Close(Some(code), reason.map(str::to_string)));
+    }
+}
+
+impl EventClient {
+    /// Create a `Send + Sync` handle to this client, for using it from another thread.
+    ///
+    /// This client must stay on the current thread, which hands messages over every few milliseconds.
+    /// ```
+    /// let remote = client.remote();
+    /// ```
+    pub fn remote(&self) -> RemoteClient {
+        let shared = Arc::new(Mutex::new(Shared {
+            status: self.status(),
+            incoming: VecDeque::new(),
+            outgoing: VecDeque::new(),
+        }));
+        // Messages wait here until the lock is free
+        let staged = Rc::new(RefCell::new(VecDeque::new()));
+        let staged_ref = staged.clone();
+        let id = self.add_on_message(Box::new(move |_, m| {
+            staged_ref.borrow_mut().push_back(m);
+        }));
+        flush(self.clone(), shared.clone(), staged, id);
+        RemoteClient { shared }
+    }
+}
+
+/// Hand staged messages and the status over and run queued commands, then schedule the next flush
+fn flush(
+    client: EventClient,
+    shared: Arc<Mutex<Shared>>,
+    staged: Rc<RefCell<VecDeque<Message>>>,
+    id: HandlerId,
+) {
+    // The browser's main thread isn't allowed to wait, so skip this flush if a worker holds the lock
+    let closed = match shared.try_lock() {
+        Ok(mut shared) => hand_over(&client, &mut shared, &staged),
+        Err(TryLockError::Poisoned(e)) => hand_over(&client, &mut e.into_inner(), &staged),
+        Err(TryLockError::WouldBlock) => false,
+    };
+    if closed || Arc::strong_count(&shared) == 1 {
+        client.remove_handler(id);
+        return;
+    }
+    set_timeout(FLUSH_INTERVAL_MS, move || flush(client, shared, staged, id));
+}
+
+/// Returns true once the connection has closed and everything was handed over
+fn hand_over(
+    client: &EventClient,
+    shared: &mut Shared,
+    staged: &RefCell<VecDeque<Message>>,
+) -> bool {
+    shared.status = client.status();
+    shared.incoming.extend(staged.borrow_mut().drain(..));
+    for command in shared.outgoing.drain(..) {
+        let result = match command {
+            Command::Send(Message::Text(text)) => client.send_string(&text),
+            Command::Send(Message::Binary(data)) => client.send_binary(data.into()),
+            Command::Close(Some(code), reason) => client.close_with(code, reason.as_deref()),
+            Command::Close(None, _) => client.close(),
+        };
+        if let Err(e) = result {
+            error!(client: client, "{}", e);
+        }
+    }
+    shared.status.is_terminal()
+}
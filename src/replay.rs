@@ -0,0 +1,223 @@
+//! Recording connection events and replaying them without a server.
+//!
+//! A [`Recorder`] captures everything a client receives into a [`Recording`], which can be
+//! serialized (with the `serde` feature) and later fed back into handlers by a [`ReplayClient`].
+//! This makes netcode regression tests reproducible without a live server.
+use crate::{error_message, timer, CloseInfo, ConnectionStatus, EventClient, Message};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// An event that happened on a connection
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConnectionEvent {
+    /// The connection was opened
+    Connected,
+    /// A message was received
+    Message(Message),
+    /// The connection failed, with the error message if the browser provided one
+    Error(String),
+    /// The connection was closed
+    Closed(CloseInfo),
+}
+
+/// A [`ConnectionEvent`] along with when it happened
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordedEvent {
+    /// Milliseconds since the recording started
+    pub time: f64,
+    /// The event that happened
+    pub event: ConnectionEvent,
+}
+
+/// A log of every event received by a client
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Recording {
+    /// The URL the recorded client was connected to
+    pub url: String,
+    /// Every recorded event, in the order they happened
+    pub events: Vec<RecordedEvent>,
+}
+
+/// Records every event received by an [`EventClient`]
+pub struct Recorder {
+    recording: Rc<RefCell<Recording>>,
+}
+
+impl Recorder {
+    /// Start recording a client's events.
+    ///
+    /// Handlers already set on the client keep running after each event is recorded,
+    /// so attach the recorder after setting your own handlers.
+    /// This also works with a [`PollingClient`](crate::PollingClient) through its `event_client` field.
+    /// ```
+    /// let recorder = Recorder::attach(&client);
+    /// // ...
+    /// let recording = recorder.recording();
+    /// ```
+    pub fn attach(client: &EventClient) -> Self {
+        let recording = Rc::new(RefCell::new(Recording {
+            url: client.url.borrow().clone(),
+            events: vec![],
+        }));
+        let started_at = timer::now_ms();
+        let record = {
+            let recording = recording.clone();
+            Rc::new(move |event: ConnectionEvent| {
+                recording.borrow_mut().events.push(RecordedEvent {
+                    time: timer::now_ms() - started_at,
+                    event,
+                });
+            })
+        };
+
+        let previous = client.on_connection.borrow_mut().take();
+        let record_ref = record.clone();
+        *client.on_connection.borrow_mut() = Some(Box::new(move |client| {
+            record_ref(ConnectionEvent::Connected);
+            if let Some(f) = &previous {
+                f.as_ref()(client);
+            }
+        }));
+
+        let previous = client.on_message.borrow_mut().take();
+        let record_ref = record.clone();
+        *client.on_message.borrow_mut() = Some(Box::new(move |client, message| {
+            record_ref(ConnectionEvent::Message(message.clone()));
+            if let Some(f) = &previous {
+                f.as_ref()(client, message);
+            }
+        }));
+
+        let previous = client.on_error.borrow_mut().take();
+        let record_ref = record.clone();
+        *client.on_error.borrow_mut() = Some(Box::new(move |e| {
+            record_ref(ConnectionEvent::Error(error_message(&e)));
+            if let Some(f) = &previous {
+                f.as_ref()(e);
+            }
+        }));
+
+        let previous = client.on_close.borrow_mut().take();
+        *client.on_close.borrow_mut() = Some(Box::new(move |e| {
+            record(ConnectionEvent::Closed(CloseInfo::from(&e)));
+            if let Some(f) = &previous {
+                f.as_ref()(e);
+            }
+        }));
+
+        Self { recording }
+    }
+    /// Get everything recorded so far
+    pub fn recording(&self) -> Recording {
+        self.recording.borrow().clone()
+    }
+}
+
+#[derive(Default)]
+struct ReplayHandlers {
+    on_error: Option<Box<dyn Fn(String)>>,
+    on_connection: Option<Box<dyn Fn()>>,
+    on_message: Option<Box<dyn Fn(Message)>>,
+    on_close: Option<Box<dyn Fn(CloseInfo)>>,
+}
+
+/// Replays a [`Recording`] into handlers, as if the events were coming from a server
+pub struct ReplayClient {
+    recording: Recording,
+    /// The connection status at the current point of the replay
+    pub status: Rc<RefCell<ConnectionStatus>>,
+    handlers: Rc<RefCell<ReplayHandlers>>,
+}
+
+impl ReplayClient {
+    /// Create a new ReplayClient for a recording. Nothing is replayed until [`play`](Self::play) is called.
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            status: Rc::new(RefCell::new(ConnectionStatus::Connecting)),
+            handlers: Rc::new(RefCell::new(ReplayHandlers::default())),
+        }
+    }
+    /// Set an on_error event handler, which receives the recorded error message
+    pub fn set_on_error(&mut self, f: Option<Box<dyn Fn(String)>>) {
+        self.handlers.borrow_mut().on_error = f;
+    }
+    /// Set an on_connection event handler
+    pub fn set_on_connection(&mut self, f: Option<Box<dyn Fn()>>) {
+        self.handlers.borrow_mut().on_connection = f;
+    }
+    /// Set an on_message event handler
+    pub fn set_on_message(&mut self, f: Option<Box<dyn Fn(Message)>>) {
+        self.handlers.borrow_mut().on_message = f;
+    }
+    /// Set an on_close event handler
+    pub fn set_on_close(&mut self, f: Option<Box<dyn Fn(CloseInfo)>>) {
+        self.handlers.borrow_mut().on_close = f;
+    }
+    /// Get the connection status at the current point of the replay
+    pub fn status(&self) -> ConnectionStatus {
+        self.status.borrow().clone()
+    }
+    /// Replay the recording with its original timing divided by `speed`,
+    /// so a speed of 1.0 plays in real time and 10.0 plays ten times faster.
+    /// Speeds that aren't positive and finite replay everything immediately, like [`play_instant`](Self::play_instant).
+    /// ```
+    /// replay.play(4.0);
+    /// ```
+    pub fn play(&self, speed: f64) {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return self.play_instant();
+        }
+        for recorded in &self.recording.events {
+            let status = self.status.clone();
+            let handlers = self.handlers.clone();
+            let event = recorded.event.clone();
+            timer::set_timeout(recorded.time / speed, move || {
+                dispatch(&status, &handlers, event)
+            });
+        }
+    }
+    /// Replay every event synchronously, in order, before returning.
+    /// This is the most deterministic way to drive handlers in tests.
+    pub fn play_instant(&self) {
+        for recorded in &self.recording.events {
+            dispatch(&self.status, &self.handlers, recorded.event.clone());
+        }
+    }
+}
+
+fn dispatch(
+    status: &Rc<RefCell<ConnectionStatus>>,
+    handlers: &Rc<RefCell<ReplayHandlers>>,
+    event: ConnectionEvent,
+) {
+    let handlers = handlers.borrow();
+    match event {
+        ConnectionEvent::Connected => {
+            *status.borrow_mut() = ConnectionStatus::Connected;
+            if let Some(f) = &handlers.on_connection {
+                f();
+            }
+        }
+        ConnectionEvent::Message(message) => {
+            if let Some(f) = &handlers.on_message {
+                f(message);
+            }
+        }
+        ConnectionEvent::Error(message) => {
+            *status.borrow_mut() = ConnectionStatus::Error(CloseInfo::abnormal());
+            if let Some(f) = &handlers.on_error {
+                f(message);
+            }
+        }
+        ConnectionEvent::Closed(info) => {
+            *status.borrow_mut() = ConnectionStatus::closed(info.clone());
+            if let Some(f) = &handlers.on_close {
+                f(info);
+            }
+        }
+    }
+}
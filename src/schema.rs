@@ -0,0 +1,122 @@
+//! Routing binary messages by their first byte, the tag most binary game protocols start with.
+//!
+//! A [`SchemaRegistry`] maps each tag to a handler, which receives the rest of the message.
+//! Messages with a tag that isn't registered, and empty messages, go to the fallback handler.
+//! ```
+//! let mut registry = SchemaRegistry::new();
+//! registry.register_decoder(0x01, PlayerMoved::decode, |_client, moved| {
+//!     world.move_player(moved.id, moved.position);
+//! });
+//! registry.register(0x02, Box::new(|_client, payload| {
+//!     chat.push(String::from_utf8_lossy(&payload).into_owned());
+//! }));
+//! registry.set_fallback(Some(Box::new(|_client, message| {
+//!     warn!("Unknown message: {:?}", message);
+//! })));
+//! client.set_schema_registry(Some(registry));
+//! ```
+use crate::{Bytes, EventClient, ProtocolError};
+use std::fmt;
+use std::rc::Rc;
+
+/// A handler for binary messages with one tag, called with the message after the tag
+pub type SchemaHandler = Box<dyn Fn(&EventClient, Bytes)>;
+
+/// A handler for binary messages that no registered handler takes, called with the whole message
+pub type SchemaFallback = Box<dyn Fn(&EventClient, Bytes)>;
+
+/// Handlers for binary messages, chosen by their first byte, see [`EventClient::set_schema_registry`]
+pub struct SchemaRegistry {
+    handlers: Vec<Option<SchemaHandler>>,
+    fallback: Option<SchemaFallback>,
+}
+
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        Self {
+            handlers: (0..=u8::MAX).map(|_| None).collect(),
+            fallback: None,
+        }
+    }
+}
+
+impl SchemaRegistry {
+    /// Create a registry without any handlers
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set the handler for messages tagged `tag`, returning the handler it replaced
+    pub fn register(&mut self, tag: u8, f: SchemaHandler) -> Option<SchemaHandler> {
+        self.handlers[tag as usize].replace(f)
+    }
+    /// Set the handler for messages tagged `tag`, decoding them with `decode` first.
+    ///
+    /// Messages that fail to decode are passed to the on_protocol_error handler as
+    /// [`ProtocolError::Decode`].
+    /// ```
+    /// registry.register_decoder(0x03, |payload| bincode::deserialize::<Snapshot>(payload), |_client, snapshot| {
+    ///     world.apply(snapshot);
+    /// });
+    /// ```
+    pub fn register_decoder<T, E: fmt::Display>(
+        &mut self,
+        tag: u8,
+        decode: impl Fn(&[u8]) -> Result<T, E> + 'static,
+        f: impl Fn(&EventClient, T) + 'static,
+    ) -> Option<SchemaHandler> {
+        self.register(
+            tag,
+            Box::new(move |client, payload| match decode(&payload) {
+                Ok(value) => f(client, value),
+                Err(e) => client.dispatch_protocol_error(ProtocolError::Decode {
+                    tag,
+                    error: e.to_string(),
+                }),
+            }),
+        )
+    }
+    /// Remove the handler for messages tagged `tag`, returning it
+    pub fn unregister(&mut self, tag: u8) -> Option<SchemaHandler> {
+        self.handlers[tag as usize].take()
+    }
+    /// Whether a handler is registered for `tag`
+    pub fn is_registered(&self, tag: u8) -> bool {
+        self.handlers[tag as usize].is_some()
+    }
+    /// Set the handler for messages that no registered handler takes, overwriting the previous handler.
+    ///
+    /// Without one, those messages are logged and dropped.
+    pub fn set_fallback(&mut self, f: Option<SchemaFallback>) {
+        self.fallback = f;
+    }
+    /// Pass a binary message to the handler for its tag, or the fallback handler
+    pub fn dispatch(&self, client: &EventClient, message: Bytes) {
+        let handler = message
+            .first()
+            .and_then(|tag| self.handlers[*tag as usize].as_ref());
+        match (handler, &self.fallback) {
+            (Some(f), _) => f(client, message.slice(1..)),
+            (None, Some(fallback)) => fallback(client, message),
+            (None, None) => match message.first() {
+                Some(tag) => {
+                    error!(client: client, "No handler registered for messages tagged {}", tag)
+                }
+                None => error!(client: client, "Received an empty message, without a tag"),
+            },
+        }
+    }
+}
+
+impl EventClient {
+    /// Route binary messages through a schema registry, or set None to stop.
+    ///
+    /// While this is set, binary messages go to the registry instead of the on_message handlers,
+    /// unless a filtered subscription takes them first. Text messages still go to on_message.
+    /// ```
+    /// client.set_schema_registry(Some(registry));
+    /// ```
+    pub fn set_schema_registry(&self, registry: Option<SchemaRegistry>) {
+        self.listeners.borrow_mut().schemas = registry.map(Rc::new);
+        self.replay_early();
+    }
+}
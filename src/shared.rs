@@ -0,0 +1,90 @@
+//! `Send + Sync` handles to the clients, enabled with the `send-wrapper` feature.
+//!
+//! The clients are built on `Rc` and can't be sent between threads, but some frameworks and
+//! executors require `Send` types even on wasm32-unknown-unknown, where everything runs on one
+//! thread anyway. These wrappers are `Send + Sync`, and panic if they're ever used or dropped
+//! on a different thread than the one that created them, so they can't cause data races.
+//! ```
+//! let client = SharedEventClient::new(EventClient::new("wss://ws.ifelse.io")?);
+//! spawn_requiring_send(async move {
+//!     client.send_string("Hello, World!").unwrap();
+//! });
+//! ```
+use crate::{EventClient, PollingClient};
+use send_wrapper::SendWrapper;
+use std::ops::{Deref, DerefMut};
+
+/// A `Send + Sync` handle to an [`EventClient`], which derefs to the client
+pub struct SharedEventClient(SendWrapper<EventClient>);
+
+impl SharedEventClient {
+    /// Wrap a client so it can be stored where `Send` is required
+    pub fn new(client: EventClient) -> Self {
+        Self(SendWrapper::new(client))
+    }
+    /// Unwrap the client, panicking if this is called on a different thread than the one that created it
+    pub fn into_inner(self) -> EventClient {
+        self.0.take()
+    }
+}
+
+impl From<EventClient> for SharedEventClient {
+    fn from(client: EventClient) -> Self {
+        Self::new(client)
+    }
+}
+
+/// Clones the handle, panicking if this is called on a different thread than the one that created it
+impl Clone for SharedEventClient {
+    fn clone(&self) -> Self {
+        Self::new(EventClient::clone(self))
+    }
+}
+
+impl Deref for SharedEventClient {
+    type Target = EventClient;
+
+    fn deref(&self) -> &EventClient {
+        &self.0
+    }
+}
+
+impl DerefMut for SharedEventClient {
+    fn deref_mut(&mut self) -> &mut EventClient {
+        &mut self.0
+    }
+}
+
+/// A `Send + Sync` handle to a [`PollingClient`], which derefs to the client
+pub struct SharedPollingClient(SendWrapper<PollingClient>);
+
+impl SharedPollingClient {
+    /// Wrap a client so it can be stored where `Send` is required
+    pub fn new(client: PollingClient) -> Self {
+        Self(SendWrapper::new(client))
+    }
+    /// Unwrap the client, panicking if this is called on a different thread than the one that created it
+    pub fn into_inner(self) -> PollingClient {
+        self.0.take()
+    }
+}
+
+impl From<PollingClient> for SharedPollingClient {
+    fn from(client: PollingClient) -> Self {
+        Self::new(client)
+    }
+}
+
+impl Deref for SharedPollingClient {
+    type Target = PollingClient;
+
+    fn deref(&self) -> &PollingClient {
+        &self.0
+    }
+}
+
+impl DerefMut for SharedPollingClient {
+    fn deref_mut(&mut self) -> &mut PollingClient {
+        &mut self.0
+    }
+}
@@ -0,0 +1,181 @@
+//! Simulating bad network conditions for testing.
+//!
+//! [`SimulatedClient`] wraps any [`WsClient`] and delays, drops, and reorders the messages
+//! passing through it using timers, so games can be tested against a bad network without
+//! external tooling.
+use crate::{
+    timer, CloseHandler, ConnectionHandler, ConnectionStatus, ErrorHandler, EventClient, Message,
+    MessageHandler, WebSocketError, WsClient,
+};
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+/// The network conditions to simulate in one direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkConditions {
+    /// Delay added to every message, in milliseconds
+    pub latency_ms: f64,
+    /// Up to this many milliseconds of random delay added on top of the latency
+    pub jitter_ms: f64,
+    /// Chance (from 0.0 to 1.0) that a message is silently dropped
+    pub drop_probability: f64,
+    /// Chance (from 0.0 to 1.0) that a message is held back by an extra `latency_ms + jitter_ms`,
+    /// letting later messages overtake it. Other messages are always delivered in order.
+    pub reorder_probability: f64,
+    /// Seed for the random number generator, so a run can be reproduced
+    pub seed: u64,
+}
+
+impl Default for NetworkConditions {
+    /// A perfect network, which delivers every message immediately and in order
+    fn default() -> Self {
+        Self {
+            latency_ms: 0.0,
+            jitter_ms: 0.0,
+            drop_probability: 0.0,
+            reorder_probability: 0.0,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+}
+
+/// Schedules the messages travelling in one direction
+pub(crate) struct Lane {
+    conditions: NetworkConditions,
+    rng: u64,
+    last_delivery: f64,
+}
+
+impl Lane {
+    pub(crate) fn new(conditions: NetworkConditions) -> Self {
+        Self {
+            // xorshift gets stuck on a zero state
+            rng: conditions.seed.max(1),
+            conditions,
+            last_delivery: 0.0,
+        }
+    }
+    /// A random number from 0.0 (inclusive) to 1.0 (exclusive), using xorshift64*
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// Decide what happens to the next message sent at `now`.
+    /// Returns how long to delay it in milliseconds, or None if it should be dropped.
+    pub(crate) fn schedule(&mut self, now: f64) -> Option<f64> {
+        if self.random() < self.conditions.drop_probability {
+            return None;
+        }
+        let delay = self.conditions.latency_ms + self.conditions.jitter_ms * self.random();
+        if self.random() < self.conditions.reorder_probability {
+            return Some(delay + self.conditions.latency_ms + self.conditions.jitter_ms);
+        }
+        // Jitter alone never reorders messages, just like a real TCP connection
+        let delivery = (now + delay).max(self.last_delivery);
+        self.last_delivery = delivery;
+        Some(delivery - now)
+    }
+}
+
+/// A [`WsClient`] wrapper that simulates bad network conditions.
+///
+/// Outgoing messages are delayed or dropped before being sent by the wrapped client, and incoming
+/// messages are delayed or dropped before reaching the on_message handler set through this wrapper.
+/// Messages returned by [`PollingClient::receive`](crate::PollingClient::receive) aren't affected.
+/// ```
+/// let lag = NetworkConditions {
+///     latency_ms: 150.0,
+///     jitter_ms: 50.0,
+///     drop_probability: 0.05,
+///     ..Default::default()
+/// };
+/// let mut client = SimulatedClient::new(EventClient::new("wss://ws.ifelse.io")?, lag.clone(), lag);
+/// ```
+pub struct SimulatedClient<C: WsClient> {
+    inner: Rc<RefCell<C>>,
+    inbound: Rc<RefCell<Lane>>,
+    outbound: Rc<RefCell<Lane>>,
+}
+
+impl<C: WsClient + 'static> SimulatedClient<C> {
+    /// Wrap a client, simulating separate conditions for incoming and outgoing messages
+    pub fn new(inner: C, inbound: NetworkConditions, outbound: NetworkConditions) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(inner)),
+            inbound: Rc::new(RefCell::new(Lane::new(inbound))),
+            outbound: Rc::new(RefCell::new(Lane::new(outbound))),
+        }
+    }
+    /// Get the wrapped client
+    pub fn inner(&self) -> Ref<'_, C> {
+        self.inner.borrow()
+    }
+    /// Send a message through the outbound lane.
+    /// Dropped messages are reported as sent, and errors from delayed sends are logged.
+    fn send_later(&self, message: Message) -> Result<(), WebSocketError> {
+        let delay = match self.outbound.borrow_mut().schedule(timer::now_ms()) {
+            Some(delay) => delay,
+            None => return Ok(()),
+        };
+        let inner = self.inner.clone();
+        timer::set_timeout(delay, move || {
+            let result = match message {
+                Message::Text(text) => inner.borrow().send_string(&text),
+                Message::Binary(data) => inner.borrow().send_binary(data.into()),
+            };
+            if let Err(e) = result {
+                match inner.borrow().event_client() {
+                    Some(client) => error!(client: client, "Failed to send delayed message: {}", e),
+                    None => error!("Failed to send delayed message: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+impl<C: WsClient + 'static> WsClient for SimulatedClient<C> {
+    fn status(&self) -> ConnectionStatus {
+        self.inner.borrow().status()
+    }
+    fn send_string(&self, message: &str) -> Result<(), WebSocketError> {
+        self.send_later(Message::Text(message.into()))
+    }
+    fn send_binary(&self, message: Vec<u8>) -> Result<(), WebSocketError> {
+        self.send_later(Message::Binary(message.into()))
+    }
+    fn close(&self) -> Result<(), WebSocketError> {
+        self.inner.borrow().close()
+    }
+    fn close_with(&self, code: u16, reason: Option<&str>) -> Result<(), WebSocketError> {
+        self.inner.borrow().close_with(code, reason)
+    }
+    fn set_on_error(&mut self, f: Option<ErrorHandler>) {
+        self.inner.borrow_mut().set_on_error(f)
+    }
+    fn set_on_connection(&mut self, f: Option<ConnectionHandler>) {
+        self.inner.borrow_mut().set_on_connection(f)
+    }
+    fn set_on_message(&mut self, f: Option<MessageHandler>) {
+        let inbound = self.inbound.clone();
+        let f = f.map(|f| {
+            let f = Rc::new(f);
+            Box::new(move |client: &EventClient, message: Message| {
+                if let Some(delay) = inbound.borrow_mut().schedule(timer::now_ms()) {
+                    let f = f.clone();
+                    let client = client.clone();
+                    timer::set_timeout(delay, move || f(&client, message));
+                }
+            }) as MessageHandler
+        });
+        self.inner.borrow_mut().set_on_message(f)
+    }
+    fn set_on_close(&mut self, f: Option<CloseHandler>) {
+        self.inner.borrow_mut().set_on_close(f)
+    }
+    fn event_client(&self) -> Option<EventClient> {
+        self.inner.borrow().event_client()
+    }
+}
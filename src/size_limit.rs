@@ -0,0 +1,101 @@
+//! Limits on the size of the messages a client sends and receives.
+//!
+//! A misbehaving server can send messages big enough to exhaust the wasm heap, and a bug can
+//! send messages the server will reject anyway. With [`SizeLimits`] set, oversize sends fail with
+//! [`WebSocketError::MessageTooLarge`], and oversize received messages are dropped or truncated
+//! and passed to the on_protocol_error handler.
+//! ```
+//! client.set_size_limits(SizeLimits {
+//!     max_outbound: Some(64 * 1024),
+//!     max_inbound: Some(4 << 20),
+//!     oversize_inbound: Oversize::Drop,
+//! });
+//! ```
+use crate::{EventClient, Message, ProtocolError, WebSocketError};
+
+/// What happens to a received message over [`SizeLimits::max_inbound`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Oversize {
+    /// Drop the message
+    #[default]
+    Drop,
+    /// Deliver the first `max_inbound` bytes of the message. Text is cut at the last whole character.
+    Truncate,
+}
+
+/// The largest messages a client sends and receives, in bytes, see [`EventClient::set_size_limits`].
+///
+/// The default has no limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeLimits {
+    /// The largest message that can be sent, or None for no limit
+    pub max_outbound: Option<usize>,
+    /// The largest message that's delivered whole, or None for no limit
+    pub max_inbound: Option<usize>,
+    /// What happens to received messages over the limit
+    pub oversize_inbound: Oversize,
+}
+
+impl EventClient {
+    /// Set the largest messages this client sends and receives, which can be changed at any time.
+    ///
+    /// Framed and batched messages count as the whole frame. Binary messages are checked before
+    /// they're copied into wasm memory. Messages passed to the raw handlers, the blob chunk handler
+    /// or the text chunk handler aren't limited, since those handle large messages a piece at a time.
+    /// ```
+    /// client.set_size_limits(SizeLimits {
+    ///     max_outbound: Some(16 * 1024),
+    ///     ..SizeLimits::default()
+    /// });
+    /// ```
+    pub fn set_size_limits(&self, limits: SizeLimits) {
+        self.listeners.borrow_mut().size_limits = limits;
+    }
+    /// Fail if a message is too large to send
+    pub(crate) fn check_outbound(&self, message: &Message) -> Result<(), WebSocketError> {
+        let size = message_size(message);
+        match self.listeners.borrow().size_limits.max_outbound {
+            Some(max) if size > max => Err(WebSocketError::MessageTooLarge { size, max }),
+            _ => Ok(()),
+        }
+    }
+    /// How many bytes of a received message of `size` bytes to deliver, or None to drop it.
+    /// Oversize messages are passed to the on_protocol_error handler.
+    pub(crate) fn inbound_len(&self, size: usize) -> Option<usize> {
+        let limits = self.listeners.borrow().size_limits;
+        let max = match limits.max_inbound {
+            Some(max) if size > max => max,
+            _ => return Some(size),
+        };
+        self.dispatch_protocol_error(ProtocolError::TooLarge { size, max });
+        match limits.oversize_inbound {
+            Oversize::Drop => None,
+            Oversize::Truncate => Some(max),
+        }
+    }
+    /// Apply the inbound size limit to a received message, returning None if it's dropped
+    pub(crate) fn limit_inbound(&self, message: Message) -> Option<Message> {
+        let size = message_size(&message);
+        let len = self.inbound_len(size)?;
+        if len == size {
+            return Some(message);
+        }
+        Some(match message {
+            Message::Text(text) => {
+                let mut end = len;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                Message::Text(text[..end].into())
+            }
+            Message::Binary(data) => Message::Binary(data.slice(..len)),
+        })
+    }
+}
+
+fn message_size(message: &Message) -> usize {
+    match message {
+        Message::Text(text) => text.len(),
+        Message::Binary(data) => data.len(),
+    }
+}
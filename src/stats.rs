@@ -0,0 +1,299 @@
+//! Counting the messages a client discards instead of sending, so silent data loss shows up somewhere,
+//! and keeping histograms of message sizes.
+//!
+//! Messages are dropped by the rate limit with [`OverLimit::Drop`](crate::OverLimit::Drop), when
+//! their [time to live](EventClient::send_with_ttl) runs out while queued, and when the connection
+//! ends with messages still queued. Each one is counted in [`EventClient::stats`], and passed to
+//! the on_dropped handler.
+//! ```
+//! client.set_on_dropped(Some(Box::new(|message, reason| {
+//!     warn!("Dropped a message ({:?}): {:?}", reason, message);
+//! })));
+//! // Later
+//! hud.set_text(&format!("{} messages lost", client.stats().dropped()));
+//! ```
+//!
+//! Every message sent and received is counted by size in [`Stats::sent_sizes`] and
+//! [`Stats::received_sizes`], so a server that starts sending unexpectedly large messages shows up
+//! without instrumenting each handler.
+//! ```
+//! let received = client.stats().received_sizes;
+//! if received.p95() > 1 << 20 {
+//!     warn!("Most messages are under {} bytes, but the largest was {}", received.p95(), received.max);
+//! }
+//! ```
+//!
+//! The counters can be exported in the Prometheus text format with [`Stats::to_prometheus_text`],
+//! for apps that relay client metrics through their backend.
+use crate::outbound::message_len;
+use crate::{EventClient, Message};
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Why a message was dropped instead of sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// It was over the rate limit, with [`OverLimit::Drop`](crate::OverLimit::Drop)
+    RateLimit,
+    /// Its time to live ran out while it was queued
+    Expired,
+    /// The connection ended while it was queued
+    Closed,
+    /// Queueing it would have gone over the memory limit, see [`EventClient::set_memory_limit`]
+    MemoryLimit,
+}
+
+/// A handler called with each message dropped instead of sent, see [`EventClient::set_on_dropped`]
+pub type DroppedHandler = Box<dyn Fn(Message, DropReason)>;
+
+/// The number of buckets in a [`SizeHistogram`]
+const BUCKETS: usize = 32;
+
+/// A histogram of message sizes in bytes, with power of two buckets.
+///
+/// Sent messages are counted at their size before any encoding, and received messages at their size
+/// as they arrived, before they're decoded or split.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeHistogram {
+    /// The number of messages counted
+    pub count: u64,
+    /// The total size of the messages counted
+    pub total: u64,
+    /// The size of the smallest message, or 0 if none have been counted
+    pub min: u64,
+    /// The size of the largest message
+    pub max: u64,
+    /// Bucket 0 counts empty messages, and bucket `i` counts messages from `2^(i-1)` to
+    /// `2^i - 1` bytes. The last bucket also counts everything larger.
+    pub buckets: [u64; BUCKETS],
+}
+
+impl SizeHistogram {
+    pub(crate) fn record(&mut self, message: &Message) {
+        let size = message_len(message) as u64;
+        self.min = if self.count == 0 {
+            size
+        } else {
+            self.min.min(size)
+        };
+        self.max = self.max.max(size);
+        self.count += 1;
+        self.total += size;
+        let bucket = (u64::BITS - size.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+    }
+    /// The average message size, or 0 if none have been counted
+    pub fn average(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total as f64 / self.count as f64
+        }
+    }
+    /// The size that 95% of messages are at or under, rounded up to the end of its bucket
+    /// and capped at the largest message
+    pub fn p95(&self) -> u64 {
+        self.percentile(0.95)
+    }
+    /// The size that the given fraction of messages, from 0 to 1, are at or under,
+    /// rounded up to the end of its bucket and capped at the largest message
+    /// ```
+    /// let median = client.stats().sent_sizes.percentile(0.5);
+    /// ```
+    pub fn percentile(&self, fraction: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((self.count as f64 * fraction).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let end = if i == 0 { 0 } else { (1u64 << i) - 1 };
+                return end.clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+    fn write_prometheus(&self, text: &mut String, labels: &str, name: &str, help: &str) {
+        writeln!(text, "# HELP {} {}", name, help).unwrap();
+        writeln!(text, "# TYPE {} histogram", name).unwrap();
+        let mut cumulative = 0;
+        for (i, count) in self.buckets[..BUCKETS - 1].iter().enumerate() {
+            cumulative += count;
+            let le = if i == 0 { 0 } else { (1u64 << i) - 1 };
+            writeln!(
+                text,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, le, cumulative
+            )
+            .unwrap();
+        }
+        writeln!(
+            text,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        )
+        .unwrap();
+        writeln!(text, "{}_sum{{{}}} {}", name, labels, self.total).unwrap();
+        writeln!(text, "{}_count{{{}}} {}", name, labels, self.count).unwrap();
+    }
+}
+
+/// Escape a label value for the Prometheus text format, where backslashes, double quotes and
+/// line feeds have to be escaped
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counters for a client, see [`EventClient::stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The client's connection id, see [`EventClient::connection_id`]
+    pub connection_id: u64,
+    /// The client's label, see [`EventClient::set_label`]
+    pub label: Option<String>,
+    /// Messages dropped by the rate limit
+    pub dropped_rate_limit: u64,
+    /// Messages whose time to live ran out while they were queued
+    pub dropped_expired: u64,
+    /// Messages still queued when the connection ended
+    pub dropped_closed: u64,
+    /// Messages that would have gone over the memory limit if they were queued
+    pub dropped_memory_limit: u64,
+    /// Received messages discarded, oldest first, to stay under the memory limit
+    pub discarded_inbound: u64,
+    /// The bytes of the messages queued to be sent, when the stats were taken
+    pub queued_outbound_bytes: u64,
+    /// The bytes of the received messages waiting to be handled or received, when the stats
+    /// were taken
+    pub buffered_inbound_bytes: u64,
+    /// The sizes of the messages sent
+    pub sent_sizes: SizeHistogram,
+    /// The sizes of the messages received
+    pub received_sizes: SizeHistogram,
+}
+
+impl Stats {
+    /// The number of messages dropped instead of sent, for any reason
+    pub fn dropped(&self) -> u64 {
+        self.dropped_rate_limit
+            + self.dropped_expired
+            + self.dropped_closed
+            + self.dropped_memory_limit
+    }
+    /// Format the counters in the Prometheus text exposition format, with metric names starting
+    /// with `wasm_sockets_`. Every sample is labelled with the `connection_id`, and the `label`
+    /// if the client has one. Add labels to tell apps apart where the text is collected.
+    /// ```
+    /// let body = client.stats().to_prometheus_text();
+    /// fetch_with_body("/metrics/client", &body).await?;
+    /// ```
+    pub fn to_prometheus_text(&self) -> String {
+        let mut labels = format!("connection_id=\"{}\"", self.connection_id);
+        if let Some(label) = &self.label {
+            write!(labels, ",label=\"{}\"", escape_label_value(label)).unwrap();
+        }
+        let mut text = String::new();
+        text.push_str(
+            "# HELP wasm_sockets_messages_dropped_total Messages dropped instead of sent.\n",
+        );
+        text.push_str("# TYPE wasm_sockets_messages_dropped_total counter\n");
+        for (reason, count) in [
+            ("rate_limit", self.dropped_rate_limit),
+            ("expired", self.dropped_expired),
+            ("closed", self.dropped_closed),
+            ("memory_limit", self.dropped_memory_limit),
+        ] {
+            writeln!(
+                text,
+                "wasm_sockets_messages_dropped_total{{{},reason=\"{}\"}} {}",
+                labels, reason, count
+            )
+            .unwrap();
+        }
+        for (name, kind, help, value) in [
+            (
+                "wasm_sockets_inbound_messages_discarded_total",
+                "counter",
+                "Received messages discarded to stay under the memory limit.",
+                self.discarded_inbound,
+            ),
+            (
+                "wasm_sockets_queued_outbound_bytes",
+                "gauge",
+                "Bytes of the messages queued to be sent.",
+                self.queued_outbound_bytes,
+            ),
+            (
+                "wasm_sockets_buffered_inbound_bytes",
+                "gauge",
+                "Bytes of the received messages waiting to be handled.",
+                self.buffered_inbound_bytes,
+            ),
+        ] {
+            writeln!(text, "# HELP {} {}", name, help).unwrap();
+            writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+            writeln!(text, "{}{{{}}} {}", name, labels, value).unwrap();
+        }
+        self.sent_sizes.write_prometheus(
+            &mut text,
+            &labels,
+            "wasm_sockets_sent_message_bytes",
+            "Sizes of the messages sent.",
+        );
+        self.received_sizes.write_prometheus(
+            &mut text,
+            &labels,
+            "wasm_sockets_received_message_bytes",
+            "Sizes of the messages received.",
+        );
+        text
+    }
+}
+
+impl EventClient {
+    /// Get the client's counters
+    /// ```
+    /// let stats = client.stats();
+    /// info!("{} expired, {} over the rate limit", stats.dropped_expired, stats.dropped_rate_limit);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.listeners.borrow().stats.clone();
+        stats.connection_id = self.connection_id();
+        stats.label = self.label();
+        stats.queued_outbound_bytes = self.queued_bytes() as u64;
+        stats.buffered_inbound_bytes = (self.held_bytes() + self.metered_bytes()) as u64;
+        stats
+    }
+    /// Set a handler for messages dropped instead of sent, overwriting the previous one.
+    /// You can set None to disable it.
+    /// ```
+    /// client.set_on_dropped(Some(Box::new(|message, reason| {
+    ///     warn!("Dropped {:?}: {:?}", reason, message);
+    /// })));
+    /// ```
+    pub fn set_on_dropped(&mut self, f: Option<DroppedHandler>) {
+        self.listeners.borrow_mut().on_dropped = f.map(Rc::new);
+    }
+    /// Count a dropped message and pass it to the on_dropped handler
+    pub(crate) fn report_dropped(&self, message: Message, reason: DropReason) {
+        let on_dropped = {
+            let mut listeners = self.listeners.borrow_mut();
+            let stats = &mut listeners.stats;
+            match reason {
+                DropReason::RateLimit => stats.dropped_rate_limit += 1,
+                DropReason::Expired => stats.dropped_expired += 1,
+                DropReason::Closed => stats.dropped_closed += 1,
+                DropReason::MemoryLimit => stats.dropped_memory_limit += 1,
+            }
+            listeners.on_dropped.clone()
+        };
+        if let Some(f) = on_dropped {
+            f.as_ref()(message, reason);
+        }
+    }
+}
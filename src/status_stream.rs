@@ -0,0 +1,91 @@
+//! Watching the connection status from async code.
+//!
+//! The stream works like a watch channel: it yields the current status first, then the latest
+//! status each time it changes. Changes that happen before the stream is polled again are
+//! skipped, so it never falls behind. It ends once the connection has closed.
+//! ```
+//! let mut statuses = client.status_stream();
+//! while let Some(status) = statuses.next().await {
+//!     ui.set_status(&status);
+//! }
+//! ```
+use crate::{ConnectionStatus, EventClient, WebSocketError};
+use futures_core::Stream;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+struct StatusStream {
+    client: EventClient,
+    /// The status yielded last, or None before the first
+    last: Option<ConnectionStatus>,
+}
+
+impl Stream for StatusStream {
+    type Item = ConnectionStatus;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let status = self.client.status();
+        if self.last.as_ref() != Some(&status) {
+            self.last = Some(status.clone());
+            return Poll::Ready(Some(status));
+        }
+        let mut listeners = self.client.listeners.borrow_mut();
+        // An error comes before the close, so the status can still change until then
+        if listeners.closed {
+            return Poll::Ready(None);
+        }
+        listeners.status_wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl EventClient {
+    /// Get a stream of the connection's status, starting with the current one.
+    ///
+    /// Statuses that change again before the stream is polled are skipped.
+    /// The stream ends once the close event has been handled.
+    /// ```
+    /// let mut statuses = client.status_stream();
+    /// while let Some(status) = statuses.next().await {
+    ///     info!("Status: {:?}", status);
+    /// }
+    /// ```
+    pub fn status_stream(&self) -> impl Stream<Item = ConnectionStatus> {
+        StatusStream {
+            client: self.clone(),
+            last: None,
+        }
+    }
+    /// Wait for the connection to open, without replacing any handlers.
+    ///
+    /// Resolves right away if it's already open, and fails if the connection ends before opening.
+    /// ```
+    /// let client = EventClient::new("wss://ws.ifelse.io")?;
+    /// client.connected().await?;
+    /// client.send_string("Hello!")?;
+    /// ```
+    pub async fn connected(&self) -> Result<(), WebSocketError> {
+        let mut statuses = self.status_stream();
+        while let Some(status) = poll_fn(|cx| Pin::new(&mut statuses).poll_next(cx)).await {
+            match status {
+                ConnectionStatus::Connecting => {}
+                ConnectionStatus::Connected => return Ok(()),
+                ConnectionStatus::Error(info) | ConnectionStatus::Disconnected(info) => {
+                    return Err(WebSocketError::ConnectionCreationError(format!(
+                        "The connection closed before opening, with code {}",
+                        info.code
+                    )))
+                }
+            }
+        }
+        unreachable!("status streams only end after the connection closes")
+    }
+    /// Wake the status streams waiting for a change, after the status has been set
+    pub(crate) fn wake_status(&self) {
+        let wakers = std::mem::take(&mut self.listeners.borrow_mut().status_wakers);
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
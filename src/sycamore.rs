@@ -0,0 +1,100 @@
+//! Sycamore integration, enabled with the `sycamore` feature.
+//!
+//! [`use_websocket`] opens a connection owned by the current reactive scope (usually a component)
+//! and exposes it as signals, like the Leptos adapter. This uses `sycamore-reactive`, which
+//! Sycamore re-exports its signals from.
+//! ```
+//! #[component]
+//! fn Chat() -> View {
+//!     let socket = use_websocket("wss://ws.ifelse.io");
+//!     view! {
+//!         p { (format!("{:?}", socket.status.get_clone())) }
+//!         p { (format!("{:?}", socket.message.get_clone())) }
+//!         button(on:click=move |_| { socket.send(Message::Text("Hello, World!".into())).ok(); }) {
+//!             "Send"
+//!         }
+//!     }
+//! }
+//! ```
+use crate::{CloseInfo, ConnectionStatus, EventClient, Message, WebSocketError};
+use sycamore_reactive::{create_signal, on_cleanup, ReadSignal, Signal};
+
+/// A websocket connection exposed as signals.
+///
+/// This is `Copy`, so it can be moved into as many closures as needed.
+#[derive(Clone, Copy)]
+pub struct UseWebSocket {
+    /// The current connection status
+    pub status: ReadSignal<ConnectionStatus>,
+    /// The most recent message received from the server
+    pub message: ReadSignal<Option<Message>>,
+    client: Signal<Option<EventClient>>,
+}
+
+impl UseWebSocket {
+    /// Send a message to the server
+    pub fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        self.client.with_untracked(|client| match client {
+            Some(client) => match message {
+                Message::Text(text) => client.send_string(&text),
+                Message::Binary(data) => client.send_binary(data.into()),
+            },
+            None => Err(WebSocketError::SendError("Not connected".into())),
+        })
+    }
+    /// Close the connection
+    pub fn close(&self) -> Result<(), WebSocketError> {
+        self.client.with_untracked(|client| match client {
+            Some(client) => client.close(),
+            None => Ok(()),
+        })
+    }
+}
+
+/// Connect to a WebSocket URL, closing the connection when the current reactive scope is disposed.
+///
+/// The signals are set directly from the socket's handlers, which is safe to do outside of
+/// the reactive scope since signals keep a reference to their root.
+pub fn use_websocket(url: &str) -> UseWebSocket {
+    let status = create_signal(ConnectionStatus::Connecting);
+    let message = create_signal(None);
+    let client = match EventClient::new(url) {
+        Ok(mut client) => {
+            client.set_on_connection(Some(Box::new(move |_| {
+                status.set(ConnectionStatus::Connected);
+            })));
+            client.set_on_error(Some(Box::new(move |_| {
+                status.set(ConnectionStatus::Error(CloseInfo::abnormal()));
+            })));
+            client.set_on_close(Some(Box::new(move |e| {
+                status.set(ConnectionStatus::closed(CloseInfo::from(&e)));
+            })));
+            client.set_on_message(Some(Box::new(move |_, m| {
+                message.set(Some(m));
+            })));
+            Some(client)
+        }
+        Err(e) => {
+            error!("{}", e);
+            status.set(ConnectionStatus::Error(CloseInfo::abnormal()));
+            None
+        }
+    };
+    let client = create_signal(client);
+    // Cleanups run before the scope's signals are disposed
+    on_cleanup(move || {
+        // Setting a disposed signal panics, so the handlers must not run after this
+        if let Some(mut client) = client.update_silent(Option::take) {
+            client.set_on_error(None);
+            client.set_on_connection(None);
+            client.set_on_message(None);
+            client.set_on_close(None);
+            client.close().ok();
+        }
+    });
+    UseWebSocket {
+        status: *status,
+        message: *message,
+        client,
+    }
+}
@@ -0,0 +1,132 @@
+//! Keeping game state in sync with snapshots and deltas, the usual netcode for browser games.
+//!
+//! The server sends a full snapshot now and then, and otherwise sends deltas against a baseline:
+//! the newest state the client has acknowledged. [`SnapshotSync`] keeps the states the server can
+//! still use as baselines, applies each delta to the right one, and tells the client which ids to
+//! acknowledge. How states and deltas are encoded, and how acknowledgements are sent, is up to the application.
+//! ```
+//! let mut sync = SnapshotSync::new();
+//! for message in client.receive() {
+//!     let ack = match decode(&message)? {
+//!         Update::Snapshot { id, state } => sync.receive_snapshot(id, state),
+//!         Update::Delta { id, baseline, delta } => {
+//!             sync.receive_delta(id, baseline, |state| state.apply(&delta))?
+//!         }
+//!     };
+//!     if let Some(ack) = ack {
+//!         client.send_binary(ack.to_be_bytes().to_vec())?;
+//!     }
+//! }
+//! if let Some(state) = sync.latest_state() {
+//!     render(state);
+//! }
+//! ```
+use std::collections::VecDeque;
+use std::fmt;
+
+/// How many states are kept as possible baselines by default
+pub const DEFAULT_SYNC_HISTORY: usize = 32;
+
+/// A delta that couldn't be applied, see [`SnapshotSync::receive_delta`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncError {
+    /// The delta's baseline isn't kept anymore, or was never received.
+    /// The server should be asked for a new snapshot.
+    UnknownBaseline(u32),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownBaseline(id) => write!(f, "Baseline {} isn't available", id),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+/// The received states of a snapshot and delta stream, kept as baselines for the deltas to come
+#[derive(Debug, Clone)]
+pub struct SnapshotSync<S> {
+    /// States that may still be used as baselines, oldest first
+    states: VecDeque<(u32, S)>,
+    max_history: usize,
+}
+
+impl<S> Default for SnapshotSync<S> {
+    fn default() -> Self {
+        Self::with_history(DEFAULT_SYNC_HISTORY)
+    }
+}
+
+impl<S> SnapshotSync<S> {
+    /// Create a SnapshotSync without any states, keeping [`DEFAULT_SYNC_HISTORY`] states as baselines
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a SnapshotSync that keeps at most `max_history` states as baselines.
+    ///
+    /// This should cover the states received in one round trip, since the server uses the
+    /// newest one it's seen acknowledged.
+    pub fn with_history(max_history: usize) -> Self {
+        Self {
+            states: VecDeque::new(),
+            max_history: max_history.max(1),
+        }
+    }
+    /// Add a full snapshot, returning the id to acknowledge, or None if a newer state was already received
+    pub fn receive_snapshot(&mut self, id: u32, state: S) -> Option<u32> {
+        if self.is_outdated(id) {
+            return None;
+        }
+        self.push(id, state);
+        Some(id)
+    }
+    /// Add a delta by applying it to the state `baseline`, returning the id to acknowledge,
+    /// or None if a newer state was already received.
+    ///
+    /// States older than the baseline are dropped, since the server has seen a newer acknowledgement.
+    pub fn receive_delta(
+        &mut self,
+        id: u32,
+        baseline: u32,
+        apply: impl FnOnce(&S) -> S,
+    ) -> Result<Option<u32>, SyncError> {
+        if self.is_outdated(id) {
+            return Ok(None);
+        }
+        let index = self
+            .states
+            .iter()
+            .position(|(state_id, _)| *state_id == baseline)
+            .ok_or(SyncError::UnknownBaseline(baseline))?;
+        self.states.drain(..index);
+        let state = apply(&self.states[0].1);
+        self.push(id, state);
+        Ok(Some(id))
+    }
+    /// The newest state, to render or simulate from
+    pub fn latest_state(&self) -> Option<&S> {
+        self.states.back().map(|(_, state)| state)
+    }
+    /// The id of the newest state
+    pub fn latest_id(&self) -> Option<u32> {
+        self.states.back().map(|(id, _)| *id)
+    }
+    /// Forget every state, like after reconnecting, when the server starts over with a snapshot
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+    fn is_outdated(&self, id: u32) -> bool {
+        match self.latest_id() {
+            Some(latest) => id <= latest,
+            None => false,
+        }
+    }
+    fn push(&mut self, id: u32, state: S) {
+        self.states.push_back((id, state));
+        while self.states.len() > self.max_history {
+            self.states.pop_front();
+        }
+    }
+}
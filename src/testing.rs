@@ -0,0 +1,129 @@
+//! Utilities for writing integration tests against real connections, or against a [`loopback`]
+//! that needs no server at all.
+//!
+//! For tests that need a real server, the `echo-server` feature builds a native echo server binary:
+//! ```text
+//! cargo run --target x86_64-unknown-linux-gnu --features echo-server --bin echo-server
+//! ```
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+use crate::EventClient;
+use crate::{timer, ConnectionStatus, WsClient};
+use std::time::Duration;
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+use wasm_bindgen::JsCast;
+
+/// A stand-in for the browser's WebSocket that sends every message straight back
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+const LOOPBACK: &str = r#"
+const listeners = {};
+// Sends wait for the ones before them, since reading a Blob takes a while
+let queue = Promise.resolve();
+const tick = () => new Promise((resolve) => setTimeout(resolve, 0));
+const socket = {
+    url: "loopback:",
+    protocol: "",
+    extensions: "",
+    binaryType: "blob",
+    readyState: 0,
+    bufferedAmount: 0,
+    onopen: null,
+    onmessage: null,
+    onerror: null,
+    onclose: null,
+    addEventListener(type, f) {
+        (listeners[type] = listeners[type] || []).push(f);
+    },
+    removeEventListener(type, f) {
+        listeners[type] = (listeners[type] || []).filter((g) => g !== f);
+    },
+    dispatch(type, event) {
+        const handler = this["on" + type];
+        if (handler) handler(event);
+        for (const f of (listeners[type] || []).slice()) f(event);
+    },
+    send(data) {
+        if (this.readyState === 0) throw new Error("The loopback is still connecting");
+        if (this.readyState !== 1) return;
+        // Views into wasm memory have to be copied before it changes
+        if (ArrayBuffer.isView(data)) {
+            data = data.buffer.slice(data.byteOffset, data.byteOffset + data.byteLength);
+        } else if (data instanceof ArrayBuffer) {
+            data = data.slice(0);
+        }
+        queue = queue.then(async () => {
+            if (typeof data !== "string") {
+                if (this.binaryType === "blob" && !(data instanceof Blob)) data = new Blob([data]);
+                if (this.binaryType === "arraybuffer" && data instanceof Blob) data = await data.arrayBuffer();
+            }
+            await tick();
+            if (this.readyState === 1) this.dispatch("message", { data });
+        });
+    },
+    close(code, reason) {
+        if (this.readyState >= 2) return;
+        this.readyState = 2;
+        queue = queue.then(async () => {
+            await tick();
+            this.readyState = 3;
+            this.dispatch("close", { code: code === undefined ? 1005 : code, reason: reason || "", wasClean: true });
+        });
+    },
+};
+setTimeout(() => {
+    socket.readyState = 1;
+    socket.dispatch("open", {});
+}, 0);
+return socket;
+"#;
+
+/// Wait until a client reaches `status`, checking every 10 milliseconds.
+///
+/// Returns the last status seen if it isn't reached within `timeout`.
+/// This works in `wasm_bindgen_test` async tests, and inside a tokio `LocalSet` with the `native` feature.
+/// ```
+/// #[wasm_bindgen_test]
+/// async fn connects() {
+///     let client = EventClient::new("wss://ws.ifelse.io").unwrap();
+///     wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+///         .await
+///         .unwrap();
+/// }
+/// ```
+pub async fn wait_for_status<C: WsClient + ?Sized>(
+    client: &C,
+    status: ConnectionStatus,
+    timeout: Duration,
+) -> Result<(), ConnectionStatus> {
+    let deadline = timer::now_ms() + timeout.as_secs_f64() * 1000.0;
+    loop {
+        let current = client.status();
+        if current == status {
+            return Ok(());
+        }
+        if timer::now_ms() >= deadline {
+            return Err(current);
+        }
+        timer::sleep(10.0).await;
+    }
+}
+
+/// Create a client connected to a loopback instead of a server, which sends every message back.
+///
+/// The loopback opens right after it's created, and closes cleanly with the code passed to close.
+/// Tests that use it run without a network, so they can't be broken by a flaky server. It's
+/// created with `Function`, so it doesn't work on pages whose content security policy forbids eval.
+/// ```
+/// #[wasm_bindgen_test]
+/// async fn echoes() {
+///     let client = loopback();
+///     client.connected().await.unwrap();
+///     client.send_string("ping").unwrap();
+/// }
+/// ```
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub fn loopback() -> EventClient {
+    let socket = js_sys::Function::new_no_args(LOOPBACK)
+        .call0(&wasm_bindgen::JsValue::NULL)
+        .expect("loopback not created");
+    EventClient::from_websocket(socket.unchecked_into())
+}
@@ -2,18 +2,31 @@ use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
 use crate as wasm_sockets;
-use log::{error, info, Level};
+use log::{info, Level};
 use std::panic;
+use std::time::Duration;
+use wasm_sockets::testing::wait_for_status;
+
+/// The echo server the tests connect to, which can be pointed at the `echo-server` binary
+/// by building with WASM_SOCKETS_ECHO_URL=ws://127.0.0.1:9001
+const ECHO_URL: &str = match option_env!("WASM_SOCKETS_ECHO_URL") {
+    Some(url) => url,
+    None => "wss://ws.ifelse.io",
+};
 
 #[wasm_bindgen_test]
-fn event() {
+async fn event() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, Message};
+
     panic::set_hook(Box::new(console_error_panic_hook::hook));
     // console_log and log macros are used instead of println!
     // so that messages can be seen in the browser console
     console_log::init_with_level(Level::Trace).expect("Failed to enable logging");
     info!("Creating connection");
 
-    let mut client = wasm_sockets::EventClient::new("wss://ws.ifelse.io").unwrap();
+    let mut client = wasm_sockets::EventClient::new(ECHO_URL).unwrap();
     client.set_on_error(Some(Box::new(|error| {
         error!("{:#?}", error);
     })));
@@ -26,10 +39,2614 @@ fn event() {
     client.set_on_close(Some(Box::new(|_evt| {
         info!("Connection closed");
     })));
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
     client.set_on_message(Some(Box::new(
-        |_client: &wasm_sockets::EventClient, message: wasm_sockets::Message| {
+        move |_client: &wasm_sockets::EventClient, message: wasm_sockets::Message| {
             info!("New Message: {:#?}", message);
+            received_ref.borrow_mut().push(message);
         },
     )));
     info!("Connection successfully created");
+
+    // The client has to be kept until the echoes come back, or the connection would close
+    for _ in 0..100 {
+        if received
+            .borrow()
+            .contains(&Message::Binary(vec![20].into()))
+        {
+            break;
+        }
+        crate::timer::sleep(100.0).await;
+    }
+    // Some echo servers send a greeting first
+    let received = received.borrow().clone();
+    assert!(received.contains(&Message::Text("Hello, World!".into())));
+    assert!(received.contains(&Message::Binary(vec![20].into())));
+
+    client.close().unwrap();
+    while !client.status().is_terminal() {
+        crate::timer::sleep(10.0).await;
+    }
+    assert!(matches!(client.status(), ConnectionStatus::Disconnected(_)));
+}
+
+#[wasm_bindgen_test]
+async fn wait_for_connection() {
+    use wasm_sockets::{ConnectionStatus, EventClient, PollingClient};
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    wait_for_status(
+        &client,
+        ConnectionStatus::Connected,
+        Duration::from_secs(10),
+    )
+    .await
+    .expect("Failed to connect");
+    // The last status seen is returned when it times out
+    assert_eq!(
+        wait_for_status(
+            &client,
+            ConnectionStatus::Connecting,
+            Duration::from_millis(50)
+        )
+        .await,
+        Err(ConnectionStatus::Connected)
+    );
+    client.close().unwrap();
+
+    // Any WsClient can be waited on
+    let client = PollingClient::new("ws://127.0.0.1:1").unwrap();
+    assert!(matches!(
+        wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5)).await,
+        Err(ConnectionStatus::Error(_)) | Err(ConnectionStatus::Disconnected(_))
+    ));
+}
+
+#[wasm_bindgen_test]
+fn added_handlers() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::Message;
+
+    let mut client = wasm_sockets::EventClient::new("ws://127.0.0.1:1").unwrap();
+    let log = Rc::new(RefCell::new(vec![]));
+    let log_ref = log.clone();
+    client.set_on_message(Some(Box::new(move |_, _| log_ref.borrow_mut().push("set"))));
+    let log_ref = log.clone();
+    let first = client.add_on_message(Box::new(move |_, _| log_ref.borrow_mut().push("first")));
+    let log_ref = log.clone();
+    client.add_on_message(Box::new(move |_, _| log_ref.borrow_mut().push("second")));
+
+    client.dispatch_message(Message::Text("a".into()));
+    assert_eq!(*log.borrow(), ["set", "first", "second"]);
+    assert!(client.remove_handler(first));
+    assert!(!client.remove_handler(first));
+    log.borrow_mut().clear();
+    client.dispatch_message(Message::Text("b".into()));
+    assert_eq!(*log.borrow(), ["set", "second"]);
+
+    let log_ref = log.clone();
+    client.once_on_message(Box::new(move |_, _| log_ref.borrow_mut().push("once")));
+    log.borrow_mut().clear();
+    client.dispatch_message(Message::Text("c".into()));
+    client.dispatch_message(Message::Text("d".into()));
+    assert_eq!(*log.borrow(), ["set", "second", "once", "set", "second"]);
+}
+
+#[wasm_bindgen_test]
+fn pause_resume() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::Message;
+
+    let client = wasm_sockets::EventClient::new("ws://127.0.0.1:1").unwrap();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.add_on_message(Box::new(move |client, message| {
+        received_ref.borrow_mut().push(message.clone());
+        // Pausing from a handler keeps the rest held
+        if message == Message::Text("b".into()) {
+            client.pause();
+        }
+    }));
+
+    client.pause();
+    assert!(client.is_paused());
+    for text in ["a", "b", "c"] {
+        client.dispatch_message(Message::Text(text.into()));
+    }
+    assert!(received.borrow().is_empty());
+    client.resume();
+    assert_eq!(
+        *received.borrow(),
+        [Message::Text("a".into()), Message::Text("b".into())]
+    );
+    assert!(client.is_paused());
+    client.resume();
+    assert_eq!(received.borrow().len(), 3);
+    assert!(!client.is_paused());
+}
+
+#[wasm_bindgen_test]
+fn early_messages() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{EventClient, Message};
+
+    // Messages before a handler are dropped by default
+    let client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    assert!(!client.buffers_early_messages());
+    client.dispatch_message(Message::Text("lost".into()));
+    assert_eq!(client.buffered_bytes(), 0);
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.add_on_message(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    }));
+    assert!(received.borrow().is_empty());
+    assert_eq!(client.buffered_bytes(), 0);
+
+    let client = EventClient::builder("ws://127.0.0.1:1")
+        .buffer_early_messages(true)
+        .build()
+        .unwrap();
+    for text in ["a", "b"] {
+        client.dispatch_message(Message::Text(text.into()));
+    }
+    assert_eq!(client.buffered_bytes(), 2);
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.add_on_message(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    }));
+    assert_eq!(
+        *received.borrow(),
+        [Message::Text("a".into()), Message::Text("b".into())]
+    );
+    assert_eq!(client.buffered_bytes(), 0);
+    client.dispatch_message(Message::Text("c".into()));
+    assert_eq!(received.borrow().len(), 3);
+    // Buffering stops once the messages are passed on
+    assert!(!client.buffers_early_messages());
+}
+
+#[wasm_bindgen_test]
+fn subscribe_filtered() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::Message;
+
+    let client = wasm_sockets::EventClient::new("ws://127.0.0.1:1").unwrap();
+    let log = Rc::new(RefCell::new(vec![]));
+    let log_ref = log.clone();
+    let binary = client.subscribe_filtered(
+        Box::new(|m| matches!(m, Message::Binary(_))),
+        Box::new(move |_, _| log_ref.borrow_mut().push("binary")),
+    );
+    let log_ref = log.clone();
+    client.add_on_message(Box::new(move |_, _| log_ref.borrow_mut().push("default")));
+
+    client.dispatch_message(Message::Binary(vec![1].into()));
+    client.dispatch_message(Message::Text("a".into()));
+    assert_eq!(*log.borrow(), ["binary", "default"]);
+    assert!(client.remove_handler(binary));
+    log.borrow_mut().clear();
+    client.dispatch_message(Message::Binary(vec![1].into()));
+    assert_eq!(*log.borrow(), ["default"]);
+}
+
+#[wasm_bindgen_test]
+fn status_predicates() {
+    use wasm_sockets::{CloseInfo, ConnectionStatus};
+
+    assert!(ConnectionStatus::Connected.is_connected());
+    assert!(!ConnectionStatus::Connecting.is_terminal());
+    let error = ConnectionStatus::Error(CloseInfo::abnormal());
+    assert!(error.is_terminal() && error.is_error());
+    assert_eq!(error.close_info().map(|info| info.code), Some(1006));
+    let disconnected = ConnectionStatus::Disconnected(CloseInfo {
+        code: 1000,
+        reason: String::new(),
+        was_clean: true,
+    });
+    assert!(disconnected.is_terminal());
+    assert!(!disconnected.is_error());
+    let client = wasm_sockets::EventClient::new("ws://127.0.0.1:1").unwrap();
+    assert!(!client.is_open());
+}
+
+#[wasm_bindgen_test]
+fn close_codes() {
+    use wasm_sockets::{close_code, CloseCode};
+
+    assert_eq!(
+        CloseCode::from(close_code::GOING_AWAY),
+        CloseCode::GoingAway
+    );
+    assert_eq!(CloseCode::from(4001), CloseCode::Application(4001));
+    assert_eq!(u16::from(CloseCode::TryAgainLater), 1013);
+    assert!(CloseCode::Abnormal.is_retryable());
+    assert!(!CloseCode::Normal.is_retryable());
+    assert!(!CloseCode::from(4003).is_retryable());
+}
+
+#[wasm_bindgen_test]
+fn handler_trait() {
+    use wasm_sockets::{EventClient, Message, WebSocketHandler, WsClient};
+
+    struct Collector(Vec<Message>);
+    impl WebSocketHandler for Collector {
+        fn on_message(&mut self, _client: &EventClient, message: Message) {
+            self.0.push(message);
+        }
+    }
+
+    let mut client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let collector = client.set_handler(Collector(vec![]));
+    client.dispatch_connection();
+    client.dispatch_message(Message::Text("a".into()));
+    assert_eq!(collector.borrow().0, [Message::Text("a".into())]);
+}
+
+#[wasm_bindgen_test]
+fn raw_event_listener() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let client = wasm_sockets::EventClient::new("ws://127.0.0.1:1").unwrap();
+    let count = Rc::new(Cell::new(0));
+    let count_ref = count.clone();
+    let id = client
+        .add_raw_event_listener(
+            "ping",
+            Box::new(move |_| count_ref.set(count_ref.get() + 1)),
+        )
+        .unwrap();
+    let event = web_sys::Event::new("ping").unwrap();
+    client.connection.borrow().dispatch_event(&event).unwrap();
+    assert!(client.remove_handler(id));
+    client.connection.borrow().dispatch_event(&event).unwrap();
+    assert_eq!(count.get(), 1);
+}
+
+#[cfg(feature = "blob")]
+#[wasm_bindgen_test]
+fn builder_binary_type() {
+    use wasm_sockets::{BinaryType, EventClient};
+
+    let client = EventClient::builder("ws://127.0.0.1:1")
+        .binary_type(BinaryType::Blob)
+        .build()
+        .unwrap();
+    assert_eq!(client.raw().binary_type(), web_sys::BinaryType::Blob);
+    client.set_binary_type(BinaryType::ArrayBuffer);
+    assert_eq!(client.raw().binary_type(), web_sys::BinaryType::Arraybuffer);
+}
+
+#[cfg(feature = "blob")]
+#[wasm_bindgen_test]
+async fn blob_read() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{EventClient, Message, ProtocolError};
+
+    let mut client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let log = Rc::new(RefCell::new(vec![]));
+    let log_ref = log.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        log_ref.borrow_mut().push(Ok(message))
+    })));
+    let log_ref = log.clone();
+    client.set_on_protocol_error(Some(Box::new(move |e| log_ref.borrow_mut().push(Err(e)))));
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(&[1u8, 2][..]));
+    client.receive_blob(web_sys::Blob::new_with_u8_array_sequence(&parts).unwrap());
+    crate::timer::sleep(50.0).await;
+    // Where there's no FileReader, like in Node, the message is skipped instead of panicking
+    let log = log.borrow();
+    match &log[..] {
+        [Ok(message)] => assert_eq!(*message, Message::Binary(vec![1, 2].into())),
+        [Err(ProtocolError::BlobRead(_))] => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[wasm_bindgen_test]
+fn constructor_errors() {
+    use wasm_sockets::{EventClient, WebSocketError};
+
+    for url in [
+        "not a url",
+        "ftp://example.com",
+        "wss://example.com/#fragment",
+    ] {
+        match EventClient::new(url) {
+            Err(WebSocketError::InvalidUrl(message)) => assert!(!message.is_empty()),
+            Err(e) => panic!("{} failed with {}", url, e),
+            Ok(_) => panic!("{} connected", url),
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn error_kinds() {
+    use wasm_sockets::{ErrorKind, EventClient, ProtocolError, WebSocketError};
+
+    let e = EventClient::new("not a url").err().unwrap();
+    assert_eq!(
+        (e.kind(), e.is_transient()),
+        (ErrorKind::Application, false)
+    );
+    let e = WebSocketError::SendError("Not connected".into());
+    assert_eq!((e.kind(), e.is_transient()), (ErrorKind::Network, true));
+    assert_eq!(
+        WebSocketError::SecurityError("Blocked".into()).kind(),
+        ErrorKind::Security
+    );
+    let e = WebSocketError::ListenerError("Not a function".into());
+    assert_eq!(
+        (e.kind(), e.is_transient()),
+        (ErrorKind::Application, false)
+    );
+    assert_eq!(
+        ProtocolError::Codec("Bad JSON".into()).kind(),
+        ErrorKind::Protocol
+    );
+    assert!(!ErrorKind::Protocol.is_transient());
+}
+
+#[wasm_bindgen_test]
+fn text_policy() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{EventClient, Message, ProtocolError, TextPolicy};
+
+    // "a", then an unpaired high surrogate
+    let text = || js_sys::JsString::from_char_code(&[0x61, 0xD800]);
+    assert_eq!(crate::text::wtf8([0x61, 0xD800]), [0x61, 0xED, 0xA0, 0x80]);
+
+    let mut client = EventClient::builder("ws://127.0.0.1:1")
+        .text_policy(TextPolicy::Strict)
+        .build()
+        .unwrap();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.add_on_message(Box::new(move |_, m| received_ref.borrow_mut().push(m)));
+    let errors = Rc::new(RefCell::new(vec![]));
+    let errors_ref = errors.clone();
+    client.set_on_protocol_error(Some(Box::new(move |e| errors_ref.borrow_mut().push(e))));
+
+    client.dispatch_text(text());
+    assert_eq!(
+        *errors.borrow(),
+        [ProtocolError::InvalidText("a\u{FFFD}".into())]
+    );
+    client.set_text_policy(TextPolicy::Lossy);
+    client.dispatch_text(text());
+    client.set_text_policy(TextPolicy::Raw);
+    client.dispatch_text(text());
+    client.dispatch_text("ok".into());
+    assert_eq!(
+        *received.borrow(),
+        [
+            Message::Text("a\u{FFFD}".into()),
+            Message::Binary(vec![0x61, 0xED, 0xA0, 0x80].into()),
+            Message::Text("ok".into()),
+        ]
+    );
+}
+
+#[wasm_bindgen_test]
+fn text_chunks() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{EventClient, TEXT_CHUNK_SIZE};
+
+    let mut client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let chunks = Rc::new(RefCell::new(vec![]));
+    let chunks_ref = chunks.clone();
+    client.set_on_text_chunk(Some(Box::new(move |_, chunk| {
+        chunks_ref.borrow_mut().push(chunk)
+    })));
+    let received = Rc::new(RefCell::new(0));
+    let received_ref = received.clone();
+    client.add_on_message(Box::new(move |_, _| *received_ref.borrow_mut() += 1));
+
+    client.dispatch_text("short".into());
+    assert_eq!(*received.borrow(), 1);
+
+    // The emoji's surrogate pair straddles the chunk boundary
+    let size = TEXT_CHUNK_SIZE as usize;
+    let text = "a".repeat(size - 1) + "\u{1F600}" + "bc";
+    client.dispatch_text(text.as_str().into());
+    let chunks = chunks.borrow();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].text.len(), size - 1);
+    assert_eq!(chunks[1].text, "\u{1F600}bc");
+    assert_eq!(
+        (chunks[1].offset, chunks[1].total),
+        (size as u32 - 1, size as u32 + 3)
+    );
+    assert!(!chunks[0].is_last() && chunks[1].is_last());
+    assert_eq!(*received.borrow(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn status_stream() {
+    use futures_core::Stream;
+    use std::future::poll_fn;
+    use std::pin::Pin;
+    use wasm_sockets::{ConnectionStatus, EventClient};
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    let mut statuses = client.status_stream();
+    assert_eq!(
+        next(&mut statuses).await,
+        Some(ConnectionStatus::Connecting)
+    );
+    assert_eq!(next(&mut statuses).await, Some(ConnectionStatus::Connected));
+    client.close().unwrap();
+    assert!(matches!(
+        next(&mut statuses).await,
+        Some(ConnectionStatus::Disconnected(_))
+    ));
+    assert_eq!(next(&mut statuses).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn connected() {
+    use wasm_sockets::EventClient;
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    client.connected().await.unwrap();
+    // Already open
+    client.connected().await.unwrap();
+    client.close().unwrap();
+    crate::timer::sleep(200.0).await;
+    assert!(client.connected().await.is_err());
+
+    let refused = EventClient::new("ws://127.0.0.1:1").unwrap();
+    assert!(refused.connected().await.is_err());
+}
+
+#[wasm_bindgen_test]
+async fn on_message_async() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{EventClient, Message};
+
+    let mut client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let log = Rc::new(RefCell::new(vec![]));
+    let log_ref = log.clone();
+    client.set_on_message_async(Some(Box::new(move |_, message| {
+        let log = log_ref.clone();
+        Box::pin(async move {
+            let delay = match &message {
+                Message::Text(text) => text.parse().unwrap(),
+                Message::Binary(_) => 0.0,
+            };
+            log.borrow_mut().push(format!("start {}", delay));
+            crate::timer::sleep(delay).await;
+            log.borrow_mut().push(format!("end {}", delay));
+        })
+    })));
+    // Later messages wait for the slower futures before them
+    for delay in ["50", "10", "0"] {
+        client.dispatch_message(Message::Text(delay.into()));
+    }
+    assert!(log.borrow().is_empty());
+    crate::timer::sleep(200.0).await;
+    assert_eq!(
+        *log.borrow(),
+        ["start 50", "end 50", "start 10", "end 10", "start 0", "end 0"]
+    );
+
+    // Waiting messages are dropped with the handler
+    client.dispatch_message(Message::Text("50".into()));
+    client.dispatch_message(Message::Text("50".into()));
+    crate::timer::sleep(10.0).await;
+    client.set_on_message_async(None);
+    crate::timer::sleep(100.0).await;
+    assert_eq!(log.borrow().len(), 8);
+}
+
+#[wasm_bindgen_test]
+fn schema_registry() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{EventClient, Message, ProtocolError, SchemaRegistry};
+
+    let mut client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let log = Rc::new(RefCell::new(vec![]));
+    let mut registry = SchemaRegistry::new();
+    let log_ref = log.clone();
+    registry.register(
+        1,
+        Box::new(move |_, payload| log_ref.borrow_mut().push(format!("one {:?}", &payload[..]))),
+    );
+    let log_ref = log.clone();
+    registry.register_decoder(
+        2,
+        |payload| match payload {
+            [byte] => Ok(*byte),
+            _ => Err("expected one byte"),
+        },
+        move |_, byte| log_ref.borrow_mut().push(format!("two {}", byte)),
+    );
+    let log_ref = log.clone();
+    registry.set_fallback(Some(Box::new(move |_, message| {
+        log_ref
+            .borrow_mut()
+            .push(format!("fallback {:?}", &message[..]))
+    })));
+    assert!(registry.is_registered(1) && !registry.is_registered(3));
+    client.set_schema_registry(Some(registry));
+    let log_ref = log.clone();
+    client.set_on_protocol_error(Some(Box::new(move |e| {
+        log_ref.borrow_mut().push(e.to_string())
+    })));
+    let log_ref = log.clone();
+    client.add_on_message(Box::new(move |_, m| {
+        log_ref.borrow_mut().push(format!("{:?}", m))
+    }));
+
+    for message in [vec![1, 7, 8], vec![2, 9], vec![2, 9, 9], vec![3, 4], vec![]] {
+        client.dispatch_message(Message::Binary(message.into()));
+    }
+    client.dispatch_message(Message::Text("hi".into()));
+    assert_eq!(
+        *log.borrow(),
+        [
+            "one [7, 8]".to_string(),
+            "two 9".to_string(),
+            ProtocolError::Decode {
+                tag: 2,
+                error: "expected one byte".into()
+            }
+            .to_string(),
+            "fallback [3, 4]".to_string(),
+            "fallback []".to_string(),
+            "Text(\"hi\")".to_string(),
+        ]
+    );
+}
+
+#[wasm_bindgen_test]
+fn snapshot_sync() {
+    use wasm_sockets::{SnapshotSync, SyncError};
+
+    let append = |n| move |state: &Vec<u32>| [&state[..], &[n]].concat();
+    let mut sync = SnapshotSync::with_history(3);
+    assert_eq!(sync.latest_state(), None);
+    assert_eq!(sync.receive_snapshot(1, vec![1]), Some(1));
+    assert_eq!(sync.receive_delta(2, 1, append(2)), Ok(Some(2)));
+    // The server hasn't seen the ack for 2 yet, so it still sends deltas against 1
+    assert_eq!(sync.receive_delta(3, 1, append(3)), Ok(Some(3)));
+    assert_eq!(sync.latest_state(), Some(&vec![1, 3]));
+    assert_eq!(sync.receive_delta(4, 2, append(4)), Ok(Some(4)));
+    assert_eq!(sync.latest_state(), Some(&vec![1, 2, 4]));
+    // Baselines older than the last one used are dropped
+    assert_eq!(
+        sync.receive_delta(5, 1, append(5)),
+        Err(SyncError::UnknownBaseline(1))
+    );
+    assert_eq!(sync.receive_delta(4, 2, append(9)), Ok(None));
+    assert_eq!(sync.receive_snapshot(3, vec![]), None);
+    assert_eq!(sync.latest_id(), Some(4));
+}
+
+#[wasm_bindgen_test]
+async fn playback() {
+    use crate::timer::sleep;
+    use wasm_sockets::{Message, Playback, PollingClient};
+
+    let mut client = PollingClient::new("ws://127.0.0.1:1").unwrap();
+    client.set_playback(Some(Playback {
+        delay_ms: 100.0,
+        timestamp: Some(Box::new(|m| match m {
+            Message::Binary(data) => Some(data[0] as f64 * 10.0),
+            Message::Text(_) => None,
+        })),
+    }));
+    client
+        .event_client
+        .dispatch_message(Message::Binary(vec![0].into()));
+    assert!(client.receive().is_empty());
+    // Sent 100ms after the first, but arriving 300ms after it, so its playback time has passed
+    sleep(300.0).await;
+    client
+        .event_client
+        .dispatch_message(Message::Binary(vec![10].into()));
+    assert_eq!(
+        client.receive(),
+        [
+            Message::Binary(vec![0].into()),
+            Message::Binary(vec![10].into())
+        ]
+    );
+
+    client
+        .event_client
+        .dispatch_message(Message::Text("held".into()));
+    client.set_playback(None);
+    assert_eq!(client.receive(), [Message::Text("held".into())]);
+}
+
+#[wasm_bindgen_test]
+fn coalesce() {
+    use wasm_sockets::{Message, PollingClient};
+
+    let mut client = PollingClient::new("ws://127.0.0.1:1").unwrap();
+    // Binary messages are keyed by their first byte
+    client.set_coalesce_key(Some(Box::new(|m| match m {
+        Message::Binary(data) => data.first().map(|key| *key as u64),
+        Message::Text(_) => None,
+    })));
+    for message in [
+        Message::Binary(vec![1, 0].into()),
+        Message::Text("a".into()),
+        Message::Binary(vec![2, 0].into()),
+        Message::Binary(vec![1, 1].into()),
+        Message::Text("a".into()),
+        Message::Binary(vec![1, 2].into()),
+    ] {
+        client.event_client.dispatch_message(message);
+    }
+    assert_eq!(
+        client.receive(),
+        [
+            Message::Text("a".into()),
+            Message::Binary(vec![2, 0].into()),
+            Message::Text("a".into()),
+            Message::Binary(vec![1, 2].into()),
+        ]
+    );
+}
+
+#[wasm_bindgen_test]
+fn size_limits() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{
+        Message, Oversize, PollingClient, ProtocolError, SizeLimits, WebSocketError,
+    };
+
+    let mut client = PollingClient::new("ws://127.0.0.1:1").unwrap();
+    client.event_client.set_size_limits(SizeLimits {
+        max_outbound: Some(4),
+        max_inbound: Some(3),
+        oversize_inbound: Oversize::Drop,
+    });
+    assert!(matches!(
+        client.send_binary(vec![0; 5]),
+        Err(WebSocketError::MessageTooLarge { size: 5, max: 4 })
+    ));
+    let errors = Rc::new(RefCell::new(vec![]));
+    let errors_ref = errors.clone();
+    client
+        .event_client
+        .set_on_protocol_error(Some(Box::new(move |e| errors_ref.borrow_mut().push(e))));
+
+    let event_client = client.event_client.clone();
+    event_client.dispatch_message(Message::Text("abcd".into()));
+    event_client.dispatch_message(Message::Text("abc".into()));
+    event_client.set_size_limits(SizeLimits {
+        max_inbound: Some(3),
+        oversize_inbound: Oversize::Truncate,
+        ..SizeLimits::default()
+    });
+    // The two byte character doesn't fit whole
+    event_client.dispatch_message(Message::Text("ab\u{e9}".into()));
+    event_client.dispatch_message(Message::Binary(vec![1, 2, 3, 4].into()));
+    assert_eq!(
+        client.receive(),
+        [
+            Message::Text("abc".into()),
+            Message::Text("ab".into()),
+            Message::Binary(vec![1, 2, 3].into()),
+        ]
+    );
+    assert_eq!(errors.borrow().len(), 3);
+    assert_eq!(
+        errors.borrow()[0],
+        ProtocolError::TooLarge { size: 4, max: 3 }
+    );
+}
+
+#[wasm_bindgen_test]
+fn receive_into() {
+    use wasm_sockets::{Message, PollingClient};
+
+    let mut client = PollingClient::new("ws://127.0.0.1:1").unwrap();
+    for message in [
+        Message::Binary(vec![1, 2].into()),
+        Message::Text("a".into()),
+        Message::Binary(vec![3].into()),
+    ] {
+        client.event_client.dispatch_message(message);
+    }
+    let (mut data, mut ranges) = (vec![], vec![]);
+    client.receive_binary_into(&mut data, &mut ranges);
+    assert_eq!(data, [1, 2, 3]);
+    assert_eq!(ranges, [0..2, 2..3]);
+
+    let mut messages = vec![];
+    client.receive_into(&mut messages);
+    assert_eq!(messages, [Message::Text("a".into())]);
+    assert!(client.receive().is_empty());
+}
+
+#[wasm_bindgen_test]
+fn raw_binary() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut client = wasm_sockets::EventClient::new("ws://127.0.0.1:1").unwrap();
+    client.set_on_message(Some(Box::new(|_, _| panic!("message wasn't raw"))));
+    let length = Rc::new(Cell::new(0));
+    let length_ref = length.clone();
+    client.set_on_raw_binary(Some(Box::new(move |_, buffer| {
+        length_ref.set(buffer.byte_length())
+    })));
+    client.dispatch_binary(js_sys::ArrayBuffer::new(4));
+    assert_eq!(length.get(), 4);
+}
+
+#[wasm_bindgen_test]
+async fn remote_client() {
+    use wasm_sockets::{EventClient, Message, RemoteClient};
+
+    fn assert_send<T: Send + Sync>(_: &T) {}
+    let client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let remote: RemoteClient = client.remote();
+    assert_send(&remote);
+    client.dispatch_message(Message::Text("a".into()));
+    crate::timer::sleep(20.0).await;
+    assert_eq!(remote.receive(), [Message::Text("a".into())]);
+}
+
+#[wasm_bindgen_test]
+fn reassemble_chunks() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::Reassembler;
+
+    let data: Vec<u8> = (0..250).collect();
+    let mut reassembler = Reassembler::new();
+    let progress = Rc::new(RefCell::new(vec![]));
+    let progress_ref = progress.clone();
+    reassembler.set_on_progress(Some(Box::new(move |p| {
+        progress_ref.borrow_mut().push((p.received, p.total))
+    })));
+    let chunks = crate::chunked::chunks(7, &data, 100);
+    assert_eq!(reassembler.push(&chunks[0]), None);
+    assert_eq!(reassembler.push(&chunks[2]), None);
+    assert_eq!(reassembler.push(&chunks[1]), None);
+    assert_eq!(reassembler.push(&chunks[2]), Some(data));
+    assert_eq!(*progress.borrow(), [(1, 3), (2, 3), (3, 3)]);
+}
+
+#[wasm_bindgen_test]
+fn framing() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{
+        Endianness, FramedWriter, Framing, FramingError, Message, PollingClient, PrefixSize,
+        ProtocolError,
+    };
+
+    let framing = Framing {
+        prefix: PrefixSize::U16,
+        endianness: Endianness::Little,
+    };
+    let mut writer = FramedWriter::new(framing);
+    writer.push(b"ab").unwrap();
+    writer.push(b"").unwrap();
+    writer.push(&[7; 300]).unwrap();
+    assert_eq!(
+        writer.push(&[0; 70_000]),
+        Err(FramingError::TooLong(70_000))
+    );
+    let frame = writer.finish();
+    assert_eq!(frame[..4], [2, 0, b'a', b'b']);
+    assert!(writer.is_empty());
+
+    let mut client = PollingClient::new("ws://127.0.0.1:1").unwrap();
+    client.event_client.set_framing(Some(framing));
+    let errors = Rc::new(RefCell::new(vec![]));
+    let errors_ref = errors.clone();
+    client
+        .event_client
+        .set_on_protocol_error(Some(Box::new(move |e| errors_ref.borrow_mut().push(e))));
+    client
+        .event_client
+        .dispatch_message(Message::Binary(frame.into()));
+    client
+        .event_client
+        .dispatch_message(Message::Binary(vec![5, 0, 1].into()));
+    assert_eq!(
+        client.receive(),
+        [
+            Message::Binary(b"ab".to_vec().into()),
+            Message::Binary(vec![].into()),
+            Message::Binary(vec![7; 300].into()),
+        ]
+    );
+    assert_eq!(
+        *errors.borrow(),
+        [ProtocolError::Framing(FramingError::Truncated)]
+    );
+}
+
+#[wasm_bindgen_test]
+async fn batching() {
+    use wasm_sockets::{Batching, ConnectionStatus, FlushTick, Message, PollingClient};
+
+    let mut client = PollingClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    let batching = Batching {
+        tick: FlushTick::Interval(10.0),
+        ..Batching::default()
+    };
+    client.event_client.set_batching(Some(batching));
+    for i in 0..3 {
+        client.send_binary(vec![i]).unwrap();
+    }
+    // The echoed batch is split back into the original messages
+    client.event_client.set_framing(Some(batching.framing));
+    crate::timer::sleep(500.0).await;
+    let echoed: Vec<Message> = client
+        .receive()
+        .into_iter()
+        .filter(|m| matches!(m, Message::Binary(_)))
+        .collect();
+    assert_eq!(
+        echoed,
+        (0..3)
+            .map(|i| Message::Binary(vec![i].into()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[wasm_bindgen_test]
+async fn rate_limit() {
+    use wasm_sockets::{ConnectionStatus, OverLimit, PollingClient, RateLimit};
+
+    let client = PollingClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.event_client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(2.0),
+        ..RateLimit::default()
+    }));
+    for _ in 0..4 {
+        client.send_string("queued").unwrap();
+    }
+    assert_eq!(client.event_client.queued_messages(), 2);
+
+    client.event_client.set_rate_limit(None);
+    assert_eq!(client.event_client.queued_messages(), 0);
+
+    client.event_client.set_rate_limit(Some(RateLimit {
+        bytes_per_sec: Some(4.0),
+        over_limit: OverLimit::Error,
+        ..RateLimit::default()
+    }));
+    client.send_binary(vec![0; 4]).unwrap();
+    assert!(client.send_binary(vec![0]).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn send_tracked() {
+    use wasm_sockets::{ConnectionStatus, EventClient, Message, OverLimit, RateLimit};
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(1.0),
+        ..RateLimit::default()
+    }));
+    let first = client.send_tracked(Message::Text("first".into())).unwrap();
+    // Held back by the rate limit, so it can't have left yet
+    let second = client.send_tracked(Message::Text("second".into())).unwrap();
+    assert!(!second.is_sent());
+    first.await.unwrap();
+    second.await.unwrap();
+
+    client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(1.0),
+        over_limit: OverLimit::Drop,
+        ..RateLimit::default()
+    }));
+    client.send_string("allowed").unwrap();
+    assert!(client
+        .send_tracked(Message::Text("dropped".into()))
+        .is_err());
+}
+
+#[wasm_bindgen_test]
+async fn send_with_ttl() {
+    use crate::timer::sleep;
+    use wasm_sockets::{ConnectionStatus, EventClient, Message, Priority, RateLimit};
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(1.0),
+        ..RateLimit::default()
+    }));
+    client.send_string("sent").unwrap();
+    let message = || Message::Text("input".into());
+    client
+        .send_with_ttl(message(), Priority::High, 50.0)
+        .unwrap();
+    client
+        .send_with_ttl(message(), Priority::High, 5000.0)
+        .unwrap();
+    assert_eq!(client.queued_messages(), 2);
+    sleep(100.0).await;
+    assert_eq!(client.queued_messages(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn dropped_messages() {
+    use crate::timer::sleep;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{
+        ConnectionStatus, DropReason, EventClient, Message, OverLimit, Priority, RateLimit,
+    };
+
+    let mut client = EventClient::new(ECHO_URL).unwrap();
+    let dropped = Rc::new(RefCell::new(vec![]));
+    let dropped_ref = dropped.clone();
+    client.set_on_dropped(Some(Box::new(move |message, reason| {
+        dropped_ref.borrow_mut().push((message, reason))
+    })));
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(1.0),
+        over_limit: OverLimit::Drop,
+        ..RateLimit::default()
+    }));
+    client.send_string("sent").unwrap();
+    client.send_string("over").unwrap();
+    client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(1.0),
+        ..RateLimit::default()
+    }));
+    client.send_string("sent").unwrap();
+    client
+        .send_with_ttl(Message::Text("late".into()), Priority::High, 10.0)
+        .unwrap();
+    sleep(50.0).await;
+    assert_eq!(client.queued_messages(), 0);
+    assert_eq!(
+        *dropped.borrow(),
+        [
+            (Message::Text("over".into()), DropReason::RateLimit),
+            (Message::Text("late".into()), DropReason::Expired),
+        ]
+    );
+    let stats = client.stats();
+    assert_eq!((stats.dropped_rate_limit, stats.dropped_expired), (1, 1));
+    assert_eq!(stats.dropped(), 2);
+    assert!(stats.to_prometheus_text().contains(&format!(
+        "wasm_sockets_messages_dropped_total{{connection_id=\"{}\",reason=\"expired\"}} 1\n",
+        stats.connection_id
+    )));
+}
+
+#[wasm_bindgen_test]
+async fn size_stats() {
+    use crate::timer::sleep;
+    use wasm_sockets::{ConnectionStatus, EventClient};
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.send_string("hi").unwrap();
+    client.send_binary(vec![0; 300]).unwrap();
+    for _ in 0..100 {
+        if client.stats().received_sizes.max >= 300 {
+            break;
+        }
+        sleep(20.0).await;
+    }
+    let sent = client.stats().sent_sizes;
+    assert_eq!(
+        (sent.count, sent.min, sent.max, sent.total),
+        (2, 2, 300, 302)
+    );
+    assert_eq!(sent.average(), 151.0);
+    assert_eq!((sent.buckets[2], sent.buckets[9]), (1, 1));
+    assert_eq!(sent.percentile(0.5), 3);
+    assert_eq!(sent.p95(), 300);
+    // The echo server may greet new connections, so only the echoes are checked
+    let received = client.stats().received_sizes;
+    assert!(received.count >= 2);
+    assert_eq!(received.max, 300);
+    client.set_label(Some("sizes \"1\""));
+    let text = client.stats().to_prometheus_text();
+    let labels = format!(
+        "connection_id=\"{}\",label=\"sizes \\\"1\\\"\"",
+        client.connection_id()
+    );
+    for line in [
+        "# TYPE wasm_sockets_sent_message_bytes histogram".to_string(),
+        format!(
+            "wasm_sockets_sent_message_bytes_bucket{{{},le=\"1\"}} 0",
+            labels
+        ),
+        format!(
+            "wasm_sockets_sent_message_bytes_bucket{{{},le=\"3\"}} 1",
+            labels
+        ),
+        format!(
+            "wasm_sockets_sent_message_bytes_bucket{{{},le=\"511\"}} 2",
+            labels
+        ),
+        format!(
+            "wasm_sockets_sent_message_bytes_bucket{{{},le=\"+Inf\"}} 2",
+            labels
+        ),
+        format!("wasm_sockets_sent_message_bytes_sum{{{}}} 302", labels),
+    ] {
+        assert!(text.lines().any(|l| l == line), "missing {}", line);
+    }
+}
+
+#[wasm_bindgen_test]
+fn prometheus_label_escaping() {
+    use wasm_sockets::Stats;
+
+    let stats = Stats {
+        connection_id: 4,
+        label: Some("C:\\ws \"main\"\nsecond line".into()),
+        ..Default::default()
+    };
+    assert!(stats.to_prometheus_text().contains(
+        "wasm_sockets_messages_dropped_total{connection_id=\"4\",label=\"C:\\\\ws \\\"main\\\"\\nsecond line\",reason=\"closed\"} 0\n"
+    ));
+}
+
+#[wasm_bindgen_test]
+fn connection_ids() {
+    use wasm_sockets::{EventClient, SocketManager};
+
+    let first = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let second = EventClient::new("ws://127.0.0.1:1").unwrap();
+    assert!(second.connection_id() > first.connection_id());
+    assert_eq!(first.clone().connection_id(), first.connection_id());
+    assert_eq!(first.label(), None);
+    first.set_label(Some("chat"));
+    let stats = first.stats();
+    assert_eq!(stats.connection_id, first.connection_id());
+    assert_eq!(stats.label.as_deref(), Some("chat"));
+
+    // Managed clients are labelled with their names
+    let manager = SocketManager::new();
+    manager.add("game", "ws://127.0.0.1:1").unwrap();
+    assert_eq!(
+        manager.client("game").unwrap().label().as_deref(),
+        Some("game")
+    );
+    manager.close_all();
+}
+
+#[wasm_bindgen_test]
+async fn health() {
+    use wasm_sockets::{ConnectionStatus, EventClient};
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    let health = client.health();
+    assert_eq!(health.status, ConnectionStatus::Connecting);
+    assert_eq!((health.uptime_ms, health.rtt_ms), (None, None));
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.record_rtt(100.0);
+    client.record_rtt(20.0);
+    let health = client.health();
+    assert_eq!(health.url, ECHO_URL);
+    assert!(health.uptime_ms.unwrap() >= 0.0);
+    assert_eq!(health.rtt_ms, Some(90.0));
+    assert_eq!((health.reconnects, health.dropped_messages), (0, 0));
+}
+
+#[wasm_bindgen_test]
+async fn close_graceful() {
+    use wasm_sockets::{close_code, ConnectionStatus, EventClient, RateLimit};
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(2.0),
+        ..RateLimit::default()
+    }));
+    client.send_string("first").unwrap();
+    client.send_string("second").unwrap();
+    client.send_string("goodbye").unwrap();
+    assert_eq!(client.queued_messages(), 1);
+    client
+        .close_graceful(close_code::NORMAL, Some("done"), 5000.0)
+        .await
+        .unwrap();
+    assert_eq!(client.queued_messages(), 0);
+    assert!(client.send_string("too late").is_err());
+}
+
+#[wasm_bindgen_test]
+async fn priority_lanes() {
+    use wasm_sockets::{ConnectionStatus, Message, PollingClient, Priority, RateLimit};
+
+    let mut client = PollingClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client.event_client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(1.0),
+        ..RateLimit::default()
+    }));
+    client.send_string("first").unwrap();
+    for (text, priority) in [
+        ("low", Priority::Low),
+        ("normal", Priority::Normal),
+        ("high", Priority::High),
+    ] {
+        client
+            .event_client
+            .send_with_priority(Message::Text(text.into()), priority)
+            .unwrap();
+    }
+    assert_eq!(client.event_client.queued_messages(), 3);
+    client.event_client.set_rate_limit(None);
+    crate::timer::sleep(500.0).await;
+    let echoed: Vec<Message> = client
+        .receive()
+        .into_iter()
+        .filter(|m| !matches!(m, Message::Text(text) if text.starts_with("Request served by")))
+        .collect();
+    assert_eq!(
+        echoed,
+        ["first", "high", "normal", "low"]
+            .iter()
+            .map(|text| Message::Text((*text).into()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, ManagedEvent, Message, Reconnect, SocketManager};
+
+    async fn wait_connected(manager: &SocketManager, name: &str) {
+        for _ in 0..100 {
+            if manager.status(name) == Some(ConnectionStatus::Connected) {
+                return;
+            }
+            crate::timer::sleep(10.0).await;
+        }
+        panic!("{} didn't connect", name);
+    }
+
+    let manager = SocketManager::new();
+    manager.set_reconnect(Some(Reconnect {
+        initial_delay_ms: 10.0,
+        ..Reconnect::default()
+    }));
+    let reconnects = Rc::new(RefCell::new(vec![]));
+    let reconnects_ref = reconnects.clone();
+    manager.set_on_event(Some(Box::new(move |name, event| {
+        if let ManagedEvent::Reconnecting { attempt, .. } = event {
+            reconnects_ref
+                .borrow_mut()
+                .push((name.to_string(), attempt));
+        }
+    })));
+    manager.add("echo", ECHO_URL).unwrap();
+    wait_connected(&manager, "echo").await;
+    manager.send_string("echo", "hello").unwrap();
+    assert!(manager.send_string("missing", "hello").is_err());
+    crate::timer::sleep(200.0).await;
+    assert!(manager
+        .receive()
+        .contains(&("echo".to_string(), Message::Text("hello".into()))));
+
+    // A connection that's still connecting can't be sent to
+    manager.add("connecting", ECHO_URL).unwrap();
+    let failures = manager.broadcast_string("everyone").unwrap_err().failures;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, "connecting");
+    manager
+        .broadcast_binary_where(|name| name == "echo", vec![1])
+        .unwrap();
+    manager.remove("connecting");
+    crate::timer::sleep(200.0).await;
+    assert!(manager
+        .receive()
+        .contains(&("echo".to_string(), Message::Binary(vec![1].into()))));
+
+    // Connections that close unexpectedly are reopened
+    manager.client("echo").unwrap().close().unwrap();
+    crate::timer::sleep(200.0).await;
+    wait_connected(&manager, "echo").await;
+    assert_eq!(*reconnects.borrow(), [("echo".to_string(), 0)]);
+
+    // But not those closed through the manager
+    manager.close_all();
+    crate::timer::sleep(200.0).await;
+    assert!(matches!(
+        manager.status("echo"),
+        Some(ConnectionStatus::Disconnected(_))
+    ));
+    assert_eq!(reconnects.borrow().len(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager_stability_window() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, ManagedEvent, Reconnect, SocketManager};
+
+    async fn reconnected(manager: &SocketManager) {
+        manager.client("echo").unwrap().close().unwrap();
+        crate::timer::sleep(50.0).await;
+        for _ in 0..100 {
+            if manager.status("echo") == Some(ConnectionStatus::Connected) {
+                return;
+            }
+            crate::timer::sleep(10.0).await;
+        }
+        panic!("echo didn't reconnect");
+    }
+
+    let manager = SocketManager::new();
+    manager.set_reconnect(Some(Reconnect {
+        initial_delay_ms: 10.0,
+        stability_window_ms: 60_000.0,
+        ..Reconnect::default()
+    }));
+    let attempts = Rc::new(RefCell::new(vec![]));
+    let attempts_ref = attempts.clone();
+    manager.set_on_event(Some(Box::new(move |_name, event| {
+        if let ManagedEvent::Reconnecting { attempt, .. } = event {
+            attempts_ref.borrow_mut().push(attempt);
+        }
+    })));
+    manager.add("echo", ECHO_URL).unwrap();
+    crate::timer::sleep(100.0).await;
+    // Connections that drop before the window is up keep backing off
+    reconnected(&manager).await;
+    reconnected(&manager).await;
+    assert_eq!(*attempts.borrow(), [0, 1]);
+
+    // And start over once they've stayed open for it
+    manager.set_reconnect(Some(Reconnect {
+        initial_delay_ms: 10.0,
+        stability_window_ms: 0.0,
+        ..Reconnect::default()
+    }));
+    reconnected(&manager).await;
+    reconnected(&manager).await;
+    assert_eq!(*attempts.borrow(), [0, 1, 2, 0]);
+    manager.close_all();
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager_idle_disconnect() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, ManagedEvent, Message, SocketManager};
+
+    let manager = SocketManager::new();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    manager.set_on_event(Some(Box::new(move |_name, event| {
+        if let ManagedEvent::Message(message) = event {
+            received_ref.borrow_mut().push(message);
+        }
+    })));
+    manager.add("echo", ECHO_URL).unwrap();
+    manager.client("echo").unwrap().connected().await.unwrap();
+    manager.set_idle_disconnect(Some(100.0));
+    crate::timer::sleep(300.0).await;
+    assert!(matches!(
+        manager.status("echo"),
+        Some(ConnectionStatus::Disconnected(_))
+    ));
+
+    // The next send reopens the connection, and goes out once it's open
+    manager.send_string("echo", "Hello").unwrap();
+    let client = manager.client("echo").unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    for _ in 0..100 {
+        if !received.borrow().is_empty() {
+            break;
+        }
+        crate::timer::sleep(10.0).await;
+    }
+    assert_eq!(*received.borrow(), [Message::Text("Hello".into())]);
+
+    // Closing a connection that went idle keeps it closed
+    manager.add("spare", ECHO_URL).unwrap();
+    manager.client("spare").unwrap().connected().await.unwrap();
+    crate::timer::sleep(300.0).await;
+    manager.close("echo").unwrap();
+    manager.close_all();
+    manager.set_idle_disconnect(None);
+    // Sending on a closed socket is dropped by the browser, but mustn't reopen it
+    manager.send_string("echo", "Hello").ok();
+    manager.send_string("spare", "Hello").ok();
+    crate::timer::sleep(100.0).await;
+    for name in ["echo", "spare"] {
+        assert!(matches!(
+            manager.status(name),
+            Some(ConnectionStatus::Disconnected(_))
+        ));
+    }
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager_idle_while_reconnecting() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, ManagedEvent, Reconnect, SocketManager};
+
+    let manager = SocketManager::new();
+    manager.set_reconnect(Some(Reconnect {
+        initial_delay_ms: 60_000.0,
+        ..Reconnect::default()
+    }));
+    let attempts = Rc::new(RefCell::new(vec![]));
+    let attempts_ref = attempts.clone();
+    manager.set_on_event(Some(Box::new(move |_name, event| {
+        if let ManagedEvent::Reconnecting { attempt, .. } = event {
+            attempts_ref.borrow_mut().push(attempt);
+        }
+    })));
+    manager.add("echo", ECHO_URL).unwrap();
+    let client = manager.client("echo").unwrap();
+    client.connected().await.unwrap();
+    client.close_with(4000, None).unwrap();
+    crate::timer::sleep(100.0).await;
+    assert_eq!(*attempts.borrow(), [0]);
+
+    // It goes idle while waiting to reconnect, then the next send reopens it
+    manager.set_idle_disconnect(Some(100.0));
+    crate::timer::sleep(300.0).await;
+    manager.send_string("echo", "Hello").unwrap();
+    let client = manager.client("echo").unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    // And it reconnects again from the first attempt when it closes
+    manager.set_idle_disconnect(None);
+    client.close_with(4000, None).unwrap();
+    crate::timer::sleep(100.0).await;
+    assert_eq!(*attempts.borrow(), [0, 0]);
+    manager.close_all();
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager_rejection() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, ManagedEvent, Reconnect, SocketManager};
+
+    let manager = SocketManager::new();
+    manager.set_reconnect(Some(Reconnect {
+        initial_delay_ms: 10.0,
+        ..Reconnect::default()
+    }));
+    manager.set_rejection_codes(&[4001, 4003]);
+    let rejected = Rc::new(RefCell::new(vec![]));
+    let rejected_ref = rejected.clone();
+    manager.set_on_rejected(Some(Box::new(move |name, info| {
+        rejected_ref
+            .borrow_mut()
+            .push((name.to_string(), info.code));
+    })));
+    let reconnects = Rc::new(RefCell::new(0));
+    let reconnects_ref = reconnects.clone();
+    manager.set_on_event(Some(Box::new(move |_name, event| {
+        if let ManagedEvent::Reconnecting { .. } = event {
+            *reconnects_ref.borrow_mut() += 1;
+        }
+    })));
+    manager.add("echo", ECHO_URL).unwrap();
+    let client = manager.client("echo").unwrap();
+    client.connected().await.unwrap();
+    // The echo server closes with the code it's sent
+    client.close_with(4003, Some("Banned")).unwrap();
+    crate::timer::sleep(200.0).await;
+    assert_eq!(*rejected.borrow(), [("echo".to_string(), 4003)]);
+    assert_eq!(*reconnects.borrow(), 0);
+    assert!(matches!(
+        manager.status("echo"),
+        Some(ConnectionStatus::Disconnected(_))
+    ));
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager_reconnect_policy() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, ManagedEvent, ReconnectDecision, SocketManager};
+
+    let manager = SocketManager::new();
+    let closes = Rc::new(RefCell::new(vec![]));
+    let closes_ref = closes.clone();
+    manager.set_reconnect_policy(Some(Box::new(move |_name, info| {
+        closes_ref.borrow_mut().push(info.code);
+        match info.code {
+            4010 => ReconnectDecision::RetryNewUrl(ECHO_URL.to_string()),
+            _ => ReconnectDecision::GiveUp,
+        }
+    })));
+    let delays = Rc::new(RefCell::new(vec![]));
+    let delays_ref = delays.clone();
+    manager.set_on_event(Some(Box::new(move |_name, event| {
+        if let ManagedEvent::Reconnecting { delay_ms, .. } = event {
+            delays_ref.borrow_mut().push(delay_ms);
+        }
+    })));
+    manager.add("echo", ECHO_URL).unwrap();
+    manager.client("echo").unwrap().connected().await.unwrap();
+    // The echo server closes with the code it's sent
+    manager
+        .client("echo")
+        .unwrap()
+        .close_with(4010, None)
+        .unwrap();
+    crate::timer::sleep(200.0).await;
+    assert_eq!(manager.status("echo"), Some(ConnectionStatus::Connected));
+    manager
+        .client("echo")
+        .unwrap()
+        .close_with(4011, None)
+        .unwrap();
+    crate::timer::sleep(200.0).await;
+    assert!(matches!(
+        manager.status("echo"),
+        Some(ConnectionStatus::Disconnected(_))
+    ));
+    assert_eq!(*closes.borrow(), [4010, 4011]);
+    assert_eq!(*delays.borrow(), [0.0]);
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager_retry_hints() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{
+        CloseInfo, ConnectionStatus, ManagedEvent, Message, Reconnect, SocketManager,
+    };
+
+    let hint = |reason: &str| {
+        CloseInfo {
+            code: 1013,
+            reason: reason.into(),
+            was_clean: true,
+        }
+        .retry_after_ms()
+    };
+    assert_eq!(hint("Overloaded, Retry-After: 30"), Some(30_000.0));
+    assert_eq!(hint("retry-after=1.5"), Some(1_500.0));
+    assert_eq!(hint("retry-after=250ms"), Some(250.0));
+    assert_eq!(hint("retry-after soon"), None);
+    assert_eq!(hint("Overloaded"), None);
+
+    async fn reconnected(manager: &SocketManager, reason: Option<&str>) {
+        let client = manager.client("echo").unwrap();
+        client.connected().await.unwrap();
+        client.close_with(4000, reason).unwrap();
+        crate::timer::sleep(50.0).await;
+        for _ in 0..100 {
+            if manager.status("echo") == Some(ConnectionStatus::Connected) {
+                return;
+            }
+            crate::timer::sleep(10.0).await;
+        }
+        panic!("echo didn't reconnect");
+    }
+
+    let manager = SocketManager::new();
+    manager.set_reconnect(Some(Reconnect {
+        initial_delay_ms: 60_000.0,
+        ..Reconnect::default()
+    }));
+    manager.set_retry_hint_parser(Some(Box::new(|message| match message {
+        Message::Text(text) => text.strip_prefix("BACKOFF ")?.parse().ok(),
+        Message::Binary(_) => None,
+    })));
+    let delays = Rc::new(RefCell::new(vec![]));
+    let delays_ref = delays.clone();
+    manager.set_on_event(Some(Box::new(move |_name, event| {
+        if let ManagedEvent::Reconnecting { delay_ms, .. } = event {
+            delays_ref.borrow_mut().push(delay_ms);
+        }
+    })));
+    manager.add("echo", ECHO_URL).unwrap();
+    // The echo server closes with the reason it's sent
+    reconnected(&manager, Some("retry-after=20ms")).await;
+    manager.send_string("echo", "BACKOFF 30").unwrap();
+    crate::timer::sleep(50.0).await;
+    assert!(manager.receive().is_empty());
+    reconnected(&manager, None).await;
+    assert_eq!(*delays.borrow(), [20.0, 30.0]);
+    manager.close_all();
+}
+
+#[wasm_bindgen_test]
+async fn socket_manager_handover() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, ManagedEvent, Message, SocketManager};
+
+    let manager = SocketManager::new();
+    let statuses = Rc::new(RefCell::new(vec![]));
+    let events = Rc::new(RefCell::new(vec![]));
+    let events_ref = events.clone();
+    manager.set_on_event(Some(Box::new(move |_, event| match event {
+        ManagedEvent::HandedOver { url } => events_ref.borrow_mut().push(url),
+        ManagedEvent::Closed(_) => events_ref.borrow_mut().push("closed".into()),
+        _ => {}
+    })));
+    manager.add("game", ECHO_URL).unwrap();
+    for _ in 0..100 {
+        if manager.status("game") == Some(ConnectionStatus::Connected) {
+            break;
+        }
+        crate::timer::sleep(10.0).await;
+    }
+    let old = manager.client("game").unwrap();
+    manager
+        .handover("game", ECHO_URL, Some(Message::Text("resume 7".into())))
+        .unwrap();
+    assert!(manager.handover("missing", ECHO_URL, None).is_err());
+    for _ in 0..50 {
+        statuses.borrow_mut().push(manager.status("game"));
+        crate::timer::sleep(10.0).await;
+    }
+    crate::timer::sleep(200.0).await;
+    // The status never left Connected, and the old connection was closed
+    assert!(statuses
+        .borrow()
+        .iter()
+        .all(|status| *status == Some(ConnectionStatus::Connected)));
+    assert_eq!(*events.borrow(), [ECHO_URL]);
+    assert!(matches!(old.status(), ConnectionStatus::Disconnected(_)));
+    assert!(manager
+        .receive()
+        .contains(&("game".to_string(), Message::Text("resume 7".into()))));
+
+    // A handover that fails to open keeps the old connection
+    manager.handover("game", "ws://127.0.0.1:1", None).unwrap();
+    crate::timer::sleep(200.0).await;
+    assert_eq!(manager.status("game"), Some(ConnectionStatus::Connected));
+    manager.close_all();
+}
+
+#[wasm_bindgen_test]
+async fn race() {
+    use wasm_sockets::{ConnectionStatus, EventClient};
+
+    let start = crate::timer::now_ms();
+    let winner = EventClient::race(&["ws://127.0.0.1:1", ECHO_URL], 5000.0)
+        .await
+        .unwrap();
+    // The refused attempt fails fast, so the next one doesn't wait for the stagger delay
+    assert!(crate::timer::now_ms() - start < 5000.0);
+    assert_eq!(winner.index, 1);
+    assert_eq!(winner.client.status(), ConnectionStatus::Connected);
+
+    assert!(EventClient::race(&["ws://127.0.0.1:1"], 10.0)
+        .await
+        .is_err());
+    assert!(EventClient::race(&[], 10.0).await.is_err());
+}
+
+#[wasm_bindgen_test]
+async fn watchdog() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use wasm_sockets::{ConnectionStatus, EventClient};
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    client.set_watchdog(Some(150.0));
+    let stale = Rc::new(Cell::new(0));
+    let stale_ref = stale.clone();
+    client.add_on_stale(Box::new(move |_| stale_ref.set(stale_ref.get() + 1)));
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    // Echoed messages keep the connection alive
+    for _ in 0..4 {
+        client.send_string("ping").unwrap();
+        crate::timer::sleep(75.0).await;
+    }
+    assert_eq!(stale.get(), 0);
+    assert!(client.is_open());
+
+    crate::timer::sleep(400.0).await;
+    assert_eq!(stale.get(), 1);
+    assert!(client.status().is_terminal());
+}
+
+#[wasm_bindgen_test]
+async fn loopback() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{close_code, testing, ConnectionStatus, Message};
+
+    let mut client = testing::loopback();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    })));
+    assert_eq!(client.status(), ConnectionStatus::Connecting);
+    client.connected().await.unwrap();
+    client.send_string("ping").unwrap();
+    client.send_binary(vec![1, 2]).unwrap();
+    crate::timer::sleep(20.0).await;
+    assert_eq!(
+        *received.borrow(),
+        [
+            Message::Text("ping".into()),
+            Message::Binary(vec![1, 2].into())
+        ]
+    );
+    client
+        .close_with(close_code::GOING_AWAY, Some("bye"))
+        .unwrap();
+    crate::timer::sleep(20.0).await;
+    let info = client.status().close_info().cloned().unwrap();
+    assert_eq!(
+        (info.code, info.reason.as_str()),
+        (close_code::GOING_AWAY, "bye")
+    );
+}
+
+#[wasm_bindgen_test]
+async fn idle_for() {
+    use wasm_sockets::EventClient;
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    assert_eq!(client.idle_for(), 0.0);
+    client.connected().await.unwrap();
+    crate::timer::sleep(100.0).await;
+    // The echo server may greet new connections
+    let greeted = client.last_message_at();
+    client.send_string("ping").unwrap();
+    for _ in 0..200 {
+        if client.last_message_at() != greeted {
+            break;
+        }
+        crate::timer::sleep(5.0).await;
+    }
+    let last = client.last_message_at().unwrap();
+    assert!(client.idle_for() < 100.0);
+    crate::timer::sleep(100.0).await;
+    assert!(client.idle_for() >= 90.0);
+    assert_eq!(client.last_message_at(), Some(last));
+}
+
+#[cfg(feature = "json")]
+#[wasm_bindgen_test]
+async fn typed_polling_client() {
+    use wasm_sockets::{ConnectionStatus, Json, TypedPollingClient};
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Moved {
+        id: u32,
+        x: f32,
+    }
+
+    let mut client = TypedPollingClient::<Moved, Json>::new(ECHO_URL).unwrap();
+    for _ in 0..100 {
+        if client.status() == ConnectionStatus::Connected {
+            break;
+        }
+        crate::timer::sleep(10.0).await;
+    }
+    client.receive();
+    client
+        .client()
+        .send_string(r#"{"id": 1, "x": 2.5}"#)
+        .unwrap();
+    client
+        .client()
+        .send_binary(br#"{"id": 2, "x": 0}"#.to_vec())
+        .unwrap();
+    client.client().send_string("not json").unwrap();
+    crate::timer::sleep(200.0).await;
+    let received = client.receive();
+    assert_eq!(received.len(), 3);
+    assert_eq!(received[0], Ok(Moved { id: 1, x: 2.5 }));
+    assert_eq!(received[1], Ok(Moved { id: 2, x: 0.0 }));
+    assert!(received[2].is_err());
+}
+
+#[cfg(feature = "json")]
+#[wasm_bindgen_test]
+async fn negotiated_codecs() {
+    use wasm_sockets::{
+        Codec, ConnectionStatus, DecodeError, EncodeError, Json, Message, NegotiatedPollingClient,
+        ProtocolCodecs,
+    };
+
+    /// JSON in binary messages
+    struct BinaryJson;
+    impl Codec for BinaryJson {
+        fn decode<T: serde::de::DeserializeOwned>(message: &Message) -> Result<T, DecodeError> {
+            match message {
+                Message::Binary(data) => serde_json::from_slice(data).map_err(DecodeError::new),
+                Message::Text(_) => Err(DecodeError::new("Expected a binary message")),
+            }
+        }
+        fn encode<T: serde::Serialize + ?Sized>(value: &T) -> Result<Message, EncodeError> {
+            serde_json::to_vec(value)
+                .map(|data| Message::Binary(data.into()))
+                .map_err(EncodeError::new)
+        }
+    }
+
+    let codecs = ProtocolCodecs::new()
+        .codec::<Json>("json")
+        .codec::<BinaryJson>("json.binary");
+    assert_eq!(codecs.protocols(), ["json", "json.binary"]);
+    let binary = Message::Binary(b"[1,2]".to_vec().into());
+    assert_eq!(codecs.encode("json.binary", &[1, 2]), Ok(binary.clone()));
+    assert_eq!(
+        codecs.encode("json", &[1, 2]),
+        Ok(Message::Text("[1,2]".into()))
+    );
+    // Without a selected protocol, the first codec is used
+    assert_eq!(
+        codecs.encode("", &[1, 2]),
+        Ok(Message::Text("[1,2]".into()))
+    );
+    assert_eq!(codecs.decode("json.binary", &binary), Ok(vec![1, 2]));
+    assert!(codecs
+        .decode::<Vec<u32>>("json.binary", &Message::Text("[1,2]".into()))
+        .is_err());
+
+    // The echo-server binary selects the first protocol offered, while public echo servers
+    // select none, so the first codec is used either way
+    let selected = match option_env!("WASM_SOCKETS_ECHO_URL") {
+        Some(_) => "json",
+        None => "",
+    };
+    let mut client = NegotiatedPollingClient::<Vec<u32>, _>::new(ECHO_URL, codecs).unwrap();
+    assert_eq!(client.protocol(), "");
+    wait_for_status(
+        &client.client().event_client,
+        ConnectionStatus::Connected,
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(client.protocol(), selected);
+    client.send(&[3, 4]).unwrap();
+    let mut received = vec![];
+    for _ in 0..100 {
+        received.extend(client.receive());
+        if !received.is_empty() {
+            break;
+        }
+        crate::timer::sleep(10.0).await;
+    }
+    assert_eq!(received, [Ok(vec![3, 4])]);
+    client.client().close().unwrap();
+}
+
+#[cfg(feature = "json")]
+#[wasm_bindgen_test]
+fn route_messages() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{route_messages, EventClient, Json, Message};
+
+    #[derive(serde::Deserialize)]
+    enum ServerMessage {
+        Chat(String),
+        Left(u32),
+        Joined(u32),
+    }
+
+    fn on_left(_: &EventClient, id: u32) {
+        assert_eq!(id, 4);
+    }
+
+    let mut client = EventClient::new("ws://127.0.0.1:1").unwrap();
+    let log = Rc::new(RefCell::new(vec![]));
+    let (chat, other) = (log.clone(), log.clone());
+    client.set_on_message(Some(route_messages!(Json, ServerMessage {
+        Chat => move |_, text| chat.borrow_mut().push(text),
+        Left => on_left,
+        _ => move |_, message| {
+            if let ServerMessage::Joined(id) = message {
+                other.borrow_mut().push(format!("joined {}", id));
+            }
+        },
+    })));
+    let errors = log.clone();
+    client.set_on_protocol_error(Some(Box::new(move |e| {
+        errors.borrow_mut().push(e.to_string())
+    })));
+    for message in [
+        r#"{"Chat": "hi"}"#,
+        r#"{"Left": 4}"#,
+        r#"{"Joined": 5}"#,
+        r#"{"Unknown": 6}"#,
+    ] {
+        client.dispatch_message(Message::Text(message.into()));
+    }
+    let log = log.borrow();
+    assert_eq!(log[..2], ["hi", "joined 5"]);
+    assert!(log[2].starts_with("Failed to decode a message: unknown variant `Unknown`"));
+}
+
+#[cfg(feature = "tungstenite")]
+#[wasm_bindgen_test]
+fn tungstenite_conversions() {
+    use std::convert::TryFrom;
+    let text = tungstenite::Message::from(wasm_sockets::Message::Text("hi".into()));
+    assert_eq!(text, tungstenite::Message::text("hi"));
+    let binary = wasm_sockets::Message::try_from(tungstenite::Message::binary(vec![1, 2]));
+    assert!(matches!(binary, Ok(wasm_sockets::Message::Binary(data)) if data[..] == [1, 2]));
+    let ping = tungstenite::Message::Ping(vec![3].into());
+    assert_eq!(
+        wasm_sockets::Message::try_from(ping.clone()).unwrap_err(),
+        ping
+    );
+}
+
+#[wasm_bindgen_test]
+fn replay_instant() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{CloseInfo, ConnectionEvent, ConnectionStatus, RecordedEvent, Recording};
+
+    let event = |time, event| RecordedEvent { time, event };
+    let recording = Recording {
+        url: "wss://example.com".into(),
+        events: vec![
+            event(0.0, ConnectionEvent::Connected),
+            event(
+                5.0,
+                ConnectionEvent::Message(wasm_sockets::Message::Text("a".into())),
+            ),
+            event(
+                9.0,
+                ConnectionEvent::Closed(CloseInfo {
+                    code: 1000,
+                    reason: "done".into(),
+                    was_clean: true,
+                }),
+            ),
+        ],
+    };
+    let log = Rc::new(RefCell::new(vec![]));
+    let mut replay = wasm_sockets::ReplayClient::new(recording);
+    let log_ref = log.clone();
+    replay.set_on_connection(Some(Box::new(move || {
+        log_ref.borrow_mut().push("open".to_string())
+    })));
+    let log_ref = log.clone();
+    replay.set_on_message(Some(Box::new(move |m| {
+        log_ref.borrow_mut().push(format!("{:?}", m))
+    })));
+    let log_ref = log.clone();
+    replay.set_on_close(Some(Box::new(move |info| {
+        log_ref.borrow_mut().push(info.reason)
+    })));
+    replay.play_instant();
+    assert_eq!(*log.borrow(), ["open", "Text(\"a\")", "done"]);
+    assert_eq!(
+        replay.status(),
+        ConnectionStatus::Disconnected(CloseInfo {
+            code: 1000,
+            reason: "done".into(),
+            was_clean: true,
+        })
+    );
+}
+
+#[wasm_bindgen_test]
+fn simulated_lane() {
+    use crate::simulate::Lane;
+    use wasm_sockets::NetworkConditions;
+
+    let mut perfect = Lane::new(NetworkConditions::default());
+    assert_eq!(perfect.schedule(0.0), Some(0.0));
+
+    let mut lossy = Lane::new(NetworkConditions {
+        drop_probability: 1.0,
+        ..Default::default()
+    });
+    assert_eq!(lossy.schedule(0.0), None);
+
+    // Jitter without reordering keeps messages in order
+    let mut jittery = Lane::new(NetworkConditions {
+        latency_ms: 100.0,
+        jitter_ms: 50.0,
+        ..Default::default()
+    });
+    let mut last = 0.0;
+    for now in 0..50 {
+        let now = now as f64;
+        let delivery = now + jittery.schedule(now).unwrap();
+        assert!(delivery >= last && delivery >= now + 100.0);
+        last = delivery;
+    }
+}
+
+#[wasm_bindgen_test]
+async fn freed_after_close() {
+    use wasm_sockets::EventClient;
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    let listeners = std::rc::Rc::downgrade(&client.listeners);
+    client.connected().await.unwrap();
+    client.close().unwrap();
+    crate::timer::sleep(300.0).await;
+    // Only the handle keeps it alive now
+    assert!(listeners.upgrade().is_some());
+    drop(client);
+    assert!(listeners.upgrade().is_none());
+}
+
+#[wasm_bindgen_test]
+async fn dropped_while_open() {
+    use wasm_sockets::EventClient;
+    use web_sys::WebSocket;
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    let listeners = std::rc::Rc::downgrade(&client.listeners);
+    let ws = client.raw();
+    client.connected().await.unwrap();
+    // Nothing's left to handle its events, so dropping the last handle closes it
+    drop(client);
+    assert!(listeners.upgrade().is_none());
+    assert!(ws.onmessage().is_none());
+    crate::timer::sleep(300.0).await;
+    assert_eq!(ws.ready_state(), WebSocket::CLOSED);
+}
+
+#[wasm_bindgen_test]
+async fn ctx_handlers() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{testing, ConnectionStatus, Message};
+
+    let client = testing::loopback();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.add_on_connection_ctx(Box::new(|ctx| {
+        assert_eq!(ctx.status(), ConnectionStatus::Connected);
+        ctx.send_string("ping").unwrap();
+    }));
+    client.add_on_message_ctx(Box::new(move |ctx, message| {
+        received_ref.borrow_mut().push(message.clone());
+        if message == Message::Text("ping".into()) {
+            ctx.send_binary(vec![1]).unwrap();
+        } else {
+            ctx.close().unwrap();
+        }
+    }));
+    crate::timer::sleep(50.0).await;
+    assert_eq!(
+        *received.borrow(),
+        [
+            Message::Text("ping".into()),
+            Message::Binary(vec![1].into())
+        ]
+    );
+    assert!(client.status().is_terminal());
+}
+
+#[wasm_bindgen_test]
+async fn late_connection_handlers() {
+    use crate::timer::sleep;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{EventClient, PollingClient, WsClient};
+
+    let mut client = EventClient::new(ECHO_URL).unwrap();
+    client.connected().await.unwrap();
+    let log = Rc::new(RefCell::new(vec![]));
+    let log_ref = log.clone();
+    client.set_on_connection(Some(Box::new(move |_| {
+        log_ref.borrow_mut().push("replaced")
+    })));
+    let log_ref = log.clone();
+    client.set_on_connection(Some(Box::new(move |_| log_ref.borrow_mut().push("set"))));
+    let log_ref = log.clone();
+    client.add_on_connection(Box::new(move |_| log_ref.borrow_mut().push("added")));
+    let log_ref = log.clone();
+    client.once_on_connection(Box::new(move |_| log_ref.borrow_mut().push("once")));
+    let log_ref = log.clone();
+    let removed = client.add_on_connection(Box::new(move |_| log_ref.borrow_mut().push("removed")));
+    client.remove_handler(removed);
+    // They run after the current task, not inside the call
+    assert!(log.borrow().is_empty());
+    sleep(20.0).await;
+    assert_eq!(*log.borrow(), ["set", "added", "once"]);
+    client.close().unwrap();
+
+    // A PollingClient's WsClient handler too
+    let mut client = PollingClient::new(ECHO_URL).unwrap();
+    client.event_client.connected().await.unwrap();
+    log.borrow_mut().clear();
+    let log_ref = log.clone();
+    WsClient::set_on_connection(
+        &mut client,
+        Some(Box::new(move |_| log_ref.borrow_mut().push("replaced"))),
+    );
+    let log_ref = log.clone();
+    WsClient::set_on_connection(
+        &mut client,
+        Some(Box::new(move |_| log_ref.borrow_mut().push("polling"))),
+    );
+    assert!(log.borrow().is_empty());
+    sleep(20.0).await;
+    assert_eq!(*log.borrow(), ["polling"]);
+    client.close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn deferred_dispatch() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_bindgen::JsCast;
+    use wasm_sockets::{EventClient, Message};
+
+    let mut client = EventClient::builder(ECHO_URL)
+        .deferred_dispatch(true)
+        .build()
+        .unwrap();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    })));
+    // Dispatched synchronously, like the browser does
+    let init = js_sys::Object::new();
+    js_sys::Reflect::set(&init, &"data".into(), &"hi".into()).unwrap();
+    let constructor = js_sys::Reflect::get(&js_sys::global(), &"MessageEvent".into()).unwrap();
+    let event = js_sys::Reflect::construct(
+        constructor.unchecked_ref::<js_sys::Function>(),
+        &js_sys::Array::of2(&"message".into(), &init),
+    )
+    .unwrap();
+    client.raw().dispatch_event(event.unchecked_ref()).unwrap();
+    assert!(received.borrow().is_empty());
+    crate::timer::sleep(0.0).await;
+    assert_eq!(*received.borrow(), [Message::Text("hi".into())]);
+    client.close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn try_send() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{testing, Message, OverLimit, RateLimit, SendOutcome};
+
+    let mut client = testing::loopback();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    })));
+    let outcome = client.try_send(Message::Text("early".into()));
+    assert!(matches!(outcome, SendOutcome::QueuedWhileConnecting));
+    client.connected().await.unwrap();
+    let outcome = client.try_send(Message::Text("now".into()));
+    assert!(matches!(outcome, SendOutcome::SentImmediately));
+    crate::timer::sleep(20.0).await;
+    assert_eq!(
+        *received.borrow(),
+        [Message::Text("early".into()), Message::Text("now".into())]
+    );
+
+    let limit = RateLimit {
+        messages_per_sec: Some(1.0),
+        over_limit: OverLimit::Drop,
+        ..RateLimit::default()
+    };
+    client.set_rate_limit(Some(limit));
+    client.send_string("uses up the limit").unwrap();
+    let outcome = client.try_send(Message::Text("dropped".into()));
+    assert!(matches!(outcome, SendOutcome::Rejected(_)));
+    client.set_rate_limit(Some(RateLimit {
+        over_limit: OverLimit::Queue,
+        ..limit
+    }));
+    client.send_string("uses up the limit").unwrap();
+    let outcome = client.try_send(Message::Text("queued".into()));
+    assert!(matches!(outcome, SendOutcome::Queued));
+    client.close().unwrap();
+}
+
+#[cfg(feature = "json")]
+#[wasm_bindgen_test]
+async fn envelopes() {
+    use wasm_sockets::{ConnectionStatus, Envelope, Json, TypedPollingClient};
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Chat {
+        text: String,
+    }
+
+    let mut client = TypedPollingClient::<Envelope<Chat>, Json>::new(ECHO_URL).unwrap();
+    for _ in 0..100 {
+        if client.status() == ConnectionStatus::Connected {
+            break;
+        }
+        crate::timer::sleep(10.0).await;
+    }
+    client.receive();
+    let before = crate::timer::now_ms();
+    let chat = |text: &str| Chat { text: text.into() };
+    assert_eq!(client.send_enveloped("chat", chat("hi")).unwrap(), 1);
+    assert_eq!(client.send_enveloped("chat", chat("bye")).unwrap(), 2);
+    crate::timer::sleep(200.0).await;
+    let received: Vec<_> = client.receive().into_iter().map(Result::unwrap).collect();
+    assert_eq!(received.len(), 2);
+    assert_eq!((received[0].id, received[1].id), (1, 2));
+    assert_eq!(received[0].kind, "chat");
+    assert_eq!(received[1].payload, chat("bye"));
+    assert!(received[0].sent_at >= before);
+    client.client().close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn handshake() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{testing, ConnectionStatus, Handshake, Message, SendOutcome};
+
+    let version = |expected: &'static str| Handshake {
+        hello: Message::Text("v2".into()),
+        check: Box::new(move |reply| match reply {
+            Message::Text(version) if &**version == expected => Ok(()),
+            _ => Err(format!("expected {}", expected)),
+        }),
+    };
+
+    let mut client = testing::loopback();
+    client.set_handshake(Some(version("v2")));
+    let log = Rc::new(RefCell::new(vec![]));
+    let log_ref = log.clone();
+    client.set_on_connection(Some(Box::new(move |client| {
+        assert!(!client.is_handshaking());
+        log_ref.borrow_mut().push("connected".to_string());
+    })));
+    let log_ref = log.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        log_ref.borrow_mut().push(format!("{:?}", message));
+    })));
+    let outcome = client.try_send(Message::Text("queued".into()));
+    assert!(matches!(outcome, SendOutcome::QueuedWhileConnecting));
+    client.connected().await.unwrap();
+    crate::timer::sleep(20.0).await;
+    // The reply isn't passed on, and queued messages wait for it
+    assert_eq!(*log.borrow(), ["connected", r#"Text("queued")"#]);
+    client.close().unwrap();
+
+    let mut client = testing::loopback();
+    client.set_handshake(Some(version("v3")));
+    let mismatch = Rc::new(RefCell::new(None));
+    let mismatch_ref = mismatch.clone();
+    client.set_on_version_mismatch(Some(Box::new(move |reason| {
+        *mismatch_ref.borrow_mut() = Some(reason);
+    })));
+    client.set_on_connection(Some(Box::new(|_| panic!("Connected"))));
+    assert!(client.connected().await.is_err());
+    assert_eq!(mismatch.borrow().as_deref(), Some("expected v3"));
+    assert!(matches!(client.status(), ConnectionStatus::Disconnected(_)));
+}
+
+#[cfg(feature = "decompress")]
+#[wasm_bindgen_test]
+fn decompression() {
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+    use wasm_sockets::{Decompression, Message, ProtocolError};
+
+    let payload = br#"{"players": [1, 2, 3, 4, 5, 6, 7, 8]}"#.repeat(20);
+    let mut gzip = GzEncoder::new(vec![], Compression::default());
+    gzip.write_all(&payload).unwrap();
+    let gzip = gzip.finish().unwrap();
+    let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+    zlib.write_all(&payload).unwrap();
+    let zlib = zlib.finish().unwrap();
+    let mut deflate = DeflateEncoder::new(vec![0xff], Compression::default());
+    deflate.write_all(&payload).unwrap();
+    let deflate = deflate.finish().unwrap();
+
+    let mut client = wasm_sockets::EventClient::new("ws://127.0.0.1:1").unwrap();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    })));
+    let errors = Rc::new(RefCell::new(vec![]));
+    let errors_ref = errors.clone();
+    client.set_on_protocol_error(Some(Box::new(move |e| errors_ref.borrow_mut().push(e))));
+
+    // Passed through as they are until decompression is set
+    client.dispatch_message(Message::Binary(gzip.clone().into()));
+    assert_eq!(*received.borrow(), [Message::Binary(gzip.clone().into())]);
+    received.borrow_mut().clear();
+
+    client.set_decompression(Some(Decompression {
+        flag_byte: Some(0xff),
+        ..Decompression::default()
+    }));
+    client.dispatch_message(Message::Binary(gzip.clone().into()));
+    client.dispatch_message(Message::Binary(zlib.into()));
+    client.dispatch_message(Message::Binary(deflate.into()));
+    client.dispatch_message(Message::Binary(vec![1, 2, 3].into()));
+    client.dispatch_message(Message::Text("text".into()));
+    let expected = Message::Binary(payload.clone().into());
+    assert_eq!(
+        *received.borrow(),
+        [
+            expected.clone(),
+            expected.clone(),
+            expected,
+            Message::Binary(vec![1, 2, 3].into()),
+            Message::Text("text".into()),
+        ]
+    );
+    assert!(errors.borrow().is_empty());
+    received.borrow_mut().clear();
+
+    client.set_decompression(Some(Decompression {
+        max_output: payload.len() - 1,
+        ..Decompression::default()
+    }));
+    client.dispatch_message(Message::Binary(gzip.into()));
+    client.dispatch_message(Message::Binary(vec![0x1f, 0x8b, 0].into()));
+    assert!(received.borrow().is_empty());
+    assert!(matches!(
+        &errors.borrow()[..],
+        [ProtocolError::Decompress(_), ProtocolError::Decompress(_)]
+    ));
+}
+
+#[wasm_bindgen_test]
+async fn line_delimited() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{testing, Message};
+
+    let mut client = testing::loopback();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    })));
+    client.set_line_delimited(true);
+    client.connected().await.unwrap();
+    client.send_lines(&["{\"a\": 1}", "{\"b\": 2}"]).unwrap();
+    client.send_string("one\r\n\r\ntwo").unwrap();
+    client.send_binary(b"not\nsplit".to_vec()).unwrap();
+    assert!(client.send_lines(&["broken\nline"]).is_err());
+    crate::timer::sleep(20.0).await;
+    assert_eq!(
+        *received.borrow(),
+        [
+            Message::Text("{\"a\": 1}".into()),
+            Message::Text("{\"b\": 2}".into()),
+            Message::Text("one".into()),
+            Message::Text("two".into()),
+            Message::Binary(b"not\nsplit".to_vec().into()),
+        ]
+    );
+    client.close().unwrap();
+}
+
+#[cfg(feature = "base64")]
+#[wasm_bindgen_test]
+async fn base64_bridge() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{testing, Message, ProtocolError};
+
+    let mut client = testing::loopback();
+    let received = Rc::new(RefCell::new(vec![]));
+    let received_ref = received.clone();
+    client.set_on_message(Some(Box::new(move |_, message| {
+        received_ref.borrow_mut().push(message)
+    })));
+    let errors = Rc::new(RefCell::new(vec![]));
+    let errors_ref = errors.clone();
+    client.set_on_protocol_error(Some(Box::new(move |e| errors_ref.borrow_mut().push(e))));
+    client.connected().await.unwrap();
+    client.send_binary(vec![0, 1, 2, 255]).unwrap();
+    crate::timer::sleep(20.0).await;
+    assert_eq!(
+        *received.borrow(),
+        [Message::Binary(vec![0, 1, 2, 255].into())]
+    );
+    received.borrow_mut().clear();
+
+    client.set_base64_bridge(true);
+    client.send_binary(vec![0, 1, 2, 255]).unwrap();
+    // Echoed back as text, which isn't base64
+    client.send_string("not base64!").unwrap();
+    crate::timer::sleep(20.0).await;
+    assert_eq!(
+        *received.borrow(),
+        [Message::Binary(vec![0, 1, 2, 255].into())]
+    );
+    assert!(matches!(&errors.borrow()[..], [ProtocolError::Base64(_)]));
+
+    received.borrow_mut().clear();
+
+    // Turned off before the echo arrives, so it's received as it was sent
+    client.send_binary(vec![0, 1, 2, 255]).unwrap();
+    client.set_base64_bridge(false);
+    crate::timer::sleep(20.0).await;
+    assert_eq!(*received.borrow(), [Message::Text("AAEC/w==".into())]);
+    client.close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn backpressure() {
+    use crate::timer::sleep;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_sockets::{BackpressureLimits, ConnectionStatus, PollingClient};
+
+    let mut client = PollingClient::new(ECHO_URL).unwrap();
+    let reports = Rc::new(RefCell::new(vec![]));
+    let reports_ref = reports.clone();
+    client.set_on_backpressure(Some(Box::new(move |report| {
+        reports_ref.borrow_mut().push(report)
+    })));
+    wait_for_status(
+        &client.event_client,
+        ConnectionStatus::Connected,
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap();
+    sleep(50.0).await;
+    client.receive();
+    client.set_backpressure_limits(Some(BackpressureLimits {
+        max_depth: Some(2),
+        ..BackpressureLimits::default()
+    }));
+    for message in ["a", "b", "c", "d"] {
+        client.send_string(message).unwrap();
+    }
+    sleep(100.0).await;
+    // Only reported once, until the messages are received
+    assert_eq!(reports.borrow().len(), 1);
+    assert_eq!(reports.borrow()[0].depth, 3);
+    assert_eq!(client.buffer_report().depth, 4);
+    client.receive();
+    assert_eq!(client.buffer_report().depth, 0);
+
+    client.set_backpressure_limits(Some(BackpressureLimits {
+        max_age_ms: Some(50.0),
+        ..BackpressureLimits::default()
+    }));
+    client.send_string("e").unwrap();
+    sleep(150.0).await;
+    assert_eq!(reports.borrow().len(), 2);
+    assert_eq!(reports.borrow()[1].depth, 1);
+    assert!(reports.borrow()[1].oldest_age_ms >= 50.0);
+}
+
+#[wasm_bindgen_test]
+async fn memory_limit() {
+    use crate::timer::sleep;
+    use wasm_sockets::{
+        ConnectionStatus, EventClient, Message, PollingClient, RateLimit, SendOutcome,
+    };
+
+    let client = EventClient::new(ECHO_URL).unwrap();
+    wait_for_status(&client, ConnectionStatus::Connected, Duration::from_secs(5))
+        .await
+        .unwrap();
+    sleep(50.0).await;
+    client.set_memory_limit(Some(10));
+    client.pause();
+    for message in ["aaaa", "bbbb", "cccc", "dddd"] {
+        client.send_string(message).unwrap();
+    }
+    sleep(100.0).await;
+    // The oldest received messages make room for new ones
+    let stats = client.stats();
+    assert_eq!(
+        (stats.buffered_inbound_bytes, stats.discarded_inbound),
+        (8, 2)
+    );
+    client.resume();
+
+    client.set_rate_limit(Some(RateLimit {
+        messages_per_sec: Some(1.0),
+        ..RateLimit::default()
+    }));
+    for message in ["eeee", "ffff", "gggg"] {
+        client.send_string(message).unwrap();
+    }
+    assert!(matches!(
+        client.try_send(Message::Text("hhhh".into())),
+        SendOutcome::Rejected(_)
+    ));
+    let stats = client.stats();
+    assert_eq!(
+        (stats.queued_outbound_bytes, stats.dropped_memory_limit),
+        (8, 1)
+    );
+    assert_eq!(client.buffered_bytes(), 8);
+    client.set_rate_limit(None);
+
+    let mut client = PollingClient::new(ECHO_URL).unwrap();
+    wait_for_status(
+        &client.event_client,
+        ConnectionStatus::Connected,
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap();
+    sleep(50.0).await;
+    client.receive();
+    client.event_client.set_memory_limit(Some(10));
+    for message in ["aaaa", "bbbb", "cccc"] {
+        client.send_string(message).unwrap();
+    }
+    sleep(100.0).await;
+    assert_eq!(client.event_client.stats().buffered_inbound_bytes, 8);
+    assert_eq!(
+        client.receive(),
+        [Message::Text("bbbb".into()), Message::Text("cccc".into())]
+    );
+}
+
+#[wasm_bindgen_test]
+async fn message_adapters() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use wasm_sockets::{testing, Message, PollingClient};
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    let mut client = PollingClient::new("ws://127.0.0.1:1")
+        .unwrap()
+        .filter_messages(|message| matches!(message, Message::Text(_)))
+        .map_messages(|message| match message {
+            Message::Text(text) => Message::Text(text.trim().into()),
+            binary => binary,
+        })
+        .filter_map_messages(|message| match &message {
+            Message::Text(text) if text.is_empty() => None,
+            _ => Some(message),
+        });
+    for message in [
+        Message::Text(" a ".into()),
+        Message::Binary(vec![1].into()),
+        Message::Text("   ".into()),
+        Message::Text("b".into()),
+    ] {
+        client.event_client.dispatch_message(message);
+    }
+    assert_eq!(
+        client.receive(),
+        [Message::Text("a".into()), Message::Text("b".into())]
+    );
+
+    let client = testing::loopback();
+    let mut stream = client.message_stream();
+    client.connected().await.unwrap();
+    client.send_string("a").unwrap();
+    client.send_binary(vec![1]).unwrap();
+    assert_eq!(next(&mut stream).await, Some(Message::Text("a".into())));
+    assert_eq!(
+        next(&mut stream).await,
+        Some(Message::Binary(vec![1].into()))
+    );
+    client.close().unwrap();
+    assert_eq!(next(&mut stream).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn js_handlers() {
+    use js_sys::{Array, Function, Reflect, Uint8Array};
+    use wasm_bindgen::JsCast;
+    use wasm_sockets::{close_code, testing};
+
+    let log = Array::new();
+    let push = Function::new_with_args("value", "this.push(value)").bind0(&log);
+    let mut client = testing::loopback();
+    client.set_on_connection_js(Some(Function::new_no_args("this.push('open')").bind0(&log)));
+    client.set_on_message_js(Some(Function::new_no_args("throw 'replaced'")));
+    client.set_on_message_js(Some(push.clone()));
+    client.set_on_close_js(Some(push));
+    client.connected().await.unwrap();
+    client.send_string("hi").unwrap();
+    client.send_binary(vec![1, 2]).unwrap();
+    crate::timer::sleep(20.0).await;
+    client.set_on_message_js(None);
+    client.send_string("ignored").unwrap();
+    client
+        .close_with(close_code::GOING_AWAY, Some("bye"))
+        .unwrap();
+    crate::timer::sleep(20.0).await;
+
+    assert_eq!(log.length(), 4);
+    assert_eq!(log.get(0), "open");
+    assert_eq!(log.get(1), "hi");
+    let binary: Uint8Array = log.get(2).dyn_into().unwrap();
+    assert_eq!(binary.to_vec(), [1, 2]);
+    let close = log.get(3);
+    let field = |name: &str| Reflect::get(&close, &name.into()).unwrap();
+    assert_eq!(field("code"), close_code::GOING_AWAY);
+    assert_eq!(field("reason"), "bye");
+}
+
+#[cfg(feature = "js-bindings")]
+#[wasm_bindgen_test]
+async fn js_bindings() {
+    use js_sys::{Array, Function};
+    use wasm_sockets::{testing, JsEventClient, JsPollingClient};
+
+    let log = Array::new();
+    let mut client = JsEventClient::from(testing::loopback());
+    assert_eq!(client.status(), "connecting");
+    client.on_message(Some(
+        Function::new_with_args("value", "this.push(value)").bind0(&log),
+    ));
+    client.on_open(Some(Function::new_no_args("this.push('open')").bind0(&log)));
+    crate::timer::sleep(20.0).await;
+    assert!(client.is_open());
+    client.send_string("hi").unwrap();
+    client.send_binary(&[1, 2]).unwrap();
+    crate::timer::sleep(20.0).await;
+    assert_eq!(log.length(), 3);
+    assert_eq!(log.get(1), "hi");
+    client.close(Some(4000), Some("done".into())).unwrap();
+    crate::timer::sleep(20.0).await;
+    assert_eq!(client.status(), "disconnected");
+
+    let mut polling = JsPollingClient::new("ws://127.0.0.1:1").unwrap();
+    assert_eq!(polling.url(), "ws://127.0.0.1:1");
+    assert_eq!(polling.receive().length(), 0);
+    assert!(polling.send_string("too early").is_err());
+    assert!(JsPollingClient::new("not a url").is_err());
 }
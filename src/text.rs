@@ -0,0 +1,152 @@
+//! Converting received text messages, which browsers hand over as JS strings.
+//!
+//! JS strings can hold unpaired surrogates that UTF-8 can't represent. By default these are
+//! replaced with U+FFFD, which can silently mangle messages from legacy servers, so
+//! [`TextPolicy`] lets applications choose what happens instead.
+//! ```
+//! client.set_text_policy(TextPolicy::Strict);
+//! client.set_on_protocol_error(Some(Box::new(|e| {
+//!     warn!("{}", e);
+//! })));
+//! ```
+//! Very large messages, like multi-megabyte JSON documents, can be converted a piece at a time
+//! with [`EventClient::set_on_text_chunk`], and fed to a streaming parser.
+use crate::EventClient;
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+use crate::{Message, ProtocolError};
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+use std::rc::Rc;
+
+/// The size of the chunks large text messages are converted in, in UTF-16 code units,
+/// when a text chunk handler is set
+pub const TEXT_CHUNK_SIZE: u32 = 1 << 20;
+
+/// A piece of a large text message, passed to the handler set with [`EventClient::set_on_text_chunk`]
+#[derive(Debug, Clone)]
+pub struct TextChunk {
+    /// The contents of this chunk
+    pub text: String,
+    /// Where this chunk starts in the message, in UTF-16 code units
+    pub offset: u32,
+    /// The length of the whole message, in UTF-16 code units
+    pub total: u32,
+}
+
+impl TextChunk {
+    /// Whether this is the last chunk of the message
+    pub fn is_last(&self) -> bool {
+        self.offset + self.text.encode_utf16().count() as u32 >= self.total
+    }
+}
+
+/// A handler bound to the chunks of large text messages
+pub type TextChunkHandler = Box<dyn Fn(&EventClient, TextChunk)>;
+
+/// What to do with a text message that isn't valid Unicode, see [`EventClient::set_text_policy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextPolicy {
+    /// Skip the message, and pass it to the on_protocol_error handler as [`ProtocolError::InvalidText`](crate::ProtocolError::InvalidText)
+    Strict,
+    /// Replace the invalid characters with U+FFFD
+    #[default]
+    Lossy,
+    /// Deliver the message as [`Message::Binary`](crate::Message::Binary), holding its WTF-8 encoding,
+    /// which is UTF-8 extended to encode unpaired surrogates. Valid messages are still delivered as text.
+    Raw,
+}
+
+/// Encode UTF-16 code units as WTF-8, keeping unpaired surrogates instead of replacing them
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub(crate) fn wtf8(units: impl IntoIterator<Item = u16>) -> Vec<u8> {
+    let mut bytes = vec![];
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Err(e) => {
+                let unit = e.unpaired_surrogate();
+                bytes.extend_from_slice(&[
+                    0xE0 | (unit >> 12) as u8,
+                    0x80 | ((unit >> 6) & 0x3F) as u8,
+                    0x80 | (unit & 0x3F) as u8,
+                ]);
+            }
+        }
+    }
+    bytes
+}
+
+impl EventClient {
+    /// Set what happens to received text messages that aren't valid Unicode, which can be changed at any time.
+    ///
+    /// [`TextPolicy::Lossy`] is the default. With the `native` feature, invalid text
+    /// fails the connection before it reaches the client, so this has no effect.
+    /// ```
+    /// client.set_text_policy(TextPolicy::Raw);
+    /// ```
+    pub fn set_text_policy(&self, policy: TextPolicy) {
+        self.listeners.borrow_mut().text_policy = policy;
+    }
+    /// Set a handler that receives text messages longer than [`TEXT_CHUNK_SIZE`] in chunks, overwriting the previous handler.
+    ///
+    /// While this is set, those messages are passed to it instead of the on_message handlers, and
+    /// are converted from JS one chunk at a time, so the whole message never has to be in wasm
+    /// memory at once. Chunks never split a surrogate pair. Shorter messages are still passed to on_message.
+    /// You can set [None](std::option) to go back to receiving [`Message::Text`].
+    /// ```
+    /// client.set_on_text_chunk(Some(Box::new(|_client, chunk| {
+    ///     parser.feed(&chunk.text);
+    ///     if chunk.is_last() {
+    ///         handle(parser.finish());
+    ///     }
+    /// })));
+    /// ```
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub fn set_on_text_chunk(&mut self, f: Option<TextChunkHandler>) {
+        self.listeners.borrow_mut().on_text_chunk = f.map(Rc::new);
+    }
+    /// Convert a received text message following the text policy, then run the message handlers
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    pub(crate) fn dispatch_text(&self, text: js_sys::JsString) {
+        let (policy, on_text_chunk) = {
+            let listeners = self.listeners.borrow();
+            (listeners.text_policy, listeners.on_text_chunk.clone())
+        };
+        if text.is_valid_utf16() || policy == TextPolicy::Lossy {
+            match on_text_chunk {
+                Some(f) if text.length() > TEXT_CHUNK_SIZE => self.dispatch_text_chunks(&text, &f),
+                _ => self.dispatch_message(Message::Text(String::from(text).into())),
+            }
+            return;
+        }
+        trace!(client: self, "message event, received invalid text");
+        match policy {
+            TextPolicy::Strict => {
+                self.dispatch_protocol_error(ProtocolError::InvalidText(String::from(text)))
+            }
+            _ => self.dispatch_message(Message::Binary(wtf8(text.iter()).into())),
+        }
+    }
+    /// Convert a large text message a chunk at a time, passing each to `f`
+    #[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+    fn dispatch_text_chunks(&self, text: &js_sys::JsString, f: &TextChunkHandler) {
+        let total = text.length();
+        let mut offset = 0;
+        while offset < total {
+            let mut end = (offset + TEXT_CHUNK_SIZE).min(total);
+            // Keep surrogate pairs together, so they aren't replaced with U+FFFD
+            let last = text.char_code_at(end - 1) as u16;
+            if end < total && (0xD800..0xDC00).contains(&last) {
+                end -= 1;
+            }
+            f(
+                self,
+                TextChunk {
+                    text: String::from(text.slice(offset, end)),
+                    offset,
+                    total,
+                },
+            );
+            offset = end;
+        }
+    }
+}
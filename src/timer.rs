@@ -0,0 +1,107 @@
+//! Platform timers shared by the utilities built on top of the clients.
+//!
+//! In the browser (and in web workers) these use the global `setTimeout`, `Date.now()`, and `performance.now()`.
+//! With the `native` feature they run on tokio, inside the same `LocalSet` the clients need.
+use std::cell::RefCell;
+use std::future::{poll_fn, Future};
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+/// The current time in milliseconds
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub(crate) fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+pub(crate) fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
+
+/// Milliseconds on a clock that never jumps, unlike [`now_ms`], for measuring how long something took
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub(crate) fn monotonic_ms() -> f64 {
+    js::performance_now()
+}
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+pub(crate) fn monotonic_ms() -> f64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_secs_f64()
+        * 1000.0
+}
+
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+mod js {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        // Node returns a Timeout object instead of a number
+        #[wasm_bindgen(js_name = setTimeout)]
+        pub fn set_timeout(closure: &JsValue, time: i32) -> JsValue;
+        #[wasm_bindgen(js_name = requestAnimationFrame)]
+        pub fn request_animation_frame(closure: &JsValue) -> JsValue;
+        #[wasm_bindgen(js_namespace = performance, js_name = now)]
+        pub fn performance_now() -> f64;
+    }
+}
+
+/// Run `f` once after `delay_ms` milliseconds
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub(crate) fn set_timeout(delay_ms: f64, f: impl FnOnce() + 'static) {
+    // once_into_js frees the closure after it has been called
+    let callback = wasm_bindgen::closure::Closure::once_into_js(f);
+    js::set_timeout(&callback, delay_ms.max(0.0).round() as i32);
+}
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+pub(crate) fn set_timeout(delay_ms: f64, f: impl FnOnce() + 'static) {
+    let delay = std::time::Duration::from_secs_f64(delay_ms.max(0.0) / 1000.0);
+    tokio::task::spawn_local(async move {
+        tokio::time::sleep(delay).await;
+        f();
+    });
+}
+
+/// Run `f` once before the next frame is painted, or after 16 milliseconds where there are no frames
+#[cfg(not(all(feature = "native", not(target_arch = "wasm32"))))]
+pub(crate) fn request_animation_frame(f: impl FnOnce() + 'static) {
+    // Node and some workers don't have requestAnimationFrame
+    if !js_sys::Reflect::has(&js_sys::global(), &"requestAnimationFrame".into()).unwrap_or(false) {
+        return set_timeout(16.0, f);
+    }
+    let callback =
+        wasm_bindgen::closure::Closure::once_into_js(move |_: wasm_bindgen::JsValue| f());
+    js::request_animation_frame(&callback);
+}
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+pub(crate) fn request_animation_frame(f: impl FnOnce() + 'static) {
+    set_timeout(16.0, f);
+}
+
+/// A future that resolves after `delay_ms` milliseconds
+pub(crate) fn sleep(delay_ms: f64) -> impl Future<Output = ()> {
+    // Whether the timer has fired, and the task waiting for it
+    let state: Rc<RefCell<(bool, Option<Waker>)>> = Rc::new(RefCell::new((false, None)));
+    let state_ref = state.clone();
+    set_timeout(delay_ms, move || {
+        let mut state = state_ref.borrow_mut();
+        state.0 = true;
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+    });
+    poll_fn(move |cx| {
+        let mut state = state.borrow_mut();
+        if state.0 {
+            Poll::Ready(())
+        } else {
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    })
+}
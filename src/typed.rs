@@ -0,0 +1,269 @@
+//! Receiving deserialized values instead of raw messages.
+//!
+//! A [`TypedPollingClient`] decodes every message it receives with a [`Codec`], so a game loop
+//! gets its own protocol types each frame. With the `json` feature, [`Json`] decodes text and
+//! binary messages as JSON. Values can be sent with the same codec, and wrapped in an
+//! [`Envelope`](crate::Envelope) carrying an id, timestamp and type tag. To pick the codec by the
+//! subprotocol the server selected, use a [`NegotiatedPollingClient`](crate::NegotiatedPollingClient).
+//!
+//! For an [`EventClient`], [`route_messages!`](crate::route_messages) builds an on_message handler
+//! that decodes each message into an enum and calls the handler for its variant.
+//! ```
+//! #[derive(Deserialize)]
+//! enum ServerMessage {
+//!     Moved { id: u32, x: f32, y: f32 },
+//!     Left { id: u32 },
+//! }
+//!
+//! let mut client = TypedPollingClient::<ServerMessage, Json>::new("wss://game.example.com")?;
+//! // Once per frame
+//! for message in client.receive() {
+//!     match message {
+//!         Ok(ServerMessage::Moved { id, x, y }) => world.move_player(id, x, y),
+//!         Ok(ServerMessage::Left { id }) => world.remove_player(id),
+//!         Err(e) => warn!("{}", e),
+//!     }
+//! }
+//! ```
+use crate::{
+    ConnectionStatus, Envelope, EventClient, Message, MessageHandler, PollingClient, ProtocolError,
+    WebSocketError,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A message that couldn't be decoded, see [`Codec::decode`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    /// What the codec said was wrong
+    pub reason: String,
+}
+
+impl DecodeError {
+    /// Create an error from anything that describes the problem
+    pub fn new(reason: impl fmt::Display) -> Self {
+        Self {
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to decode message: {}", self.reason)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// A value that couldn't be encoded, see [`Codec::encode`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeError {
+    /// What the codec said was wrong
+    pub reason: String,
+}
+
+impl EncodeError {
+    /// Create an error from anything that describes the problem
+    pub fn new(reason: impl fmt::Display) -> Self {
+        Self {
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to encode message: {}", self.reason)
+    }
+}
+
+impl Error for EncodeError {}
+
+impl From<EncodeError> for WebSocketError {
+    fn from(e: EncodeError) -> Self {
+        WebSocketError::SendError(e.to_string())
+    }
+}
+
+/// A format for turning received messages into values, and values into messages to send
+pub trait Codec {
+    /// Decode a received message
+    fn decode<T: DeserializeOwned>(message: &Message) -> Result<T, DecodeError>;
+    /// Encode a value as a message
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Message, EncodeError>;
+}
+
+/// JSON, in text or binary messages
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn decode<T: DeserializeOwned>(message: &Message) -> Result<T, DecodeError> {
+        let data = match message {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(data) => &data[..],
+        };
+        serde_json::from_slice(data).map_err(DecodeError::new)
+    }
+    /// Encodes values as text messages
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Message, EncodeError> {
+        serde_json::to_string(value)
+            .map(|text| Message::Text(text.into()))
+            .map_err(EncodeError::new)
+    }
+}
+
+/// A [`PollingClient`] that decodes the messages it receives into `T` with the codec `C`
+pub struct TypedPollingClient<T, C> {
+    client: PollingClient,
+    /// The id for the next [`Envelope`] sent
+    next_id: Cell<u64>,
+    types: PhantomData<fn() -> (T, C)>,
+}
+
+impl<T: DeserializeOwned, C: Codec> TypedPollingClient<T, C> {
+    /// Create a new TypedPollingClient and connect to a WebSocket URL
+    ///
+    /// Note: An Ok() from this function does not mean the connection has succeeded.
+    /// ```
+    /// TypedPollingClient::<ServerMessage, Json>::new("wss://ws.ifelse.io")?;
+    /// ```
+    pub fn new(url: &str) -> Result<Self, WebSocketError> {
+        Ok(Self::from_client(PollingClient::new(url)?))
+    }
+    /// Decode the messages received by an existing client
+    pub fn from_client(client: PollingClient) -> Self {
+        Self {
+            client,
+            next_id: Cell::new(1),
+            types: PhantomData,
+        }
+    }
+    /// Receive the decoded messages, in order, along with an error for each one that couldn't be decoded
+    /// ```
+    /// for message in client.receive().into_iter().flatten() {
+    ///     world.apply(message);
+    /// }
+    /// ```
+    pub fn receive(&mut self) -> Vec<Result<T, DecodeError>> {
+        self.client.receive().iter().map(C::decode).collect()
+    }
+    /// Encode a value with the codec and send it
+    /// ```
+    /// client.send(&ClientMessage::Ready)?;
+    /// ```
+    pub fn send<U: Serialize + ?Sized>(&self, value: &U) -> Result<(), WebSocketError> {
+        match C::encode(value)? {
+            Message::Text(text) => self.client.send_string(&text),
+            Message::Binary(data) => self.client.send_binary(data.to_vec()),
+        }
+    }
+    /// Wrap a value in an [`Envelope`] with the next id and the current time, then encode and
+    /// send it, returning the id
+    /// ```
+    /// let id = client.send_enveloped("move", &Move { x: 1.0, y: 0.0 })?;
+    /// pending.insert(id);
+    /// ```
+    pub fn send_enveloped<U: Serialize>(
+        &self,
+        kind: &str,
+        payload: U,
+    ) -> Result<u64, WebSocketError> {
+        let id = self.next_id.get();
+        self.send(&Envelope::new(id, kind, payload))?;
+        self.next_id.set(id + 1);
+        Ok(id)
+    }
+    /// The client underneath, for everything else
+    pub fn client(&self) -> &PollingClient {
+        &self.client
+    }
+    /// Get the client's current connection status
+    pub fn status(&self) -> ConnectionStatus {
+        self.client.status()
+    }
+    /// Unwrap the client, to receive raw messages again
+    pub fn into_inner(self) -> PollingClient {
+        self.client
+    }
+}
+
+/// Wrap a handler for values of `T` into an on_message handler that decodes each message with `C` first.
+///
+/// Messages that can't be decoded are passed to the on_protocol_error handler as [`ProtocolError::Codec`].
+/// ```
+/// client.add_on_message(decoding_handler::<ChatMessage, Json>(|_client, chat| {
+///     log.push(chat);
+/// }));
+/// ```
+pub fn decoding_handler<T: DeserializeOwned, C: Codec>(
+    f: impl Fn(&EventClient, T) + 'static,
+) -> MessageHandler {
+    Box::new(move |client, message| match C::decode(&message) {
+        Ok(value) => f(client, value),
+        Err(e) => client.dispatch_protocol_error(ProtocolError::Codec(e.reason)),
+    })
+}
+
+/// Build an on_message handler that decodes each message into an enum with a [`Codec`], and
+/// calls the handler for its variant with the variant's value.
+///
+/// Every variant has to hold one value, and has to be listed unless a `_` handler at the end takes
+/// the rest, so a new variant can't be missed by accident. Handlers are functions or closures taking
+/// the client and the value. Messages that can't be decoded are passed to the on_protocol_error
+/// handler, see [`decoding_handler`].
+/// ```
+/// #[derive(Deserialize)]
+/// enum ServerMessage {
+///     Moved(Moved),
+///     Chat(String),
+///     Left(u32),
+/// }
+///
+/// fn on_moved(client: &EventClient, moved: Moved) {
+///     world.move_player(moved.id, moved.position);
+/// }
+///
+/// client.set_on_message(Some(route_messages!(Json, ServerMessage {
+///     Moved => on_moved,
+///     Chat => |_client, text| chat.push(text),
+///     _ => |_client, other| warn!("Unhandled: {:?}", other),
+/// })));
+/// ```
+#[macro_export]
+macro_rules! route_messages {
+    ($codec:ty, $enum:ident { $($variant:ident => $handler:expr),+ , _ => $fallback:expr $(,)? }) => {{
+        $(
+            #[allow(non_snake_case)]
+            let $variant = $crate::__variant_handler($handler);
+        )+
+        let fallback = $crate::__variant_handler($fallback);
+        $crate::decoding_handler::<$enum, $codec>(move |client, message| match message {
+            $($enum::$variant(value) => $variant(client, value),)+
+            #[allow(unreachable_patterns)]
+            other => fallback(client, other),
+        })
+    }};
+    ($codec:ty, $enum:ident { $($variant:ident => $handler:expr),+ $(,)? }) => {{
+        $(
+            #[allow(non_snake_case)]
+            let $variant = $crate::__variant_handler($handler);
+        )+
+        $crate::decoding_handler::<$enum, $codec>(move |client, message| match message {
+            $($enum::$variant(value) => $variant(client, value),)+
+        })
+    }};
+}
+
+/// Gives a handler passed to [`route_messages!`] its signature, so closures don't need their types written out
+#[doc(hidden)]
+pub fn __variant_handler<T>(f: impl Fn(&EventClient, T)) -> impl Fn(&EventClient, T) {
+    f
+}
@@ -0,0 +1,157 @@
+//! Detecting half-open connections, where the socket still says it's connected but nothing arrives.
+//!
+//! Mobile browsers often leave sockets like this after switching networks or waking up. With a
+//! watchdog set, a connection that hasn't received a message within the timeout is stale: the
+//! on_stale handlers run, and the connection is closed. A [`SocketManager`](crate::SocketManager)
+//! with a reconnect policy opens a new one right away. If the server can be quiet for longer than
+//! the timeout, have it send heartbeats.
+//!
+//! For other liveness checks, [`EventClient::idle_for`] says how long the connection has been quiet.
+//! ```
+//! client.set_watchdog(Some(15_000.0));
+//! client.add_on_stale(Box::new(|_client| {
+//!     warn!("The connection went quiet");
+//! }));
+//! ```
+use crate::timer::{monotonic_ms, set_timeout};
+use crate::{snapshot, Added, EventClient, HandlerId};
+use std::rc::Rc;
+
+/// A handler called when the watchdog finds a stale connection, just before it's closed
+pub type StaleHandler = Box<dyn Fn(&EventClient)>;
+
+/// The watchdog settings shared by every handle to a client
+#[derive(Default)]
+pub(crate) struct Watchdog {
+    timeout_ms: Option<f64>,
+    /// Changed whenever the timeout is, so the checks scheduled for the old one stop
+    generation: u64,
+    on_stale: Added<StaleHandler>,
+    /// When the last message arrived, from [`monotonic_ms`]
+    last_received: Option<f64>,
+    /// When the connection opened, from [`monotonic_ms`]
+    opened_at: Option<f64>,
+}
+
+impl Watchdog {
+    pub(crate) fn remove_handler(&mut self, id: HandlerId) -> bool {
+        crate::remove(&mut self.on_stale, id)
+    }
+    /// When the connection opened, from [`monotonic_ms`]
+    pub(crate) fn opened_at(&self) -> Option<f64> {
+        self.opened_at
+    }
+}
+
+impl EventClient {
+    /// Note that a message arrived, before it's dispatched
+    pub(crate) fn mark_received(&self) {
+        self.listeners.borrow_mut().watchdog.last_received = Some(monotonic_ms());
+    }
+    /// Note that the connection opened, before the on_connection handlers run
+    pub(crate) fn mark_opened(&self) {
+        self.listeners.borrow_mut().watchdog.opened_at = Some(monotonic_ms());
+    }
+    /// When the last message arrived, in milliseconds on the `performance.now()` clock,
+    /// or None if none has
+    /// ```
+    /// if let Some(at) = client.last_message_at() {
+    ///     info!("Last message {}ms after the page loaded", at);
+    /// }
+    /// ```
+    pub fn last_message_at(&self) -> Option<f64> {
+        self.listeners.borrow().watchdog.last_received
+    }
+    /// How long it's been since the last message arrived, or since the connection opened if none
+    /// has, in milliseconds. This is 0 while connecting.
+    /// ```
+    /// if client.idle_for() > 5000.0 {
+    ///     show_unstable_banner();
+    /// }
+    /// ```
+    pub fn idle_for(&self) -> f64 {
+        let watchdog = &self.listeners.borrow().watchdog;
+        match watchdog.last_received.or(watchdog.opened_at) {
+            Some(since) => monotonic_ms() - since,
+            None => 0.0,
+        }
+    }
+    /// Close the connection once it goes `timeout_ms` milliseconds without receiving a message,
+    /// or set None to stop watching it.
+    ///
+    /// The time counts from the last message, or from when the connection opened.
+    /// ```
+    /// client.set_watchdog(Some(30_000.0));
+    /// ```
+    pub fn set_watchdog(&self, timeout_ms: Option<f64>) {
+        let generation = {
+            let mut listeners = self.listeners.borrow_mut();
+            listeners.watchdog.timeout_ms = timeout_ms;
+            listeners.watchdog.generation += 1;
+            listeners.watchdog.generation
+        };
+        if timeout_ms.is_some() {
+            self.watch(generation, monotonic_ms());
+        }
+    }
+    /// Add a handler that runs when the watchdog finds the connection stale, just before closing it
+    /// ```
+    /// let id = client.add_on_stale(Box::new(|_client| {
+    ///     show_reconnecting_banner();
+    /// }));
+    /// ```
+    pub fn add_on_stale(&self, f: StaleHandler) -> HandlerId {
+        let mut listeners = self.listeners.borrow_mut();
+        let id = listeners.next_id();
+        listeners.watchdog.on_stale.push((id, Rc::new(f)));
+        id
+    }
+    /// Check whether the connection is stale, and keep checking until the watchdog changes or the connection ends.
+    /// `since` is when the connection was last known to be alive.
+    fn watch(&self, generation: u64, since: f64) {
+        let timeout_ms = {
+            let listeners = self.listeners.borrow();
+            match listeners.watchdog.timeout_ms {
+                Some(timeout_ms) if listeners.watchdog.generation == generation => timeout_ms,
+                _ => return,
+            }
+        };
+        let status = self.status();
+        if status.is_terminal() {
+            return;
+        }
+        let now = monotonic_ms();
+        let since = if status.is_connected() {
+            since.max(
+                self.listeners
+                    .borrow()
+                    .watchdog
+                    .last_received
+                    .unwrap_or(0.0),
+            )
+        } else {
+            // Still connecting, so the timeout starts once it opens
+            now
+        };
+        if now - since >= timeout_ms {
+            self.stale();
+            return;
+        }
+        let client = self.clone();
+        set_timeout((timeout_ms / 4.0).max(10.0), move || {
+            client.watch(generation, since)
+        });
+    }
+    fn stale(&self) {
+        trace!(
+            client: self,
+            "No messages within the watchdog's timeout, closing the connection");
+        let handlers = snapshot(&self.listeners.borrow().watchdog.on_stale);
+        for f in handlers {
+            f.as_ref()(self);
+        }
+        if let Err(e) = self.close() {
+            error!(client: self, "{}", e);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Yew integration, enabled with the `yew` feature.
+//!
+//! [`use_websocket`] opens a connection owned by a function component, and re-renders the
+//! component whenever the connection status changes or a message arrives.
+//! ```
+//! #[function_component]
+//! fn Chat() -> Html {
+//!     let socket = use_websocket("wss://ws.ifelse.io");
+//!     let onclick = {
+//!         let send = socket.send.clone();
+//!         Callback::from(move |_| {
+//!             send.emit(Message::Text("Hello, World!".into())).ok();
+//!         })
+//!     };
+//!     html! {
+//!         <>
+//!             <p>{ format!("{:?}", socket.status) }</p>
+//!             <p>{ format!("{:?}", socket.message) }</p>
+//!             <button {onclick}>{ "Send" }</button>
+//!         </>
+//!     }
+//! }
+//! ```
+//!
+//! To keep the socket off the main thread, register [`WebSocketWorker`] in a separate worker
+//! binary, wrap the app in a `WorkerProvider<WebSocketWorker>` pointing to it, and use
+//! [`use_websocket_worker`] instead. Every component using the worker shares its connection.
+//! ```
+//! // In the main function of src/bin/worker.rs
+//! WebSocketWorker::registrar().register();
+//! ```
+use crate::{CloseInfo, ConnectionStatus, EventClient, Message, WebSocketError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use yew::prelude::*;
+use yew_agent::worker::{use_worker_bridge, HandlerId, Worker, WorkerScope};
+
+/// The state of a websocket connection used by a component
+#[derive(Clone)]
+pub struct UseWebSocketHandle {
+    /// The current connection status
+    pub status: ConnectionStatus,
+    /// The most recent message received from the server
+    pub message: Option<Message>,
+    /// Send a message to the server
+    pub send: Callback<Message, Result<(), WebSocketError>>,
+}
+
+/// Connect to a WebSocket URL for as long as the component is mounted.
+///
+/// The connection is closed when the component unmounts, and reopened if `url` changes.
+#[hook]
+pub fn use_websocket(url: &str) -> UseWebSocketHandle {
+    let status = use_state(|| ConnectionStatus::Connecting);
+    let message = use_state(|| None);
+    let client = use_mut_ref(|| None::<EventClient>);
+
+    {
+        let status = status.clone();
+        let message = message.clone();
+        let client = client.clone();
+        use_effect_with(url.to_string(), move |url| {
+            status.set(ConnectionStatus::Connecting);
+            match EventClient::new(url) {
+                Ok(mut new_client) => {
+                    let status_ref = status.clone();
+                    new_client.set_on_connection(Some(Box::new(move |_| {
+                        status_ref.set(ConnectionStatus::Connected);
+                    })));
+                    let status_ref = status.clone();
+                    new_client.set_on_error(Some(Box::new(move |_| {
+                        status_ref.set(ConnectionStatus::Error(CloseInfo::abnormal()));
+                    })));
+                    new_client.set_on_close(Some(Box::new(move |e| {
+                        status.set(ConnectionStatus::closed(CloseInfo::from(&e)));
+                    })));
+                    new_client.set_on_message(Some(Box::new(move |_, m| {
+                        message.set(Some(m));
+                    })));
+                    *client.borrow_mut() = Some(new_client);
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    status.set(ConnectionStatus::Error(CloseInfo::abnormal()));
+                }
+            }
+            move || {
+                if let Some(client) = client.borrow_mut().take() {
+                    close_quietly(client);
+                }
+            }
+        });
+    }
+
+    let send = {
+        let client = client.clone();
+        Callback::from(move |m: Message| match client.borrow().as_ref() {
+            Some(client) => match m {
+                Message::Text(text) => client.send_string(&text),
+                Message::Binary(data) => client.send_binary(data.into()),
+            },
+            None => Err(WebSocketError::SendError("Not connected".into())),
+        })
+    };
+
+    UseWebSocketHandle {
+        status: (*status).clone(),
+        message: (*message).clone(),
+        send,
+    }
+}
+
+/// Close a client without running its handlers, so it can't report a status after being replaced
+fn close_quietly(mut client: EventClient) {
+    client.set_on_error(None);
+    client.set_on_connection(None);
+    client.set_on_message(None);
+    client.set_on_close(None);
+    client.close().ok();
+}
+
+/// Requests components send to a [`WebSocketWorker`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WorkerInput {
+    /// Connect to a WebSocket URL, unless the worker is already connected to it
+    Connect(String),
+    /// Send a message to the server
+    Send(Message),
+    /// Close the connection
+    Close,
+}
+
+/// Updates a [`WebSocketWorker`] sends to every connected component
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WorkerOutput {
+    /// The connection status changed
+    Status(ConnectionStatus),
+    /// A message was received from the server
+    Message(Message),
+}
+
+/// A web worker that owns a websocket connection shared by every component bridged to it
+pub struct WebSocketWorker {
+    client: Option<EventClient>,
+    subscribers: HashSet<HandlerId>,
+}
+
+impl WebSocketWorker {
+    fn broadcast(&self, scope: &WorkerScope<Self>, output: WorkerOutput) {
+        for id in &self.subscribers {
+            scope.respond(*id, output.clone());
+        }
+    }
+    fn connect(&mut self, scope: &WorkerScope<Self>, url: &str) {
+        if let Some(client) = self.client.take() {
+            close_quietly(client);
+        }
+        self.update(scope, WorkerOutput::Status(ConnectionStatus::Connecting));
+        let mut client = match EventClient::new(url) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("{}", e);
+                return self.update(
+                    scope,
+                    WorkerOutput::Status(ConnectionStatus::Error(CloseInfo::abnormal())),
+                );
+            }
+        };
+        let scope_ref = scope.clone();
+        client.set_on_connection(Some(Box::new(move |_| {
+            scope_ref.send_message(WorkerOutput::Status(ConnectionStatus::Connected));
+        })));
+        let scope_ref = scope.clone();
+        client.set_on_error(Some(Box::new(move |_| {
+            scope_ref.send_message(WorkerOutput::Status(ConnectionStatus::Error(
+                CloseInfo::abnormal(),
+            )));
+        })));
+        let scope_ref = scope.clone();
+        client.set_on_close(Some(Box::new(move |e| {
+            scope_ref.send_message(WorkerOutput::Status(ConnectionStatus::closed(
+                CloseInfo::from(&e),
+            )));
+        })));
+        let scope_ref = scope.clone();
+        client.set_on_message(Some(Box::new(move |_, m| {
+            scope_ref.send_message(WorkerOutput::Message(m));
+        })));
+        self.client = Some(client);
+    }
+}
+
+impl Worker for WebSocketWorker {
+    type Message = WorkerOutput;
+    type Input = WorkerInput;
+    type Output = WorkerOutput;
+
+    fn create(_scope: &WorkerScope<Self>) -> Self {
+        Self {
+            client: None,
+            subscribers: HashSet::new(),
+        }
+    }
+    fn update(&mut self, scope: &WorkerScope<Self>, msg: Self::Message) {
+        self.broadcast(scope, msg);
+    }
+    fn connected(&mut self, _scope: &WorkerScope<Self>, id: HandlerId) {
+        self.subscribers.insert(id);
+    }
+    fn received(&mut self, scope: &WorkerScope<Self>, msg: Self::Input, id: HandlerId) {
+        match msg {
+            WorkerInput::Connect(url) => match &self.client {
+                Some(client) if *client.url.borrow() == url => {
+                    scope.respond(id, WorkerOutput::Status(client.status()));
+                }
+                _ => self.connect(scope, &url),
+            },
+            WorkerInput::Send(message) => {
+                let result = match &self.client {
+                    Some(client) => match message {
+                        Message::Text(text) => client.send_string(&text),
+                        Message::Binary(data) => client.send_binary(data.into()),
+                    },
+                    None => Err(WebSocketError::SendError("Not connected".into())),
+                };
+                if let Err(e) = result {
+                    error!("{}", e);
+                }
+            }
+            WorkerInput::Close => {
+                if let Some(client) = &self.client {
+                    client.close().ok();
+                }
+            }
+        }
+    }
+    fn disconnected(&mut self, _scope: &WorkerScope<Self>, id: HandlerId) {
+        self.subscribers.remove(&id);
+    }
+}
+
+/// Like [`use_websocket`], but through a shared [`WebSocketWorker`].
+///
+/// The component must be inside a `WorkerProvider<WebSocketWorker>`. The connection stays open
+/// after the component unmounts, since other components may be using it. Errors from sends are
+/// logged by the worker, so `send` only fails if the message couldn't reach the worker.
+#[hook]
+pub fn use_websocket_worker(url: &str) -> UseWebSocketHandle {
+    let status = use_state(|| ConnectionStatus::Connecting);
+    let message = use_state(|| None);
+    let bridge = {
+        let status = status.clone();
+        let message = message.clone();
+        use_worker_bridge::<WebSocketWorker, _>(move |output| match output {
+            WorkerOutput::Status(s) => status.set(s),
+            WorkerOutput::Message(m) => message.set(Some(m)),
+        })
+    };
+
+    {
+        let bridge = bridge.clone();
+        use_effect_with(url.to_string(), move |url| {
+            bridge.send(WorkerInput::Connect(url.clone()));
+        });
+    }
+
+    let send = Callback::from(move |m| {
+        bridge.send(WorkerInput::Send(m));
+        Ok(())
+    });
+
+    UseWebSocketHandle {
+        status: (*status).clone(),
+        message: (*message).clone(),
+        send,
+    }
+}